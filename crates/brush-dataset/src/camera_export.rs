@@ -0,0 +1,333 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use brush_render::camera::CameraModel;
+use brush_train::scene::SceneView;
+
+#[derive(serde::Serialize)]
+struct SyntheticScene {
+    camera_angle_x: f32,
+    frames: Vec<FrameData>,
+}
+
+#[derive(serde::Serialize)]
+struct FrameData {
+    transform_matrix: Vec<Vec<f32>>,
+    file_path: String,
+    #[serde(flatten)]
+    intrinsics: Option<FrameIntrinsics>,
+}
+
+// Per frame intrinsics in pixels, as written by nerfstudio.
+#[derive(serde::Serialize)]
+struct FrameIntrinsics {
+    fl_x: f32,
+    fl_y: f32,
+    cx: f32,
+    cy: f32,
+    w: u32,
+    h: u32,
+}
+
+fn image_file_name(view: &SceneView) -> String {
+    Path::new(&view.name)
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_else(|| view.name.clone())
+}
+
+/// Writes the cameras of the views as a COLMAP `cameras.txt`, with one camera per view.
+///
+/// The camera ids match the ones written by [`views_to_colmap_images`].
+pub fn views_to_colmap_cameras(views: &[SceneView]) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "# Camera list with one line of data per camera:")?;
+    writeln!(out, "#   CAMERA_ID, MODEL, WIDTH, HEIGHT, PARAMS[]")?;
+    writeln!(out, "# Number of cameras: {}", views.len())?;
+
+    for (i, view) in views.iter().enumerate() {
+        let img_size = glam::uvec2(view.image.width(), view.image.height());
+        let focal = view.camera.focal(img_size);
+        let center = view.camera.center(img_size);
+
+        let (model, params) = match view.camera.model {
+            CameraModel::Fisheye { distortion } => (
+                "OPENCV_FISHEYE",
+                vec![
+                    focal.x,
+                    focal.y,
+                    center.x,
+                    center.y,
+                    distortion.x,
+                    distortion.y,
+                    distortion.z,
+                    distortion.w,
+                ],
+            ),
+            CameraModel::Orthographic { .. } => {
+                anyhow::bail!("COLMAP doesn't support orthographic cameras.")
+            }
+            CameraModel::Pinhole => ("PINHOLE", vec![focal.x, focal.y, center.x, center.y]),
+        };
+
+        let params: Vec<_> = params.iter().map(|p| p.to_string()).collect();
+        writeln!(
+            out,
+            "{} {model} {} {} {}",
+            i + 1,
+            img_size.x,
+            img_size.y,
+            params.join(" ")
+        )?;
+    }
+
+    Ok(out)
+}
+
+/// Writes the poses of the views as a COLMAP `images.txt`.
+///
+/// Images only refer to their file name, and the 2D points are left empty.
+pub fn views_to_colmap_images(views: &[SceneView]) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "# Image list with two lines of data per image:")?;
+    writeln!(
+        out,
+        "#   IMAGE_ID, QW, QX, QY, QZ, TX, TY, TZ, CAMERA_ID, NAME"
+    )?;
+    writeln!(out, "#   POINTS2D[] as (X, Y, POINT3D_ID)")?;
+    writeln!(out, "# Number of images: {}", views.len())?;
+
+    for (i, view) in views.iter().enumerate() {
+        // COLMAP stores the world to camera transform.
+        let (_, quat, translation) = view.camera.world_to_local().to_scale_rotation_translation();
+        let id = i + 1;
+
+        writeln!(
+            out,
+            "{id} {} {} {} {} {} {} {} {id} {}",
+            quat.w,
+            quat.x,
+            quat.y,
+            quat.z,
+            translation.x,
+            translation.y,
+            translation.z,
+            image_file_name(view)
+        )?;
+        writeln!(out)?;
+    }
+
+    Ok(out)
+}
+
+/// Writes the views as a NeRF style `transforms.json`.
+///
+/// File paths are written without extension like the NeRF synthetic scenes. The field of view
+/// of the first view is written for the whole scene. When the views don't all share it, or have
+/// an off center principal point, each frame also gets its own intrinsics like nerfstudio does.
+pub fn views_to_transforms_json(views: &[SceneView]) -> Result<String> {
+    let first = views
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No views to export"))?;
+    let camera_angle_x = first.camera.fov.x;
+    let per_frame_intrinsics = views.iter().any(|view| {
+        view.camera.fov != first.camera.fov || view.camera.center_uv != glam::vec2(0.5, 0.5)
+    });
+
+    let frames = views
+        .iter()
+        .map(|view| {
+            // Undo the basis change done when loading, going back to an OpenGL style camera
+            // to world transform.
+            let mut transform = glam::Mat4::from_rotation_x(-std::f32::consts::PI / 2.0)
+                * view.camera.local_to_world();
+            transform.y_axis *= -1.0;
+            transform.z_axis *= -1.0;
+
+            let intrinsics = per_frame_intrinsics.then(|| {
+                let img_size = glam::uvec2(view.image.width(), view.image.height());
+                let focal = view.camera.focal(img_size);
+                let center = view.camera.center(img_size);
+                FrameIntrinsics {
+                    fl_x: focal.x,
+                    fl_y: focal.y,
+                    cx: center.x,
+                    cy: center.y,
+                    w: img_size.x,
+                    h: img_size.y,
+                }
+            });
+
+            FrameData {
+                transform_matrix: transform
+                    .transpose()
+                    .to_cols_array_2d()
+                    .iter()
+                    .map(|row| row.to_vec())
+                    .collect(),
+                file_path: Path::new(&view.name)
+                    .with_extension("")
+                    .to_string_lossy()
+                    .into_owned(),
+                intrinsics,
+            }
+        })
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&SyntheticScene {
+        camera_angle_x,
+        frames,
+    })?)
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use crate::{read_dataset_views, LoadDatasetArgs, ZipData};
+    use async_std::stream::StreamExt;
+    use async_std::task;
+    use brush_render::camera::Camera;
+    use std::io::{Cursor, Write};
+    use std::sync::Arc;
+    use zip::ZipArchive;
+
+    fn test_views() -> Vec<SceneView> {
+        let image = Arc::new(image::DynamicImage::new_rgb8(16, 16));
+        let poses = [
+            (glam::vec3(0.0, 0.0, -5.0), glam::Quat::IDENTITY),
+            (
+                glam::vec3(1.0, 2.0, -3.0),
+                glam::Quat::from_euler(glam::EulerRot::YXZ, 0.4, -0.2, 0.1),
+            ),
+            (
+                glam::vec3(-4.0, 0.5, 2.0),
+                glam::Quat::from_euler(glam::EulerRot::YXZ, 2.5, 0.3, -0.6),
+            ),
+        ];
+        poses
+            .into_iter()
+            .enumerate()
+            .map(|(i, (position, rotation))| SceneView {
+                name: format!("view_{i}.png"),
+                camera: Camera::new(
+                    position,
+                    rotation,
+                    glam::vec2(0.8, 0.8),
+                    glam::vec2(0.5, 0.5),
+                ),
+                image: image.clone(),
+            })
+            .collect()
+    }
+
+    // Zips up the exported files together with the images of the views, and reads them back
+    // like any other dataset.
+    fn read_back(
+        views: &[SceneView],
+        files: Vec<(String, String)>,
+        image_dir: &str,
+    ) -> Vec<SceneView> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, contents) in files {
+            writer.start_file(name, options).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        for view in views {
+            let mut png = vec![];
+            view.image
+                .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+                .unwrap();
+            writer
+                .start_file(format!("{image_dir}{}", image_file_name(view)), options)
+                .unwrap();
+            writer.write_all(&png).unwrap();
+        }
+        let data = ZipData::from(writer.finish().unwrap().into_inner());
+        let archive = ZipArchive::new(data.open_for_read()).unwrap();
+
+        let load_args = LoadDatasetArgs {
+            max_frames: None,
+            max_resolution: None,
+            eval_split_every: None,
+        };
+
+        task::block_on(async {
+            let mut stream = read_dataset_views(archive, &load_args).unwrap();
+            let mut dataset = None;
+            while let Some(d) = stream.next().await {
+                dataset = Some(d.unwrap());
+            }
+            dataset.unwrap().train.views.as_ref().clone()
+        })
+    }
+
+    fn assert_same_cameras(views: &[SceneView], read: &[SceneView]) {
+        assert_eq!(views.len(), read.len());
+        for (view, read) in views.iter().zip(read) {
+            assert_eq!(image_file_name(view), image_file_name(read));
+            assert!(view.camera.position.abs_diff_eq(read.camera.position, 1e-4));
+            assert!(view.camera.rotation.dot(read.camera.rotation).abs() > 1.0 - 1e-5);
+            assert!(view.camera.fov.abs_diff_eq(read.camera.fov, 1e-4));
+            assert!(view
+                .camera
+                .center_uv
+                .abs_diff_eq(read.camera.center_uv, 1e-4));
+        }
+    }
+
+    #[test]
+    fn colmap_round_trip() {
+        let mut views = test_views();
+        let distortion = glam::vec4(0.1, -0.05, 0.01, 0.002);
+        views[1].camera = views[1]
+            .camera
+            .clone()
+            .with_model(CameraModel::Fisheye { distortion });
+
+        let files = vec![
+            (
+                "sparse/0/cameras.txt".to_owned(),
+                views_to_colmap_cameras(&views).unwrap(),
+            ),
+            (
+                "sparse/0/images.txt".to_owned(),
+                views_to_colmap_images(&views).unwrap(),
+            ),
+        ];
+        let read = read_back(&views, files, "images/");
+        assert_same_cameras(&views, &read);
+
+        match read[1].camera.model {
+            CameraModel::Fisheye { distortion: read } => {
+                assert!(read.abs_diff_eq(distortion, 1e-6));
+            }
+            _ => panic!("Expected a fisheye camera"),
+        }
+        assert!(matches!(read[0].camera.model, CameraModel::Pinhole));
+    }
+
+    #[test]
+    fn transforms_json_round_trip() {
+        let views = test_views();
+        let files = vec![(
+            "transforms_train.json".to_owned(),
+            views_to_transforms_json(&views).unwrap(),
+        )];
+        let read = read_back(&views, files, "");
+        assert_same_cameras(&views, &read);
+    }
+
+    #[test]
+    fn transforms_json_keeps_per_view_intrinsics() {
+        let mut views = test_views();
+        views[1].camera.fov = glam::vec2(0.6, 0.5);
+        views[2].camera.center_uv = glam::vec2(0.45, 0.55);
+
+        let json = views_to_transforms_json(&views).unwrap();
+        let files = vec![("transforms_train.json".to_owned(), json)];
+        let read = read_back(&views, files, "");
+        assert_same_cameras(&views, &read);
+    }
+}
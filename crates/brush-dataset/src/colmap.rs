@@ -8,7 +8,7 @@ use anyhow::{Context, Result};
 use async_fn_stream::try_fn_stream;
 use async_std::stream::StreamExt;
 use brush_render::{
-    camera::{self, Camera},
+    camera::{self, Camera, CameraModel},
     gaussian_splats::Splats,
     Backend,
 };
@@ -101,9 +101,13 @@ fn read_views(
                 let cam_to_world = world_to_cam.inverse();
                 let (_, quat, translation) = cam_to_world.to_scale_rotation_translation();
 
-                let converted_cam =
+                let mut converted_cam =
                     Camera::new(translation, quat, glam::vec2(fovx, fovy), center_uv);
 
+                if let Some(distortion) = cam.fisheye_distortion() {
+                    converted_cam = converted_cam.with_model(CameraModel::Fisheye { distortion });
+                }
+
                 let view = SceneView {
                     name: img_path.to_str().context("Invalid file name")?.to_owned(),
                     camera: converted_cam,
@@ -39,6 +39,23 @@ impl CameraModel {
         }
     }
 
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "SIMPLE_PINHOLE" => Some(CameraModel::SimplePinhole),
+            "PINHOLE" => Some(CameraModel::Pinhole),
+            "SIMPLE_RADIAL" => Some(CameraModel::SimpleRadial),
+            "RADIAL" => Some(CameraModel::Radial),
+            "OPENCV" => Some(CameraModel::OpenCV),
+            "OPENCV_FISHEYE" => Some(CameraModel::OpenCvFishEye),
+            "FULL_OPENCV" => Some(CameraModel::FullOpenCV),
+            "FOV" => Some(CameraModel::Fov),
+            "SIMPLE_RADIAL_FISHEYE" => Some(CameraModel::SimpleRadialFisheye),
+            "RADIAL_FISHEYE" => Some(CameraModel::RadialFisheye),
+            "THIN_PRISM_FISHEYE" => Some(CameraModel::ThinPrismFisheye),
+            _ => None,
+        }
+    }
+
     fn num_params(&self) -> usize {
         match self {
             CameraModel::SimplePinhole => 3,
@@ -132,6 +149,19 @@ impl Camera {
         }] as f32;
         glam::vec2(x, y)
     }
+
+    /// The Kannala-Brandt coefficients (k1, k2, k3, k4) for fisheye models. Tangential
+    /// and thin prism terms are ignored.
+    pub(crate) fn fisheye_distortion(&self) -> Option<glam::Vec4> {
+        let k = |i: usize| self.params[i] as f32;
+        match self.model {
+            CameraModel::OpenCvFishEye => Some(glam::vec4(k(4), k(5), k(6), k(7))),
+            CameraModel::SimpleRadialFisheye => Some(glam::vec4(k(3), 0.0, 0.0, 0.0)),
+            CameraModel::RadialFisheye => Some(glam::vec4(k(3), k(4), 0.0, 0.0)),
+            CameraModel::ThinPrismFisheye => Some(glam::vec4(k(4), k(5), k(8), k(9))),
+            _ => None,
+        }
+    }
 }
 
 fn parse<T: std::str::FromStr>(s: &str) -> io::Result<T> {
@@ -159,7 +189,9 @@ pub(crate) fn read_cameras_text<R: Read>(reader: &mut R) -> io::Result<HashMap<i
         }
 
         let id = parse(parts[0])?;
-        let model = CameraModel::from_id(parse(parts[1])?)
+        // COLMAP writes model names in text files, but accept ids as well.
+        let model = CameraModel::from_name(parts[1])
+            .or_else(|| parts[1].parse().ok().and_then(CameraModel::from_id))
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid camera model"))?;
         let width = parse(parts[2])?;
         let height = parse(parts[3])?;
@@ -237,7 +269,7 @@ pub(crate) fn read_images_text<R: Read>(reader: &mut R) -> io::Result<HashMap<i3
         }
 
         let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 9 {
+        if parts.len() < 10 {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Invalid image data",
@@ -257,10 +289,15 @@ pub(crate) fn read_images_text<R: Read>(reader: &mut R) -> io::Result<HashMap<i3
         let camera_id: i32 = parse(parts[8])?;
         let name = parts[9].to_string();
 
+        // Each image line is followed by a line with its 2D points, which can be empty.
+        line.clear();
+        buf_reader.read_line(&mut line)?;
+        let points: Vec<&str> = line.split_whitespace().collect();
+
         let mut xys = Vec::new();
         let mut point3d_ids = Vec::new();
 
-        for chunk in parts[10..].chunks(3) {
+        for chunk in points.chunks(3) {
             if chunk.len() < 3 {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
@@ -1,3 +1,4 @@
+pub mod camera_export;
 pub mod colmap;
 pub mod colmap_read_model;
 pub mod nerf_synthetic;
@@ -24,6 +24,12 @@ struct SyntheticScene {
 struct FrameData {
     transform_matrix: Vec<Vec<f32>>,
     file_path: String,
+    // Optional per frame intrinsics in pixels, as written by nerfstudio.
+    fl_x: Option<f32>,
+    fl_y: Option<f32>,
+    cx: Option<f32>,
+    cy: Option<f32>,
+    w: Option<u32>,
 }
 
 fn read_transforms_file(
@@ -103,17 +109,27 @@ fn read_transforms_file(
                     image = rgb_image.into();
                 }
 
-                let fovy =
-                    camera::focal_to_fov(camera::fov_to_focal(fovx, image.width()), image.height());
+                // The intrinsics are given for the full size image, so scale them to the image as
+                // loaded.
+                let (width, height) = (image.width(), image.height());
+                let scale = frame.w.map_or(1.0, |w| width as f32 / w as f32);
+
+                let fovx = match frame.fl_x {
+                    Some(fl_x) => camera::focal_to_fov(fl_x * scale, width),
+                    None => fovx,
+                };
+                let fovy = match frame.fl_y.or(frame.fl_x) {
+                    Some(fl_y) => camera::focal_to_fov(fl_y * scale, height),
+                    None => camera::focal_to_fov(camera::fov_to_focal(fovx, width), height),
+                };
+                let center_uv = glam::vec2(
+                    frame.cx.map_or(0.5, |cx| cx * scale / width as f32),
+                    frame.cy.map_or(0.5, |cy| cy * scale / height as f32),
+                );
 
                 let view = SceneView {
                     name: image_path.to_str().context("Invalid filename")?.to_owned(),
-                    camera: Camera::new(
-                        translation,
-                        rotation,
-                        glam::vec2(fovx, fovy),
-                        glam::vec2(0.5, 0.5),
-                    ),
+                    camera: Camera::new(translation, rotation, glam::vec2(fovx, fovy), center_uv),
                     image: Arc::new(image),
                 };
                 anyhow::Result::<SceneView>::Ok(view)
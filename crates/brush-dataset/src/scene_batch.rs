@@ -44,6 +44,7 @@ impl<B: Backend> SceneLoader<B> {
                 let scene_batch = SceneBatch {
                     gt_images: batch_tensor,
                     gt_views,
+                    gt_view_ids: indices.iter().map(|&x| x as usize).collect(),
                     scene_extent,
                 };
 
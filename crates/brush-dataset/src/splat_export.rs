@@ -1,13 +1,106 @@
 use anyhow::anyhow;
-use brush_render::{gaussian_splats::Splats, Backend};
-use burn::tensor::DataError;
+use brush_render::{
+    bounding_box::OrientedBox,
+    gaussian_splats::Splats,
+    panorama,
+    render::{sh_coeffs_for_degree, SH_C0},
+    Backend,
+};
+use brush_train::image::tensor_into_image;
+use burn::tensor::{DataError, Tensor};
+use glam::{Quat, Vec3};
 use ply_rs::{
-    ply::{self, Ply, PropertyDef, PropertyType, ScalarType},
+    ply::{self, DefaultElement, Ply, Property, PropertyDef, PropertyType, ScalarType},
     writer::Writer,
 };
 
 use crate::splat_import::GaussianData;
 
+/// Options to slim down splats before writing them out.
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    /// Drop all SH bands above this degree.
+    pub sh_degree: Option<u32>,
+    /// Drop all splats with an opacity below this threshold.
+    pub min_opacity: f32,
+    /// Only keep splats with their center inside this box.
+    pub crop: Option<OrientedBox>,
+    /// Replace the view dependent color by the view independent color, clamped to a displayable range.
+    pub bake_color: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            sh_degree: None,
+            min_opacity: 0.0,
+            crop: None,
+            bake_color: false,
+        }
+    }
+}
+
+/// Slims down the splats according to the options. The 3D filter is kept as is, see
+/// [`apply_external_export_options`] for formats that don't support it.
+pub async fn apply_export_options<B: Backend>(
+    splats: Splats<B>,
+    options: &ExportOptions,
+) -> anyhow::Result<Splats<B>> {
+    let mut splats = splats;
+
+    let mut keep = vec![splats.opacity().greater_equal_elem(options.min_opacity)];
+
+    if let Some(crop) = &options.crop {
+        keep.push(splats.inside_mask(crop));
+    }
+
+    let keep_inds = Tensor::stack::<2>(keep, 1)
+        .all_dim(1)
+        .squeeze::<1>(1)
+        .argwhere_async()
+        .await;
+
+    if keep_inds.dims()[0] == 0 {
+        anyhow::bail!("No splats left to export.");
+    }
+
+    if keep_inds.dims()[0] < splats.num_splats() {
+        splats = splats.select(keep_inds.squeeze(1));
+    }
+
+    let max_coeffs = if options.bake_color {
+        Some(1)
+    } else {
+        options.sh_degree.map(sh_coeffs_for_degree)
+    };
+
+    if let Some(max_coeffs) = max_coeffs {
+        let [n, coeffs, _] = splats.sh_coeffs.dims();
+        let coeffs = coeffs.min(max_coeffs as usize);
+        Splats::map_param(&mut splats.sh_coeffs, |x| x.slice([0..n, 0..coeffs, 0..3]));
+    }
+
+    if options.bake_color {
+        Splats::map_param(&mut splats.sh_coeffs, |x| {
+            let color = (x * SH_C0 + 0.5).clamp(0.0, 1.0);
+            (color - 0.5) / SH_C0
+        });
+    }
+
+    Ok(splats)
+}
+
+/// Like [`apply_export_options`], but for formats read by other viewers. These don't apply
+/// a 3D filter, so it's folded into the splats first.
+pub async fn apply_external_export_options<B: Backend>(
+    splats: Splats<B>,
+    options: &ExportOptions,
+) -> anyhow::Result<Splats<B>> {
+    let mut splats = splats;
+    splats.bake_filter_3d();
+    apply_export_options(splats, options).await
+}
+
 async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Result<Vec<GaussianData>, DataError> {
     let means = splats.means.val().into_data_async().await.to_vec()?;
     let log_scales = splats.log_scales.val().into_data_async().await.to_vec()?;
@@ -64,7 +157,12 @@ async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Result<Vec<GaussianDa
     Ok(splats)
 }
 
-pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> anyhow::Result<Vec<u8>> {
+pub async fn splat_to_ply<B: Backend>(
+    splats: Splats<B>,
+    options: &ExportOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let splats = apply_external_export_options(splats, options).await?;
+
     let data = read_splat_data(splats.clone())
         .await
         .map_err(|_| anyhow!("Failed to read data from splat"))?;
@@ -104,3 +202,392 @@ pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> anyhow::Result<Vec<u
     writer.write_ply(&mut buf, &mut ply)?;
     Ok(buf)
 }
+
+fn splat_color(splat: &GaussianData) -> [u8; 4] {
+    let [r, g, b] = splat
+        .sh_dc
+        .map(|c| ((c * SH_C0 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8);
+    let alpha = 1.0 / (1.0 + (-splat.opacity).exp());
+    [r, g, b, (alpha * 255.0).round() as u8]
+}
+
+fn colored_vertex(pos: Vec3, color: [u8; 4]) -> DefaultElement {
+    let mut vertex = DefaultElement::new();
+    vertex.insert("x".to_owned(), Property::Float(pos.x));
+    vertex.insert("y".to_owned(), Property::Float(pos.y));
+    vertex.insert("z".to_owned(), Property::Float(pos.z));
+    vertex.insert("red".to_owned(), Property::UChar(color[0]));
+    vertex.insert("green".to_owned(), Property::UChar(color[1]));
+    vertex.insert("blue".to_owned(), Property::UChar(color[2]));
+    vertex.insert("alpha".to_owned(), Property::UChar(color[3]));
+    vertex
+}
+
+fn colored_vertex_def() -> ply::ElementDef {
+    let mut vertex = ply::ElementDef::new("vertex");
+    vertex.properties = ["x", "y", "z"]
+        .into_iter()
+        .map(|name| PropertyDef::new(name, PropertyType::Scalar(ScalarType::Float)))
+        .chain(
+            ["red", "green", "blue", "alpha"]
+                .into_iter()
+                .map(|name| PropertyDef::new(name, PropertyType::Scalar(ScalarType::UChar))),
+        )
+        .collect();
+    vertex
+}
+
+fn write_default_ply(mut ply: Ply<DefaultElement>) -> anyhow::Result<Vec<u8>> {
+    ply.header.encoding = ply::Encoding::BinaryLittleEndian;
+    ply.header.comments.push("Exported from Brush".to_string());
+    ply.header.comments.push("Vertical axis: y".to_string());
+
+    let mut buf = vec![];
+    let writer = Writer::<DefaultElement>::new();
+    writer.write_ply(&mut buf, &mut ply)?;
+    Ok(buf)
+}
+
+/// Writes the splat centers as a plain colored point cloud, which most mesh & point cloud tools can read.
+pub async fn splat_to_point_cloud_ply<B: Backend>(
+    splats: Splats<B>,
+    options: &ExportOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let splats = apply_external_export_options(splats, options).await?;
+    let data = read_splat_data(splats)
+        .await
+        .map_err(|_| anyhow!("Failed to read data from splat"))?;
+
+    let vertices = data
+        .iter()
+        .map(|splat| colored_vertex(Vec3::from_array(splat.means), splat_color(splat)))
+        .collect();
+
+    let mut ply = Ply::<DefaultElement>::new();
+    ply.header.elements.push(colored_vertex_def());
+    ply.payload.insert("vertex".to_string(), vertices);
+    write_default_ply(ply)
+}
+
+// Vertices & faces of an icosahedron, the lowest poly approximation of a sphere that still looks like one.
+fn icosahedron() -> ([Vec3; 12], [[u32; 3]; 20]) {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let vertices = [
+        glam::vec3(-1.0, t, 0.0),
+        glam::vec3(1.0, t, 0.0),
+        glam::vec3(-1.0, -t, 0.0),
+        glam::vec3(1.0, -t, 0.0),
+        glam::vec3(0.0, -1.0, t),
+        glam::vec3(0.0, 1.0, t),
+        glam::vec3(0.0, -1.0, -t),
+        glam::vec3(0.0, 1.0, -t),
+        glam::vec3(t, 0.0, -1.0),
+        glam::vec3(t, 0.0, 1.0),
+        glam::vec3(-t, 0.0, -1.0),
+        glam::vec3(-t, 0.0, 1.0),
+    ]
+    .map(|v| v.normalize());
+
+    let faces = [
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    (vertices, faces)
+}
+
+/// Writes each splat as a small colored ellipsoid mesh, spanning `sigma` standard deviations.
+pub async fn splat_to_ellipsoid_ply<B: Backend>(
+    splats: Splats<B>,
+    options: &ExportOptions,
+    sigma: f32,
+) -> anyhow::Result<Vec<u8>> {
+    let splats = apply_external_export_options(splats, options).await?;
+    let data = read_splat_data(splats)
+        .await
+        .map_err(|_| anyhow!("Failed to read data from splat"))?;
+
+    let (sphere_verts, sphere_faces) = icosahedron();
+
+    let mut vertices = Vec::with_capacity(data.len() * sphere_verts.len());
+    let mut faces = Vec::with_capacity(data.len() * sphere_faces.len());
+
+    for splat in &data {
+        let color = splat_color(splat);
+        let [w, x, y, z] = splat.rotation;
+        let rotation = Quat::from_xyzw(x, y, z, w).normalize();
+        let scale = Vec3::from_array(splat.scale).exp() * sigma;
+        let center = Vec3::from_array(splat.means);
+
+        let base = vertices.len() as u32;
+        vertices.extend(
+            sphere_verts
+                .iter()
+                .map(|&v| colored_vertex(center + rotation * (v * scale), color)),
+        );
+        faces.extend(sphere_faces.iter().map(|f| {
+            let mut face = DefaultElement::new();
+            face.insert(
+                "vertex_indices".to_owned(),
+                Property::ListUInt(f.iter().map(|i| base + i).collect()),
+            );
+            face
+        }));
+    }
+
+    let mut face_def = ply::ElementDef::new("face");
+    face_def.properties.push(PropertyDef::new(
+        "vertex_indices",
+        PropertyType::List(ScalarType::UChar, ScalarType::UInt),
+    ));
+
+    let mut ply = Ply::<DefaultElement>::new();
+    ply.header.elements.push(colored_vertex_def());
+    ply.header.elements.push(face_def);
+    ply.payload.insert("vertex".to_string(), vertices);
+    ply.payload.insert("face".to_string(), faces);
+    write_default_ply(ply)
+}
+
+/// Renders a 360° equirectangular panorama around `position`, encoded as a png.
+pub async fn splat_to_panorama_png<B: Backend>(
+    splats: Splats<B>,
+    options: &ExportOptions,
+    position: Vec3,
+    rotation: Quat,
+    img_size: glam::UVec2,
+    background: Vec3,
+) -> anyhow::Result<Vec<u8>> {
+    let splats = apply_export_options(splats, options).await?;
+    let img = panorama::render_equirect(&splats, position, rotation, img_size, background);
+    let [h, w, _] = img.dims();
+    let rgb = img.slice([0..h, 0..w, 0..3]);
+    let image = tensor_into_image(rgb.into_data_async().await).to_rgb8();
+
+    let mut bytes = vec![];
+    image.write_to(
+        &mut std::io::Cursor::new(&mut bytes),
+        image::ImageFormat::Png,
+    )?;
+    Ok(bytes)
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use async_std::task;
+    use brush_render::PrimaryBackend;
+    use burn::backend::wgpu::WgpuDevice;
+
+    type B = PrimaryBackend;
+
+    // Four splats along the x axis, with increasing opacity and SH degree 2.
+    fn test_splats(device: &WgpuDevice) -> Splats<B> {
+        let means = Tensor::<B, 1>::from_floats(
+            [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 3.0, 0.0, 0.0],
+            device,
+        )
+        .reshape([4, 3]);
+        let opacities = [0.1f32, 0.4, 0.6, 0.9].map(|o| (o / (1.0 - o)).ln());
+        Splats::from_data(
+            means,
+            Tensor::ones([4, 9, 3], device),
+            Tensor::<B, 1>::from_floats([1.0, 0.0, 0.0, 0.0], device)
+                .reshape([1, 4])
+                .repeat_dim(0, 4),
+            Tensor::from_floats(opacities, device),
+            Tensor::zeros([4, 3], device),
+            device,
+        )
+    }
+
+    fn export(options: ExportOptions) -> anyhow::Result<Splats<B>> {
+        let device = WgpuDevice::BestAvailable;
+        task::block_on(apply_export_options(test_splats(&device), &options))
+    }
+
+    #[test]
+    fn min_opacity_drops_splats() {
+        let splats = export(ExportOptions {
+            min_opacity: 0.5,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(splats.num_splats(), 2);
+
+        let splats = export(ExportOptions {
+            min_opacity: 0.95,
+            ..Default::default()
+        });
+        assert!(splats.is_err());
+    }
+
+    #[test]
+    fn crop_keeps_splats_inside() {
+        let crop = OrientedBox::new(
+            glam::vec3(1.5, 0.0, 0.0),
+            glam::vec3(0.6, 1.0, 1.0),
+            Quat::IDENTITY,
+        );
+        let splats = export(ExportOptions {
+            crop: Some(crop),
+            ..Default::default()
+        })
+        .unwrap();
+        let means = splats.means.val().into_data().to_vec::<f32>().unwrap();
+        assert_eq!(means, [1.0, 0.0, 0.0, 2.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn sh_degree_drops_bands() {
+        let splats = export(ExportOptions {
+            sh_degree: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(splats.sh_coeffs.dims(), [4, 4, 3]);
+
+        // A higher degree than the splats have keeps all bands.
+        let splats = export(ExportOptions {
+            sh_degree: Some(3),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(splats.sh_coeffs.dims(), [4, 9, 3]);
+    }
+
+    #[test]
+    fn bake_color_clamps_to_displayable_range() {
+        let device = WgpuDevice::BestAvailable;
+        let mut splats = test_splats(&device);
+        // DC colors above one, below zero, and in range.
+        Splats::map_param(&mut splats.sh_coeffs, |x| {
+            let dc = Tensor::<B, 1>::from_floats([3.0, -3.0, 0.5], &device)
+                .reshape([1, 1, 3])
+                .repeat_dim(0, 4);
+            let [n, coeffs, _] = x.dims();
+            Tensor::cat(vec![dc, x.slice([0..n, 1..coeffs, 0..3])], 1)
+        });
+
+        let baked = task::block_on(apply_export_options(
+            splats,
+            &ExportOptions {
+                bake_color: true,
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        assert_eq!(baked.sh_coeffs.dims(), [4, 1, 3]);
+
+        let colors = (baked.sh_coeffs.val() * SH_C0 + 0.5)
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap();
+        let expected = [1.0, 0.0, 0.5 * SH_C0 + 0.5];
+        for color in colors.chunks_exact(3) {
+            for (c, e) in color.iter().zip(expected) {
+                assert!((c - e).abs() < 1e-5, "Baked color {c}, expected {e}");
+            }
+        }
+    }
+
+    fn read_ply(data: &[u8]) -> Ply<DefaultElement> {
+        ply_rs::parser::Parser::<DefaultElement>::new()
+            .read_ply(&mut std::io::Cursor::new(data))
+            .unwrap()
+    }
+
+    // Checks the vertices have the color of the test splats, and the alpha of the given opacities.
+    fn assert_vertex_colors(vertices: &[DefaultElement], opacities: &[f32]) {
+        let channel = |v: &DefaultElement, name: &str| match v[name] {
+            Property::UChar(c) => c as f32,
+            _ => panic!("{name} should be a uchar"),
+        };
+        let color = ((SH_C0 + 0.5) * 255.0).round();
+        let per_splat = vertices.len() / opacities.len();
+        for (i, vertex) in vertices.iter().enumerate() {
+            for name in ["red", "green", "blue"] {
+                assert_eq!(channel(vertex, name), color);
+            }
+            let alpha = opacities[i / per_splat] * 255.0;
+            assert!((channel(vertex, "alpha") - alpha).abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn point_cloud_has_a_vertex_per_splat() {
+        let device = WgpuDevice::BestAvailable;
+        let export = |min_opacity| {
+            let options = ExportOptions {
+                min_opacity,
+                ..Default::default()
+            };
+            let data =
+                task::block_on(splat_to_point_cloud_ply(test_splats(&device), &options)).unwrap();
+            read_ply(&data)
+        };
+
+        let ply = export(0.0);
+        assert_vertex_colors(&ply.payload["vertex"], &[0.1, 0.4, 0.6, 0.9]);
+        let xs: Vec<_> = ply.payload["vertex"]
+            .iter()
+            .map(|v| match v["x"] {
+                Property::Float(x) => x,
+                _ => panic!("x should be a float"),
+            })
+            .collect();
+        assert_eq!(xs, [0.0, 1.0, 2.0, 3.0]);
+
+        let ply = export(0.5);
+        assert_eq!(ply.payload["vertex"].len(), 2);
+        assert_vertex_colors(&ply.payload["vertex"], &[0.6, 0.9]);
+    }
+
+    #[test]
+    fn ellipsoids_have_a_mesh_per_splat() {
+        let device = WgpuDevice::BestAvailable;
+        let options = ExportOptions {
+            min_opacity: 0.5,
+            ..Default::default()
+        };
+        let data =
+            task::block_on(splat_to_ellipsoid_ply(test_splats(&device), &options, 2.0)).unwrap();
+        let ply = read_ply(&data);
+
+        let (sphere_verts, sphere_faces) = icosahedron();
+        let vertices = &ply.payload["vertex"];
+        let faces = &ply.payload["face"];
+        assert_eq!(vertices.len(), 2 * sphere_verts.len());
+        assert_eq!(faces.len(), 2 * sphere_faces.len());
+        assert_vertex_colors(vertices, &[0.6, 0.9]);
+
+        // All faces index into the vertices.
+        for face in faces {
+            match &face["vertex_indices"] {
+                Property::ListUInt(indices) => {
+                    assert_eq!(indices.len(), 3);
+                    assert!(indices.iter().all(|&i| (i as usize) < vertices.len()));
+                }
+                _ => panic!("Faces should be a list of uint"),
+            }
+        }
+    }
+}
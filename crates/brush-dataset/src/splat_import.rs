@@ -9,6 +9,7 @@ use ply_rs::{
     parser::Parser,
     ply::{Property, PropertyAccess},
 };
+use safetensors::SafeTensors;
 use std::io::BufRead;
 use tracing::trace_span;
 
@@ -147,6 +148,8 @@ fn update_splats<B: Backend>(
             log_scales: Param::initialized(ParamId::new(), log_scales),
             xys_dummy: Tensor::zeros([n_splats, 2], device).require_grad(),
             xys_norm_dummy: Tensor::zeros([n_splats], device).require_grad(),
+            flags: None,
+            filter_3d: None,
         };
         init.norm_rotations();
         // Create a new splat instance if it hasn't been initialzized yet.
@@ -311,3 +314,18 @@ pub fn load_splat_from_ply<B: Backend>(
         Ok(())
     })
 }
+
+pub fn load_splat_from_safetensors<B: Backend>(
+    data: &[u8],
+    device: &B::Device,
+) -> Result<Splats<B>> {
+    let _span = trace_span!("Read safetensors splats").entered();
+    let tensors = SafeTensors::deserialize(data)?;
+    let splats = Splats::from_safetensors(&tensors, device)?;
+
+    if splats.num_splats() == 0 {
+        Err(anyhow::anyhow!("No splats found"))?;
+    }
+
+    Ok(splats)
+}
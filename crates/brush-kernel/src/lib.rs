@@ -164,7 +164,16 @@ pub fn create_uniform_buffer<R: JitRuntime, T: Pod>(
     device: &R::Device,
     client: &ComputeClient<R::Server, R::Channel>,
 ) -> JitTensor<R, u32> {
-    let bytes = bytemuck::bytes_of(&val);
+    create_uniform_array(std::slice::from_ref(&val), device, client)
+}
+
+// Upload a slice of structs, to bind as an array in a kernel.
+pub fn create_uniform_array<R: JitRuntime, T: Pod>(
+    vals: &[T],
+    device: &R::Device,
+    client: &ComputeClient<R::Server, R::Channel>,
+) -> JitTensor<R, u32> {
+    let bytes: &[u8] = bytemuck::cast_slice(vals);
     let shape = bytes.len() / 4;
 
     JitTensor::new_contiguous(
@@ -9,8 +9,10 @@ fn main() -> miette::Result<()> {
             "src/shaders/get_tile_bin_edges.wgsl",
             "src/shaders/rasterize.wgsl",
             "src/shaders/rasterize_backwards.wgsl",
+            "src/shaders/rasterize_features.wgsl",
             "src/shaders/gather_grads.wgsl",
             "src/shaders/project_backwards.wgsl",
+            "src/shaders/sum_isect_grads.wgsl",
         ],
         &["src/shaders/helpers.wgsl"],
         "src/shaders",
@@ -20,3 +20,102 @@ impl BoundingBox {
         self.center + self.extent
     }
 }
+
+/// A box with an arbitrary orientation, eg. to crop a scene with.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct OrientedBox {
+    pub center: glam::Vec3,
+    pub extent: glam::Vec3,
+    pub rotation: glam::Quat,
+}
+
+impl OrientedBox {
+    pub fn new(center: glam::Vec3, extent: glam::Vec3, rotation: glam::Quat) -> Self {
+        Self {
+            center,
+            extent,
+            rotation,
+        }
+    }
+
+    /// Transforms a world space point to the local space of the box, where the box spans [-extent, extent].
+    pub fn world_to_local(&self, point: glam::Vec3) -> glam::Vec3 {
+        self.rotation.inverse() * (point - self.center)
+    }
+
+    pub fn contains(&self, point: glam::Vec3) -> bool {
+        self.world_to_local(point).abs().cmple(self.extent).all()
+    }
+
+    /// The six planes bounding the box, facing inwards. See [`ClipVolume::Planes`].
+    pub fn planes(&self) -> [glam::Vec4; 6] {
+        let axes = glam::Mat3::from_quat(self.rotation);
+        [0, 1, 2]
+            .map(|i| {
+                let axis = axes.col(i);
+                let offset = axis.dot(self.center);
+                [
+                    axis.extend(self.extent[i] - offset),
+                    (-axis).extend(self.extent[i] + offset),
+                ]
+            })
+            .concat()
+            .try_into()
+            .expect("Box has six planes")
+    }
+}
+
+/// A volume to clip splats against while rendering. Splats with their center outside
+/// of the volume are not rendered.
+#[derive(Clone, Debug)]
+pub enum ClipVolume {
+    Box(OrientedBox),
+    /// Planes as (normal, offset). A point is inside when `dot(normal, point) + offset >= 0`
+    /// for all planes.
+    Planes(Vec<glam::Vec4>),
+}
+
+impl ClipVolume {
+    pub fn planes(&self) -> Vec<glam::Vec4> {
+        match self {
+            Self::Box(bounds) => bounds.planes().to_vec(),
+            Self::Planes(planes) => planes.clone(),
+        }
+    }
+
+    pub fn contains(&self, point: glam::Vec3) -> bool {
+        self.planes()
+            .iter()
+            .all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+    }
+}
+
+impl From<BoundingBox> for OrientedBox {
+    fn from(bounds: BoundingBox) -> Self {
+        Self::new(bounds.center, bounds.extent, glam::Quat::IDENTITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_planes_match_contains() {
+        let bounds = OrientedBox::new(
+            glam::vec3(1.0, -2.0, 0.5),
+            glam::vec3(0.5, 1.0, 2.0),
+            glam::Quat::from_euler(glam::EulerRot::YXZ, 0.3, -0.7, 1.1),
+        );
+        let clip = ClipVolume::Box(bounds.clone());
+
+        for x in -8..=8 {
+            for y in -8..=8 {
+                for z in -8..=8 {
+                    let p = glam::vec3(x as f32, y as f32, z as f32) * 0.4 + bounds.center;
+                    assert_eq!(bounds.contains(p), clip.contains(p), "Mismatch at {p}");
+                }
+            }
+        }
+    }
+}
@@ -1,9 +1,43 @@
-#[derive(Debug, Default, Clone)]
+/// How the camera projects points onto the image.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CameraModel {
+    /// Perspective projection, using the field of view of the camera.
+    #[default]
+    Pinhole,
+    /// Parallel projection, where `extent` is the size of the view in world units.
+    Orthographic { extent: glam::Vec2 },
+    /// Kannala-Brandt fisheye projection, with distortion coefficients (k1, k2, k3, k4).
+    ///
+    /// All zero coefficients give an equidistant fisheye. The focal length is derived from the
+    /// field of view as for a pinhole camera.
+    Fisheye { distortion: glam::Vec4 },
+}
+
+pub const DEFAULT_NEAR: f32 = 0.01;
+pub const DEFAULT_FAR: f32 = 1e10;
+
+#[derive(Debug, Clone)]
 pub struct Camera {
     pub fov: glam::Vec2,
     pub center_uv: glam::Vec2,
     pub position: glam::Vec3,
     pub rotation: glam::Quat,
+    pub model: CameraModel,
+    /// Splats closer than this depth are not rendered.
+    pub near: f32,
+    /// Splats further than this depth are not rendered.
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::Vec2::ZERO,
+            glam::Vec2::ZERO,
+        )
+    }
 }
 
 impl Camera {
@@ -18,14 +52,32 @@ impl Camera {
             center_uv,
             position,
             rotation,
+            model: CameraModel::Pinhole,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
         }
     }
 
+    pub fn with_model(mut self, model: CameraModel) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.near = near;
+        self.far = far;
+        self
+    }
+
+    /// The focal length in pixels. For orthographic cameras, this is the number of pixels per world unit.
     pub fn focal(&self, img_size: glam::UVec2) -> glam::Vec2 {
-        glam::vec2(
-            fov_to_focal(self.fov.x, img_size.x),
-            fov_to_focal(self.fov.y, img_size.y),
-        )
+        match self.model {
+            CameraModel::Pinhole | CameraModel::Fisheye { .. } => glam::vec2(
+                fov_to_focal(self.fov.x, img_size.x),
+                fov_to_focal(self.fov.y, img_size.y),
+            ),
+            CameraModel::Orthographic { extent } => img_size.as_vec2() / extent,
+        }
     }
 
     pub fn center(&self, img_size: glam::UVec2) -> glam::Vec2 {
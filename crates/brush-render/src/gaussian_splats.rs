@@ -1,17 +1,21 @@
 use crate::{
-    bounding_box::BoundingBox, camera::Camera, render::sh_coeffs_for_degree,
-    safetensor_utils::safetensor_to_burn, shaders, Backend,
+    bounding_box::{BoundingBox, OrientedBox},
+    camera::{Camera, CameraModel},
+    render::{sh_coeffs_for_degree, sh_degree_from_coeffs},
+    safetensor_utils::safetensor_to_burn,
+    sh::sh_rotation_matrices,
+    shaders, Backend, RenderOptions,
 };
 use burn::{
     config::Config,
     module::{Module, Param, ParamId},
     tensor::activation::sigmoid,
-    tensor::{Device, Shape, Tensor},
+    tensor::{Bool, Device, Int, Shape, Tensor},
 };
-use glam::Vec3;
+use glam::{Affine3A, Vec3};
 use kiddo::{KdTree, SquaredEuclidean};
 use rand::Rng;
-use safetensors::SafeTensors;
+use safetensors::{tensor::TensorView, Dtype, SafeTensors};
 
 #[derive(Config)]
 pub struct RandomSplatsConfig {
@@ -21,6 +25,125 @@ pub struct RandomSplatsConfig {
     sh_degree: u32,
 }
 
+/// A set of splats to merge with [`merge_splats`].
+pub struct MergePart<B: Backend> {
+    pub splats: Splats<B>,
+    /// Transform from the space of these splats to the merged space.
+    pub transform: Affine3A,
+    /// Only keep splats with their center inside this box. The box is in the merged
+    /// space, so overlapping sections can be cut off where they meet.
+    pub crop: Option<OrientedBox>,
+}
+
+impl<B: Backend> MergePart<B> {
+    pub fn new(splats: Splats<B>) -> Self {
+        Self {
+            splats,
+            transform: Affine3A::IDENTITY,
+            crop: None,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Affine3A) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_crop(mut self, crop: OrientedBox) -> Self {
+        self.crop = Some(crop);
+        self
+    }
+}
+
+/// Merges multiple sets of splats into one.
+///
+/// Each part is first transformed and cropped. Parts with a lower SH degree are padded
+/// with zero coefficients up to the highest SH degree of all parts. The 3D filter and
+/// flags are kept if any part has them.
+pub async fn merge_splats<B: Backend>(parts: Vec<MergePart<B>>) -> anyhow::Result<Splats<B>> {
+    let max_coeffs = parts
+        .iter()
+        .map(|p| p.splats.sh_coeffs.dims()[1])
+        .max()
+        .ok_or_else(|| anyhow::anyhow!("No splats to merge"))?;
+
+    let mut merged = vec![];
+
+    for part in parts {
+        let mut splats = part.splats;
+        splats.transform(part.transform);
+
+        if let Some(crop) = &part.crop {
+            let keep_inds = splats.inside_mask(crop).argwhere_async().await;
+            if keep_inds.dims()[0] == 0 {
+                continue;
+            }
+            if keep_inds.dims()[0] < splats.num_splats() {
+                splats = splats.select(keep_inds.squeeze(1));
+            }
+        }
+
+        let [n, coeffs, _] = splats.sh_coeffs.dims();
+        if coeffs < max_coeffs {
+            let device = splats.means.device();
+            Splats::map_param(&mut splats.sh_coeffs, |c| {
+                Tensor::cat(
+                    vec![c, Tensor::zeros([n, max_coeffs - coeffs, 3], &device)],
+                    1,
+                )
+            });
+        }
+
+        merged.push(splats);
+    }
+
+    if merged.is_empty() {
+        anyhow::bail!("No splats left to merge after cropping.");
+    }
+
+    let device = merged[0].means.device();
+
+    // Parts without a 3D filter or flags get a zero filter, which doesn't blur, and no flags.
+    let filter_3d = merged.iter().any(|s| s.filter_3d.is_some()).then(|| {
+        Tensor::cat(
+            merged
+                .iter()
+                .map(|s| {
+                    s.filter_3d
+                        .clone()
+                        .unwrap_or_else(|| Tensor::zeros([s.num_splats()], &device))
+                })
+                .collect(),
+            0,
+        )
+    });
+    let flags = merged.iter().any(|s| s.flags.is_some()).then(|| {
+        Tensor::cat(
+            merged
+                .iter()
+                .map(|s| {
+                    s.flags
+                        .clone()
+                        .unwrap_or_else(|| Tensor::zeros([s.num_splats()], &device))
+                })
+                .collect(),
+            0,
+        )
+    });
+
+    let mut splats = Splats::from_data(
+        Tensor::cat(merged.iter().map(|s| s.means.val()).collect(), 0),
+        Tensor::cat(merged.iter().map(|s| s.sh_coeffs.val()).collect(), 0),
+        Tensor::cat(merged.iter().map(|s| s.rotation.val()).collect(), 0),
+        Tensor::cat(merged.iter().map(|s| s.raw_opacity.val()).collect(), 0),
+        Tensor::cat(merged.iter().map(|s| s.log_scales.val()).collect(), 0),
+        &device,
+    );
+    splats.filter_3d = filter_3d;
+    splats.flags = flags;
+    Ok(splats)
+}
+
 #[derive(Module, Debug)]
 pub struct Splats<B: Backend> {
     pub means: Param<Tensor<B, 2>>,
@@ -34,8 +157,26 @@ pub struct Splats<B: Backend> {
 
     // Dummy input to track screenspace gradient magnitude
     pub xys_norm_dummy: Tensor<B, 1>,
+
+    /// Optional flags per splat, see [`SPLAT_HIDDEN`], [`SPLAT_SELECTED`] and [`SPLAT_LOCKED`].
+    pub flags: Option<Tensor<B, 1, Int>>,
+
+    /// Optional size (std. dev.) of the Mip-Splatting 3D smoothing filter per splat, see
+    /// [`Splats::update_filter_3d`].
+    pub filter_3d: Option<Tensor<B, 1>>,
 }
 
+/// Variance of the 3D filter relative to the sample spacing of the training cameras, as
+/// used in Mip-Splatting.
+pub const FILTER_3D_VARIANCE: f32 = 0.2;
+
+/// Hidden splats are not rendered.
+pub const SPLAT_HIDDEN: u32 = shaders::helpers::SPLAT_HIDDEN;
+/// Selected splats are highlighted when rendering a display buffer.
+pub const SPLAT_SELECTED: u32 = shaders::helpers::SPLAT_SELECTED;
+/// Locked splats are rendered as normal, but training doesn't change, split or prune them.
+pub const SPLAT_LOCKED: u32 = shaders::helpers::SPLAT_LOCKED;
+
 pub fn inverse_sigmoid(x: f32) -> f32 {
     (x / (1.0 - x)).ln()
 }
@@ -158,6 +299,8 @@ impl<B: Backend> Splats<B> {
             log_scales: Param::initialized(ParamId::new(), log_scales.detach().require_grad()),
             xys_dummy: Tensor::zeros([num_points, 2], device).require_grad(),
             xys_norm_dummy: Tensor::zeros([num_points], device).require_grad(),
+            flags: None,
+            filter_3d: None,
         }
     }
 
@@ -174,6 +317,26 @@ impl<B: Backend> Splats<B> {
         img_size: glam::UVec2,
         bg_color: glam::Vec3,
         render_u32_buffer: bool,
+    ) -> (Tensor<B, 3>, crate::RenderAux) {
+        self.render_with(
+            camera,
+            img_size,
+            bg_color,
+            RenderOptions {
+                render_u32_buffer,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Render with extra settings, eg. to clip the splats or to get gradients for the
+    /// camera, see [`RenderOptions`].
+    pub fn render_with(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        bg_color: glam::Vec3,
+        options: RenderOptions<B>,
     ) -> (Tensor<B, 3>, crate::RenderAux) {
         B::render_splats(
             camera,
@@ -185,11 +348,189 @@ impl<B: Backend> Splats<B> {
             self.rotation.val(),
             self.sh_coeffs.val(),
             self.raw_opacity.val(),
+            self.flags.clone(),
+            self.filter_3d.clone(),
+            bg_color,
+            options,
+        )
+    }
+
+    /// Render the splats from several cameras at once, as [N, H, W, C]. This is faster than
+    /// rendering them one by one, see [`crate::Backend::render_splats_batch`].
+    pub fn render_batch(
+        &self,
+        cameras: &[Camera],
+        img_size: glam::UVec2,
+        bg_color: glam::Vec3,
+        options: RenderOptions<B>,
+    ) -> (Tensor<B, 4>, crate::RenderAux) {
+        B::render_splats_batch(
+            cameras,
+            img_size,
+            self.means.val(),
+            self.xys_dummy.clone(),
+            self.xys_norm_dummy.clone(),
+            self.log_scales.val(),
+            self.rotation.val(),
+            self.sh_coeffs.val(),
+            self.raw_opacity.val(),
+            self.flags.clone(),
+            self.filter_3d.clone(),
             bg_color,
-            render_u32_buffer,
+            options,
         )
     }
 
+    /// Set the flags of all splats, as a combination of [`SPLAT_HIDDEN`], [`SPLAT_SELECTED`]
+    /// and [`SPLAT_LOCKED`].
+    pub fn set_flags(&mut self, flags: &[u32]) {
+        assert_eq!(flags.len(), self.num_splats(), "Need flags for each splat");
+        let flags: Vec<i32> = flags.iter().map(|&f| f as i32).collect();
+        self.flags = Some(Tensor::from_ints(flags.as_slice(), &self.means.device()));
+    }
+
+    /// Set or clear a flag for each splat, eg. to update a selection.
+    pub async fn set_flag(&mut self, flag: u32, mask: &[bool]) {
+        assert_eq!(
+            mask.len(),
+            self.num_splats(),
+            "Need a mask value for each splat"
+        );
+
+        let mut flags: Vec<u32> = match self.flags.clone() {
+            Some(flags) => flags
+                .into_data_async()
+                .await
+                .iter::<i32>()
+                .map(|f| f as u32)
+                .collect(),
+            None => vec![0; mask.len()],
+        };
+
+        for (f, &set) in flags.iter_mut().zip(mask) {
+            if set {
+                *f |= flag;
+            } else {
+                *f &= !flag;
+            }
+        }
+        self.set_flags(&flags);
+    }
+
+    pub fn clear_flags(&mut self) {
+        self.flags = None;
+    }
+
+    /// Which splats have [`SPLAT_LOCKED`] set, if there are any flags.
+    pub fn locked_mask(&self) -> Option<Tensor<B, 1, Bool>> {
+        self.flags.clone().map(|flags| {
+            flags
+                .div_scalar(SPLAT_LOCKED as i32)
+                .remainder_scalar(2)
+                .equal_elem(1)
+        })
+    }
+
+    /// Compute the Mip-Splatting 3D filter from the cameras the splats were trained with.
+    ///
+    /// For each splat this finds the highest sampling rate (focal length over depth) of all
+    /// cameras that see the splat. Detail finer than that can't be reconstructed, so each
+    /// splat is smoothed by a gaussian of the size of one sample. This prevents splats from
+    /// degenerating to tiny points which alias when viewed from further away.
+    pub fn update_filter_3d<'a>(
+        &mut self,
+        cameras: impl IntoIterator<Item = (&'a Camera, glam::UVec2)>,
+    ) {
+        let device = self.means.device();
+        let means = self.means.val().detach();
+        let n = self.num_splats();
+
+        let mut max_rate = Tensor::<B, 1>::zeros([n], &device);
+
+        for (camera, img_size) in cameras {
+            let world_to_local = camera.world_to_local();
+            // Means are row vectors, so multiply with the transposed rotation.
+            let rotation = glam::Mat3::from_mat4(world_to_local);
+            let rotation =
+                Tensor::<B, 1>::from_floats(rotation.to_cols_array(), &device).reshape([3, 3]);
+            let translation =
+                Tensor::<B, 1>::from_floats(world_to_local.w_axis.truncate().to_array(), &device)
+                    .reshape([1, 3]);
+            let p_view = means.clone().matmul(rotation) + translation;
+
+            let focal = camera.focal(img_size);
+            let center = camera.center(img_size);
+            let xy = p_view.clone().slice([0..n, 0..2]);
+            let z = p_view.clone().slice([0..n, 2..3]).squeeze(1);
+
+            let (depth, xy) = match camera.model {
+                // The orthographic sample spacing doesn't depend on depth.
+                CameraModel::Orthographic { .. } => (Tensor::ones([n], &device), xy),
+                // Approximate the fisheye by a pinhole camera, which is correct in the center.
+                CameraModel::Pinhole | CameraModel::Fisheye { .. } => {
+                    let depth = z.clone().clamp_min(camera.near);
+                    (depth.clone(), xy / depth.unsqueeze_dim(1))
+                }
+            };
+
+            let x = xy.clone().slice([0..n, 0..1]).squeeze(1) * focal.x + center.x;
+            let y = xy.slice([0..n, 1..2]).squeeze(1) * focal.y + center.y;
+
+            // Include splats just outside of the image, they still contribute to the edges.
+            let margin = img_size.as_vec2() * 0.15;
+            let visible = Tensor::stack::<2>(
+                vec![
+                    z.clone().greater_elem(camera.near),
+                    z.lower_elem(camera.far),
+                    x.clone().greater_equal_elem(-margin.x),
+                    x.lower_equal_elem(img_size.x as f32 + margin.x),
+                    y.clone().greater_equal_elem(-margin.y),
+                    y.lower_equal_elem(img_size.y as f32 + margin.y),
+                ],
+                1,
+            )
+            .all_dim(1)
+            .squeeze(1);
+
+            let rate = depth.recip() * focal.max_element();
+            max_rate = max_rate.max_pair(rate.mask_fill(visible.bool_not(), 0.0));
+        }
+
+        // Splats not seen by any camera use the largest filter of all splats.
+        let unseen = max_rate.clone().equal_elem(0.0);
+        let filter = max_rate.clamp_min(1e-12).recip() * FILTER_3D_VARIANCE.sqrt();
+        let max_filter = filter
+            .clone()
+            .mask_fill(unseen.clone(), 0.0)
+            .max()
+            .repeat_dim(0, n);
+        self.filter_3d = Some(filter.mask_where(unseen, max_filter));
+    }
+
+    /// Fold the 3D filter into the scales and opacities, so the splats render the same
+    /// without a filter. This is useful when exporting to formats that don't know about
+    /// the filter.
+    pub fn bake_filter_3d(&mut self) {
+        let Some(filter) = self.filter_3d.take() else {
+            return;
+        };
+        let filter_sqr = filter.powf_scalar(2.0).unsqueeze_dim(1);
+        let scale_sqr = self.log_scales.val().mul_scalar(2.0).exp();
+        let filtered_sqr = scale_sqr.clone() + filter_sqr;
+
+        // Same as filter_compensation in the shaders.
+        let compensation = (scale_sqr / filtered_sqr.clone())
+            .prod_dim(1)
+            .sqrt()
+            .squeeze(1);
+        let opacity = (self.opacity() * compensation).clamp(1e-6, 1.0 - 1e-6);
+
+        Self::map_param(&mut self.log_scales, |_| filtered_sqr.clone().log() * 0.5);
+        Self::map_param(&mut self.raw_opacity, |_| {
+            (opacity.clone() / (-opacity.clone() + 1.0)).log()
+        });
+    }
+
     pub fn opacity(&self) -> Tensor<B, 1> {
         sigmoid(self.raw_opacity.val())
     }
@@ -208,6 +549,107 @@ impl<B: Backend> Splats<B> {
         });
     }
 
+    /// Moves, rotates and scales all splats by the given transform.
+    ///
+    /// This also rotates the SH coefficients, so view dependent effects move along. Splats
+    /// can't represent a non-uniform scale or shear of their shape, for those the splats
+    /// are scaled by the average (geometric mean) scale instead.
+    pub fn transform(&mut self, transform: Affine3A) {
+        let device = self.means.device();
+        let (scale, rotation, _) = transform.to_scale_rotation_translation();
+
+        // Means are row vectors, so multiply with the transposed matrix, which is
+        // what a column major array read back as row major gives.
+        let linear = glam::Mat3::from(transform.matrix3);
+        let linear = Tensor::<B, 1>::from_floats(linear.to_cols_array(), &device).reshape([3, 3]);
+        let translation =
+            Tensor::<B, 1>::from_floats(transform.translation.to_array(), &device).reshape([1, 3]);
+        Self::map_param(&mut self.means, |m| {
+            m.matmul(linear.clone()) + translation.clone()
+        });
+
+        // Left multiplication by the rotation quaternion, for quaternions stored as [w, x, y, z].
+        let [x, y, z, w] = rotation.to_array();
+        let quat_mul = Tensor::<B, 1>::from_floats(
+            [
+                w, -x, -y, -z, //
+                x, w, -z, y, //
+                y, z, w, -x, //
+                z, -y, x, w,
+            ],
+            &device,
+        )
+        .reshape([4, 4])
+        .transpose();
+        Self::map_param(&mut self.rotation, |r| r.matmul(quat_mul.clone()));
+
+        let log_scale = (scale.x * scale.y * scale.z).abs().ln() / 3.0;
+        Self::map_param(&mut self.log_scales, |s| s + log_scale);
+        self.filter_3d = self.filter_3d.take().map(|f| f * log_scale.exp());
+
+        let [n, num_coeffs, _] = self.sh_coeffs.dims();
+        let degree = sh_degree_from_coeffs(num_coeffs as u32);
+        if degree > 0 {
+            let rotations = sh_rotation_matrices(glam::Mat3::from_quat(rotation), degree);
+
+            Self::map_param(&mut self.sh_coeffs, |coeffs| {
+                let bands = rotations
+                    .iter()
+                    .enumerate()
+                    .map(|(l, rot)| {
+                        let start = l * l;
+                        let band_size = 2 * l + 1;
+                        let rot = Tensor::<B, 1>::from_floats(rot.as_slice(), &device)
+                            .reshape([band_size, band_size]);
+
+                        // [n, band, 3] -> [n * 3, band], rotate, and back.
+                        coeffs
+                            .clone()
+                            .slice([0..n, start..start + band_size, 0..3])
+                            .swap_dims(1, 2)
+                            .reshape([n * 3, band_size])
+                            .matmul(rot.transpose())
+                            .reshape([n, 3, band_size])
+                            .swap_dims(1, 2)
+                    })
+                    .collect();
+                Tensor::cat(bands, 1)
+            });
+        }
+    }
+
+    /// Returns a mask of the splats that have their center inside the given box.
+    pub fn inside_mask(&self, bounds: &OrientedBox) -> Tensor<B, 1, Bool> {
+        let device = self.means.device();
+        // Row vectors, so multiply by the transposed rotation.
+        let to_local = glam::Mat3::from_quat(bounds.rotation.inverse());
+        let to_local =
+            Tensor::<B, 1>::from_floats(to_local.to_cols_array(), &device).reshape([3, 3]);
+        let center = Tensor::<B, 1>::from_floats(bounds.center.to_array(), &device).reshape([1, 3]);
+        let extent = Tensor::<B, 1>::from_floats(bounds.extent.to_array(), &device).reshape([1, 3]);
+
+        let local = (self.means.val() - center).matmul(to_local);
+        (local.abs() / extent)
+            .lower_equal_elem(1.0)
+            .all_dim(1)
+            .squeeze(1)
+    }
+
+    /// Creates a new set of splats, containing only the splats at the given indices.
+    pub fn select(&self, indices: Tensor<B, 1, Int>) -> Self {
+        let mut selected = Self::from_data(
+            self.means.val().select(0, indices.clone()),
+            self.sh_coeffs.val().select(0, indices.clone()),
+            self.rotation.val().select(0, indices.clone()),
+            self.raw_opacity.val().select(0, indices.clone()),
+            self.log_scales.val().select(0, indices.clone()),
+            &self.means.device(),
+        );
+        selected.flags = self.flags.clone().map(|f| f.select(0, indices.clone()));
+        selected.filter_3d = self.filter_3d.clone().map(|f| f.select(0, indices));
+        selected
+    }
+
     pub fn from_safetensors(tensors: &SafeTensors, device: &B::Device) -> anyhow::Result<Self> {
         let means = safetensor_to_burn::<B, 2>(tensors.tensor("means")?, device);
         let log_scales = safetensor_to_burn::<B, 2>(tensors.tensor("scales")?, device);
@@ -215,13 +657,36 @@ impl<B: Backend> Splats<B> {
         let quats = safetensor_to_burn::<B, 2>(tensors.tensor("quats")?, device);
         let raw_opacity = safetensor_to_burn::<B, 1>(tensors.tensor("opacities")?, device);
 
-        Ok(Self::from_data(
-            means,
-            sh_coeffs,
-            quats,
-            raw_opacity,
-            log_scales,
-            device,
-        ))
+        let mut splats = Self::from_data(means, sh_coeffs, quats, raw_opacity, log_scales, device);
+        // Older files don't have a 3D filter.
+        splats.filter_3d = tensors
+            .tensor("filter_3d")
+            .ok()
+            .map(|t| safetensor_to_burn::<B, 1>(t, device));
+        Ok(splats)
+    }
+
+    pub async fn to_safetensors(&self) -> anyhow::Result<Vec<u8>> {
+        let mut tensors = vec![
+            ("means", self.means.val().into_data_async().await),
+            ("scales", self.log_scales.val().into_data_async().await),
+            ("coeffs", self.sh_coeffs.val().into_data_async().await),
+            ("quats", self.rotation.val().into_data_async().await),
+            ("opacities", self.raw_opacity.val().into_data_async().await),
+        ];
+
+        if let Some(filter_3d) = &self.filter_3d {
+            tensors.push(("filter_3d", filter_3d.clone().into_data_async().await));
+        }
+
+        let views = tensors
+            .iter()
+            .map(|(name, data)| {
+                let view = TensorView::new(Dtype::F32, data.shape.clone(), &data.bytes)?;
+                Ok((*name, view))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(safetensors::serialize(views, &None)?)
     }
 }
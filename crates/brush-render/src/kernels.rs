@@ -1,15 +1,17 @@
 use super::shaders::{
     get_tile_bin_edges, map_gaussian_to_intersects, project_backwards, project_forward,
-    project_visible, rasterize, rasterize_backwards,
+    project_visible, rasterize, rasterize_backwards, rasterize_features, sum_isect_grads,
 };
 use crate::shaders::gather_grads;
 use brush_kernel::kernel_source_gen;
 
 kernel_source_gen!(ProjectSplats {}, project_forward);
-kernel_source_gen!(ProjectVisible {}, project_visible);
+kernel_source_gen!(ProjectVisible { resort }, project_visible);
 kernel_source_gen!(MapGaussiansToIntersect {}, map_gaussian_to_intersects);
 kernel_source_gen!(GetTileBinEdges {}, get_tile_bin_edges);
-kernel_source_gen!(Rasterize { raster_u32 }, rasterize);
-kernel_source_gen!(RasterizeBackwards { hard_float }, rasterize_backwards);
+kernel_source_gen!(Rasterize { raster_u32, resort }, rasterize);
+kernel_source_gen!(RasterizeBackwards { hard_float, deterministic, resort }, rasterize_backwards);
+kernel_source_gen!(RasterizeFeatures { backward, hard_float }, rasterize_features);
 kernel_source_gen!(GatherGrads {}, gather_grads);
 kernel_source_gen!(ProjectBackwards {}, project_backwards);
+kernel_source_gen!(SumIsectGrads {}, sum_isect_grads);
@@ -1,8 +1,9 @@
 #![allow(clippy::too_many_arguments)]
 #![allow(clippy::single_range_in_vec_init)]
+use bounding_box::ClipVolume;
 use brush_kernel::bitcast_tensor;
 use burn::backend::Autodiff;
-use burn::tensor::{ElementConversion, Int, Tensor};
+use burn::tensor::{ElementConversion, Int, Tensor, TensorPrimitive};
 use burn_jit::JitBackend;
 use burn_wgpu::{JitTensor, WgpuRuntime};
 use camera::Camera;
@@ -15,19 +16,39 @@ mod shaders;
 pub mod bounding_box;
 pub mod camera;
 pub mod gaussian_splats;
+pub mod panorama;
 pub mod render;
+pub mod render_cpu;
+pub mod sh;
 
 #[derive(Debug, Clone)]
 pub struct RenderAux {
     pub uniforms_buffer: JitTensor<WgpuRuntime, u32>,
+    /// The view and intrinsics of each rendered camera.
+    pub cameras_buffer: JitTensor<WgpuRuntime, u32>,
     pub projected_splats: JitTensor<WgpuRuntime, f32>,
     pub num_intersections: JitTensor<WgpuRuntime, u32>,
+    pub intersection_capacity: u32,
     pub num_visible: JitTensor<WgpuRuntime, u32>,
     pub final_index: JitTensor<WgpuRuntime, u32>,
     pub cum_tiles_hit: JitTensor<WgpuRuntime, u32>,
     pub tile_bins: JitTensor<WgpuRuntime, u32>,
     pub compact_gid_from_isect: JitTensor<WgpuRuntime, u32>,
     pub global_from_compact_gid: JitTensor<WgpuRuntime, u32>,
+    /// Per splat data to sort the splats per pixel, only set when resorting.
+    pub ray_depths: Option<JitTensor<WgpuRuntime, f32>>,
+}
+
+/// A splat contributing to a pixel, see [`RenderAux::pick_pixel`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickedSplat {
+    /// Index of the splat in the rendered splats.
+    pub global_gid: u32,
+    /// Opacity of the splat at the pixel.
+    pub alpha: f32,
+    /// Contribution to the final pixel color, which is the alpha times the transmittance
+    /// left by the splats in front of it.
+    pub weight: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -55,6 +76,141 @@ impl RenderAux {
         .elem()
     }
 
+    /// Whether there were more intersections than fit in the intersection buffers.
+    ///
+    /// This can only happen on wasm, where the buffers are sized by an estimate. When
+    /// it does, some splats are missing from the rendered image.
+    pub async fn read_intersections_truncated(&self) -> bool {
+        self.read_num_intersections().await > self.intersection_capacity
+    }
+
+    /// Find the splats contributing to a pixel, ordered front to back.
+    ///
+    /// This follows the rasterizer, so only includes splats that are blended, and stops
+    /// once the pixel is saturated. For resorted renders, the splats are in the order of the
+    /// per pixel queue. For batched renders, this picks from the first camera.
+    pub async fn pick_pixel(&self, pixel: glam::UVec2) -> Vec<PickedSplat> {
+        type B = JitBackend<WgpuRuntime, f32, i32>;
+
+        let bins = Tensor::<B, 3, Int>::from_primitive(bitcast_tensor(self.tile_bins.clone()));
+        let [ty, tx, _] = bins.dims();
+        let tile = pixel / shaders::helpers::TILE_WIDTH;
+        let (tile_x, tile_y) = (tile.x as usize, tile.y as usize);
+
+        if tile_x >= tx || tile_y >= ty {
+            return vec![];
+        }
+
+        let range = bins
+            .slice([tile_y..tile_y + 1, tile_x..tile_x + 1, 0..2])
+            .into_data_async()
+            .await
+            .to_vec::<i32>()
+            .expect("Failed to read tile bins");
+        let (start, end) = (range[0] as usize, range[1] as usize);
+
+        if start >= end {
+            return vec![];
+        }
+
+        let compact_gids = Tensor::<B, 1, Int>::from_primitive(bitcast_tensor(
+            self.compact_gid_from_isect.clone(),
+        ))
+        .slice([start..end]);
+        let global_gids = Tensor::<B, 1, Int>::from_primitive(bitcast_tensor(
+            self.global_from_compact_gid.clone(),
+        ))
+        .select(0, compact_gids.clone())
+        .into_data_async()
+        .await
+        .to_vec::<i32>()
+        .expect("Failed to read splat ids");
+        let projected =
+            Tensor::<B, 2>::from_primitive(TensorPrimitive::Float(self.projected_splats.clone()))
+                .select(0, compact_gids.clone())
+                .into_data_async()
+                .await
+                .to_vec::<f32>()
+                .expect("Failed to read projected splats");
+
+        let projected: &[shaders::helpers::ProjectedSplat] = bytemuck::cast_slice(&projected);
+        let pixel_coord = pixel.as_vec2() + 0.5;
+        let alpha_at = |splat: &shaders::helpers::ProjectedSplat| {
+            render_cpu::splat_alpha(
+                glam::vec2(splat.xy_x, splat.xy_y),
+                glam::vec3(splat.conic_x, splat.conic_y, splat.conic_z),
+                splat.color_a,
+                pixel_coord,
+            )
+        };
+
+        // Indices into the intersections of this tile, in the order they're blended.
+        let mut blend_order = vec![];
+
+        if let Some(ray_depths) = &self.ray_depths {
+            // When resorting, follow the per pixel queue of the rasterizer.
+            let ray_depths =
+                Tensor::<B, 2>::from_primitive(TensorPrimitive::Float(ray_depths.clone()))
+                    .select(0, compact_gids)
+                    .into_data_async()
+                    .await
+                    .to_vec::<f32>()
+                    .expect("Failed to read ray depths");
+            let ray_depths: &[shaders::helpers::SplatRayDepth] = bytemuck::cast_slice(&ray_depths);
+
+            let camera_size = std::mem::size_of::<shaders::helpers::CameraUniforms>()
+                / std::mem::size_of::<u32>();
+            let camera =
+                Tensor::<B, 1, Int>::from_primitive(bitcast_tensor(self.cameras_buffer.clone()))
+                    .slice([0..camera_size])
+                    .into_data_async()
+                    .await
+                    .to_vec::<i32>()
+                    .expect("Failed to read camera");
+            let camera: shaders::helpers::CameraUniforms =
+                bytemuck::pod_read_unaligned(bytemuck::cast_slice(&camera));
+            let (ray_origin, ray_dir) = render_cpu::pixel_ray(&camera, pixel_coord);
+
+            let mut queue = render_cpu::ResortQueue::default();
+            for (i, splat) in projected.iter().enumerate() {
+                if alpha_at(splat) == 0.0 {
+                    continue;
+                }
+                let depth = render_cpu::ray_max_depth(ray_origin, ray_dir, &ray_depths[i]);
+                blend_order.extend(queue.push(depth, i as u32));
+            }
+            blend_order.extend(queue.into_remaining());
+        } else {
+            blend_order.extend(0..projected.len() as u32);
+        }
+
+        let mut transmittance = 1.0;
+        let mut picked = vec![];
+
+        for i in blend_order {
+            let i = i as usize;
+            let alpha = alpha_at(&projected[i]);
+
+            if alpha == 0.0 {
+                continue;
+            }
+
+            let next_transmittance = transmittance * (1.0 - alpha);
+            if next_transmittance <= 1e-4 {
+                break;
+            }
+
+            picked.push(PickedSplat {
+                global_gid: global_gids[i] as u32,
+                alpha,
+                weight: alpha * transmittance,
+            });
+            transmittance = next_transmittance;
+        }
+
+        picked
+    }
+
     pub fn read_tile_depth(&self) -> Tensor<JitBackend<WgpuRuntime, f32, i32>, 2, Int> {
         let bins = Tensor::from_primitive(bitcast_tensor(self.tile_bins.clone()));
         let [ty, tx, _] = bins.dims();
@@ -62,6 +218,86 @@ impl RenderAux {
         let min = bins.clone().slice([0..ty, 0..tx, 0..1]).squeeze(2);
         max - min
     }
+
+    /// The number of intersections in the tile of each pixel, as [H, W].
+    pub fn read_tile_depth_per_pixel(&self) -> Tensor<JitBackend<WgpuRuntime, f32, i32>, 2, Int> {
+        let [width, height] = self.final_index.shape.dims();
+        tiles_to_pixels(self.read_tile_depth(), width, height)
+    }
+
+    /// The number of splats the rasterizer went through for each pixel, as [H, W].
+    ///
+    /// This counts from the front of the tile up to the last splat blended into the pixel,
+    /// so includes splats that were too transparent at the pixel. The final splat per pixel is
+    /// only recorded when not rendering a u32 buffer. Pixels without any blended splats can
+    /// read as one, mask them by the rendered alpha to be sure.
+    pub fn read_splats_per_pixel(&self) -> Tensor<JitBackend<WgpuRuntime, f32, i32>, 2, Int> {
+        type B = JitBackend<WgpuRuntime, f32, i32>;
+
+        let [width, height] = self.final_index.shape.dims();
+        let bins = Tensor::<B, 3, Int>::from_primitive(bitcast_tensor(self.tile_bins.clone()));
+        let [ty, tx, _] = bins.dims();
+        let start = bins.clone().slice([0..ty, 0..tx, 0..1]).squeeze(2);
+        let end = bins.slice([0..ty, 0..tx, 1..2]).squeeze(2);
+        let start = tiles_to_pixels(start, width, height);
+        let end = tiles_to_pixels(end, width, height);
+
+        let final_index =
+            Tensor::<B, 2, Int>::from_primitive(bitcast_tensor(self.final_index.clone()))
+                .reshape([height, width]);
+        let count = (final_index - start.clone() + 1).clamp_min(0);
+        count.mask_fill(end.lower_equal(start), 0)
+    }
+}
+
+// Expands a value per tile to a value per pixel.
+fn tiles_to_pixels(
+    tiles: Tensor<JitBackend<WgpuRuntime, f32, i32>, 2, Int>,
+    width: usize,
+    height: usize,
+) -> Tensor<JitBackend<WgpuRuntime, f32, i32>, 2, Int> {
+    let [ty, tx] = tiles.dims();
+    let tile_width = shaders::helpers::TILE_WIDTH as usize;
+    tiles
+        .reshape([ty, 1, tx, 1])
+        .repeat_dim(1, tile_width)
+        .repeat_dim(3, tile_width)
+        .reshape([ty * tile_width, tx * tile_width])
+        .slice([0..height, 0..width])
+}
+
+/// Tensors that carry gradients to the camera, see [`Backend::render_splats`]. The render
+/// itself always uses the [`Camera`], so these have to match it.
+#[derive(Debug, Clone, Default)]
+pub struct CameraGrads<B: Backend> {
+    /// The [4, 4] world to camera matrix, which receives gradients for the camera pose.
+    ///
+    /// Nb: This only includes the gradient through the projection of the splats. For SH
+    /// degrees above 0 the color also depends on the view direction, which isn't
+    /// differentiated, as for the splat means.
+    pub viewmat: Option<Tensor<B, 2>>,
+    /// The [focal.x, focal.y, center.x, center.y] in pixels, which receive gradients for
+    /// the intrinsics.
+    pub intrinsics: Option<Tensor<B, 1>>,
+}
+
+/// Optional settings for [`Backend::render_splats`]. The defaults render a regular float
+/// image of all splats.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions<'a, B: Backend> {
+    /// Splats outside of this volume are not rendered.
+    pub clip_volume: Option<&'a ClipVolume>,
+    /// Render a packed RGBA (8 bits per channel) buffer instead of floats. This is useful
+    /// when the results need to be displayed immediately.
+    pub render_u32_buffer: bool,
+    /// Make the render and its gradients independent of the order that GPU threads run in,
+    /// so they're bit identical between runs. This makes the backward pass slower.
+    /// It's ignored when resorting, as the resorted backward pass isn't deterministic.
+    pub deterministic: bool,
+    /// Re-sort the splats per pixel by the depth along its ray where they contribute most,
+    /// like StopThePop. This reduces popping as the view rotates, at some performance cost.
+    pub resort: bool,
+    pub camera_grads: CameraGrads<B>,
 }
 
 // Custom operations in Burn work by extending the backend with an extra func.
@@ -72,8 +308,10 @@ pub trait Backend: burn::tensor::backend::Backend {
     /// differentiable way.
     /// The arguments are all passed as raw tensors. See [`Splats`] for a convenient Module that wraps this fun
     /// The ['xy_dummy'] variable is only used to carry screenspace xy gradients.
-    /// This function can optionally render a "u32" buffer, which is a packed RGBA (8 bits per channel)
-    /// buffer. This is useful when the results need to be displayed immediatly.
+    /// The optional flags are a combination of the `SPLAT_` flags in [`gaussian_splats`] per splat.
+    /// The optional 3D filter is the size of the Mip-Splatting smoothing filter per splat, see
+    /// [`gaussian_splats::Splats::update_filter_3d`]. It's not differentiable.
+    /// See [`RenderOptions`] for the other settings.
     fn render_splats(
         cam: &Camera,
         img_size: glam::UVec2,
@@ -84,9 +322,53 @@ pub trait Backend: burn::tensor::backend::Backend {
         quats: Tensor<Self, 2>,
         sh_coeffs: Tensor<Self, 3>,
         raw_opacity: Tensor<Self, 1>,
+        flags: Option<Tensor<Self, 1, Int>>,
+        filter_3d: Option<Tensor<Self, 1>>,
         background: glam::Vec3,
-        render_u32_buffer: bool,
+        options: RenderOptions<Self>,
     ) -> (Tensor<Self, 3>, RenderAux);
+
+    /// Render splats from multiple cameras at once, to a [N, H, W, C] buffer.
+    ///
+    /// This works like [`Backend::render_splats`], but projects the splats for all cameras in one
+    /// dispatch, sorts them together, and rasterizes all images at once. This saves the
+    /// overhead of launching all kernels per camera. All cameras are rendered at the same
+    /// ['img_size']. The gradients of the splats are summed over the cameras, the cameras
+    /// themselves don't receive gradients, so any [`CameraGrads`] are ignored.
+    /// The returned [`RenderAux`] covers all cameras, so eg. counts the visible splats of every
+    /// camera. The per pixel readbacks on it only work for a single camera.
+    fn render_splats_batch(
+        cameras: &[Camera],
+        img_size: glam::UVec2,
+        means: Tensor<Self, 2>,
+        xy_grad_dummy: Tensor<Self, 2>,
+        xy_grad_norm_dummy: Tensor<Self, 1>,
+        log_scales: Tensor<Self, 2>,
+        quats: Tensor<Self, 2>,
+        sh_coeffs: Tensor<Self, 3>,
+        raw_opacity: Tensor<Self, 1>,
+        flags: Option<Tensor<Self, 1, Int>>,
+        filter_3d: Option<Tensor<Self, 1>>,
+        background: glam::Vec3,
+        options: RenderOptions<Self>,
+    ) -> (Tensor<Self, 4>, RenderAux);
+
+    /// Alpha composite an N dimensional feature per splat, eg. semantic logits or features
+    /// distilled from a 2D model.
+    ///
+    /// This re-uses the projection, sorting and tile binning of an earlier [`Backend::render_splats`]
+    /// call, so the features blend with the same weights as the colors. Features are [N, C], in the
+    /// same order as the rendered splats, and the output is [H, W, C].
+    ///
+    /// This is a separate pass meant for auxiliary outputs, not a differentiable part of the
+    /// render: gradients only flow to the features, the splats are treated as constant.
+    /// The features are blended in the global depth order, so this only accepts the aux of a
+    /// single camera render without resorting, and returns an error otherwise.
+    fn render_features(
+        aux: &RenderAux,
+        img_size: glam::UVec2,
+        features: Tensor<Self, 2>,
+    ) -> anyhow::Result<Tensor<Self, 3>>;
 }
 
 pub trait AutodiffBackend: burn::tensor::backend::AutodiffBackend + Backend {}
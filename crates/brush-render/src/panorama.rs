@@ -0,0 +1,230 @@
+use burn::tensor::{Int, Tensor, TensorData};
+use glam::{Quat, UVec2, Vec3};
+
+use crate::{
+    camera::{focal_to_fov, Camera},
+    gaussian_splats::Splats,
+    Backend,
+};
+
+// Rotations from the camera looking down +z, to each cube face.
+fn cube_faces() -> [Quat; 6] {
+    use std::f32::consts::FRAC_PI_2;
+    [
+        Quat::IDENTITY,
+        Quat::from_rotation_y(std::f32::consts::PI),
+        Quat::from_rotation_y(FRAC_PI_2),
+        Quat::from_rotation_y(-FRAC_PI_2),
+        Quat::from_rotation_x(-FRAC_PI_2),
+        Quat::from_rotation_x(FRAC_PI_2),
+    ]
+}
+
+/// The direction of an equirectangular pixel, in the local space of the panorama camera.
+///
+/// Longitude runs from -pi to pi over the width, with the center of the image looking down +z.
+/// Latitude runs from up (-y) to down (+y) over the height.
+pub fn equirect_dir(pixel: glam::Vec2, img_size: UVec2) -> Vec3 {
+    use std::f32::consts::{FRAC_PI_2, PI};
+    let lon = pixel.x / img_size.x as f32 * 2.0 * PI - PI;
+    let lat = FRAC_PI_2 - pixel.y / img_size.y as f32 * PI;
+    Vec3::new(lat.cos() * lon.sin(), -lat.sin(), lat.cos() * lon.cos())
+}
+
+/// Render a 360° panorama as an equirectangular image of [H, W, 4].
+///
+/// This renders the six faces of a cubemap around `position`, and resamples them to
+/// an equirectangular projection. The center of the image looks along `rotation * +z`.
+pub fn render_equirect<B: Backend>(
+    splats: &Splats<B>,
+    position: Vec3,
+    rotation: Quat,
+    img_size: UVec2,
+    background: Vec3,
+) -> Tensor<B, 3> {
+    let device = splats.means.device();
+
+    // Match the resolution at the equator.
+    let face_size = img_size.x.div_ceil(4).max(4);
+
+    // Render a slightly wider fov than 90°, such that the bilinear samples at the
+    // cube edges stay within the faces.
+    let focal = (face_size as f32 - 2.0) / 2.0;
+    let fov = focal_to_fov(focal, face_size);
+    let faces = cube_faces();
+
+    let renders = faces
+        .iter()
+        .map(|&face| {
+            let camera = Camera::new(
+                position,
+                rotation * face,
+                glam::vec2(fov, fov),
+                glam::vec2(0.5, 0.5),
+            );
+            let (img, _) = splats.render(&camera, UVec2::splat(face_size), background, false);
+            img
+        })
+        .collect();
+    let renders = Tensor::stack::<4>(renders, 0).reshape([6 * (face_size * face_size) as usize, 4]);
+
+    // Find the bilinear samples for each output pixel.
+    let num_pixels = (img_size.x * img_size.y) as usize;
+    let mut indices = vec![vec![0i32; num_pixels]; 4];
+    let mut weights = vec![vec![0.0f32; num_pixels]; 4];
+
+    let center = face_size as f32 / 2.0;
+
+    for y in 0..img_size.y {
+        for x in 0..img_size.x {
+            let pix_id = (x + y * img_size.x) as usize;
+            let dir = equirect_dir(glam::vec2(x as f32 + 0.5, y as f32 + 0.5), img_size);
+
+            // Pick the face this direction is most aligned with.
+            let (face_id, local) = faces
+                .iter()
+                .map(|face| face.inverse() * dir)
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.z.total_cmp(&b.z))
+                .expect("Cube has faces");
+
+            let pixel = local.truncate() / local.z * focal + center - 0.5;
+            let max = face_size as f32 - 1.0;
+            let pixel = pixel.clamp(glam::Vec2::ZERO, glam::Vec2::splat(max));
+
+            let p0 = pixel.floor();
+            let frac = pixel - p0;
+            let p0 = p0.as_uvec2();
+            let p1 = (p0 + 1).min(UVec2::splat(face_size - 1));
+
+            let corners = [
+                (p0.x, p0.y, (1.0 - frac.x) * (1.0 - frac.y)),
+                (p1.x, p0.y, frac.x * (1.0 - frac.y)),
+                (p0.x, p1.y, (1.0 - frac.x) * frac.y),
+                (p1.x, p1.y, frac.x * frac.y),
+            ];
+
+            for (i, (cx, cy, w)) in corners.into_iter().enumerate() {
+                let face_offset = face_id as u32 * face_size * face_size;
+                indices[i][pix_id] = (face_offset + cx + cy * face_size) as i32;
+                weights[i][pix_id] = w;
+            }
+        }
+    }
+
+    let out = indices
+        .into_iter()
+        .zip(weights)
+        .map(|(indices, weights)| {
+            let indices =
+                Tensor::<B, 1, Int>::from_data(TensorData::new(indices, [num_pixels]), &device);
+            let weights =
+                Tensor::<B, 1>::from_data(TensorData::new(weights, [num_pixels]), &device);
+            renders.clone().select(0, indices) * weights.unsqueeze_dim(1)
+        })
+        .reduce(|a, b| a + b)
+        .expect("Has samples");
+
+    out.reshape([img_size.y as usize, img_size.x as usize, 4])
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use crate::PrimaryBackend;
+    use burn_wgpu::WgpuDevice;
+
+    // A single gray splat at the given position.
+    fn single_splat(position: Vec3, device: &WgpuDevice) -> Splats<PrimaryBackend> {
+        Splats::from_data(
+            Tensor::<PrimaryBackend, 1>::from_floats(position.to_array(), device).reshape([1, 3]),
+            Tensor::zeros([1, 1, 3], device),
+            Tensor::<PrimaryBackend, 1>::from_floats(Quat::IDENTITY.to_array(), device)
+                .reshape([1, 4]),
+            Tensor::from_floats([2.0], device),
+            Tensor::ones([1, 3], device) * 0.6f32.ln(),
+            device,
+        )
+    }
+
+    fn render_pixels(splats: &Splats<PrimaryBackend>, rotation: Quat, size: UVec2) -> Vec<f32> {
+        render_equirect(splats, Vec3::ZERO, rotation, size, Vec3::ZERO)
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap()
+    }
+
+    #[test]
+    fn splat_ahead_is_at_center() {
+        let device = WgpuDevice::BestAvailable;
+        let size = glam::uvec2(256, 128);
+        let splats = single_splat(glam::vec3(0.0, 0.0, 5.0), &device);
+        let pixels = render_pixels(&splats, Quat::IDENTITY, size);
+
+        let brightest = (0..(size.x * size.y) as usize)
+            .max_by(|&a, &b| pixels[a * 4 + 3].total_cmp(&pixels[b * 4 + 3]))
+            .unwrap() as u32;
+        let (x, y) = (brightest % size.x, brightest / size.x);
+        assert!(pixels[brightest as usize * 4 + 3] > 0.5);
+        assert!(x.abs_diff(size.x / 2) <= 1 && y.abs_diff(size.y / 2) <= 1);
+    }
+
+    #[test]
+    fn face_borders_have_no_seams() {
+        let device = WgpuDevice::BestAvailable;
+        let size = glam::uvec2(256, 128);
+        let yaw = std::f32::consts::FRAC_PI_4;
+
+        // A splat on the border of the front and right faces, and the same splat rendered
+        // with the panorama turned towards it, so it's in the middle of the front face.
+        let splats = single_splat(Quat::from_rotation_y(yaw) * Vec3::Z * 5.0, &device);
+        let border = render_pixels(&splats, Quat::IDENTITY, size);
+        let center = render_pixels(&splats, Quat::from_rotation_y(yaw), size);
+
+        // Turning the panorama by 45° shifts the image by an eighth of its width.
+        let shift = size.x / 8;
+        let mut max_diff = 0.0f32;
+        for y in 0..size.y {
+            for x in 0..size.x - shift {
+                for c in 0..4 {
+                    let a = border[((y * size.x + x + shift) * 4 + c) as usize];
+                    let b = center[((y * size.x + x) * 4 + c) as usize];
+                    max_diff = max_diff.max((a - b).abs());
+                }
+            }
+        }
+        assert!(border.iter().skip(3).step_by(4).any(|&a| a > 0.5));
+        assert!(
+            max_diff < 0.05,
+            "Splat on a face border differs by {max_diff}"
+        );
+    }
+
+    #[test]
+    fn equirect_center_looks_forward() {
+        let size = glam::uvec2(64, 32);
+        let dir = equirect_dir(glam::vec2(32.0, 16.0), size);
+        assert!((dir - Vec3::Z).length() < 1e-5);
+
+        let up = equirect_dir(glam::vec2(32.0, 0.0), size);
+        assert!((up - Vec3::NEG_Y).length() < 1e-5);
+
+        let right = equirect_dir(glam::vec2(48.0, 16.0), size);
+        assert!((right - Vec3::X).length() < 1e-5);
+    }
+
+    #[test]
+    fn cube_faces_cover_axes() {
+        let dirs: Vec<Vec3> = cube_faces().iter().map(|f| *f * Vec3::Z).collect();
+        for axis in [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ] {
+            assert!(dirs.iter().any(|d| (*d - axis).length() < 1e-5));
+        }
+    }
+}
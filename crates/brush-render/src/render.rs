@@ -1,20 +1,21 @@
-use super::{shaders, Backend, RenderAux};
+use super::{shaders, Backend, CameraGrads, RenderAux, RenderOptions};
 
 use std::mem::{offset_of, size_of};
 
 use crate::{
-    camera::Camera,
+    bounding_box::ClipVolume,
+    camera::{Camera, CameraModel},
     dim_check::DimCheck,
     kernels::{
         GatherGrads, GetTileBinEdges, MapGaussiansToIntersect, ProjectBackwards, ProjectSplats,
-        ProjectVisible, Rasterize, RasterizeBackwards,
+        ProjectVisible, Rasterize, RasterizeBackwards, RasterizeFeatures, SumIsectGrads,
     },
     PrimaryBackend,
 };
 
 use brush_kernel::{
-    bitcast_tensor, calc_cube_count, create_dispatch_buffer, create_tensor, create_uniform_buffer,
-    CubeCount,
+    bitcast_tensor, calc_cube_count, create_dispatch_buffer, create_tensor, create_uniform_array,
+    create_uniform_buffer, CubeCount,
 };
 use brush_prefix_sum::prefix_sum;
 use brush_sort::radix_argsort;
@@ -29,7 +30,7 @@ use burn::backend::{
 };
 use burn::tensor::ops::IntTensorOps;
 use burn::tensor::ops::{FloatTensor, FloatTensorOps};
-use burn::tensor::{Tensor, TensorPrimitive};
+use burn::tensor::{ElementConversion, Int, Tensor, TensorData, TensorPrimitive};
 use burn_wgpu::{JitTensor, WgpuRuntime};
 use glam::uvec2;
 
@@ -50,17 +51,72 @@ pub fn sh_degree_from_coeffs(coeffs_per_channel: u32) -> u32 {
     }
 }
 
+fn camera_uniforms(camera: &Camera, img_size: glam::UVec2) -> shaders::helpers::CameraUniforms {
+    shaders::helpers::CameraUniforms {
+        viewmat: camera.world_to_local().to_cols_array_2d(),
+        focal: camera.focal(img_size).into(),
+        pixel_center: camera.center(img_size).into(),
+        distortion: match camera.model {
+            CameraModel::Fisheye { distortion } => distortion.into(),
+            _ => [0.0; 4],
+        },
+        camera_model: match camera.model {
+            CameraModel::Pinhole => shaders::helpers::CAMERA_PINHOLE,
+            CameraModel::Orthographic { .. } => shaders::helpers::CAMERA_ORTHOGRAPHIC,
+            CameraModel::Fisheye { .. } => shaders::helpers::CAMERA_FISHEYE,
+        },
+        near: camera.near,
+        far: camera.far,
+        pad_0: 0,
+    }
+}
+
+// With multiple cameras, gradients are written per camera, as [num_cameras * N, ...]. Sum them
+// per splat.
+fn sum_camera_grads<const D: usize>(
+    grads: JitTensor<WgpuRuntime, f32>,
+    num_cameras: usize,
+) -> JitTensor<WgpuRuntime, f32> {
+    if num_cameras == 1 {
+        return grads;
+    }
+
+    let grads = Tensor::<PrimaryBackend, D>::from_primitive(TensorPrimitive::Float(grads));
+    let mut dims = grads.dims();
+    dims[0] /= num_cameras;
+    let num_elements = grads.shape().num_elements();
+    grads
+        .reshape([num_cameras, num_elements / num_cameras])
+        .sum_dim(0)
+        .reshape(dims)
+        .into_primitive()
+        .tensor()
+}
+
+// Renders all cameras to a [num_cameras, H, W, C] image.
 fn render_forward(
-    camera: &Camera,
+    cameras: &[Camera],
     img_size: glam::UVec2,
     means: JitTensor<WgpuRuntime, f32>,
     log_scales: JitTensor<WgpuRuntime, f32>,
     quats: JitTensor<WgpuRuntime, f32>,
     sh_coeffs: JitTensor<WgpuRuntime, f32>,
     raw_opacities: JitTensor<WgpuRuntime, f32>,
+    flags: Option<JitTensor<WgpuRuntime, i32>>,
+    filter_3d: JitTensor<WgpuRuntime, f32>,
     background: glam::Vec3,
+    clip_volume: Option<&ClipVolume>,
     raster_u32: bool,
+    deterministic: bool,
+    resort: bool,
 ) -> (JitTensor<WgpuRuntime, f32>, RenderAux) {
+    // The resorted backward pass writes gradients per pixel, in whatever order threads run.
+    if deterministic && resort {
+        log::warn!("Deterministic rendering doesn't support resorting, ignoring deterministic.");
+    }
+    let deterministic = deterministic && !resort;
+    assert!(!cameras.is_empty(), "Need at least one camera to render");
+
     let device = &means.device.clone();
     let client = means.client.clone();
 
@@ -75,7 +131,8 @@ fn render_forward(
         .check_dims(&log_scales, &["D".into(), 3.into()])
         .check_dims(&quats, &["D".into(), 4.into()])
         .check_dims(&sh_coeffs, &["D".into(), "C".into(), 3.into()])
-        .check_dims(&raw_opacities, &["D".into()]);
+        .check_dims(&raw_opacities, &["D".into()])
+        .check_dims(&filter_3d, &["D".into()]);
 
     // Divide screen into tiles.
     let tile_bounds = uvec2(
@@ -95,35 +152,60 @@ fn render_forward(
     // Then, various buffers map between these, which are named x_from_y_gid, eg.
     //  global_from_compact_gid.
 
+    // With multiple cameras, each splat is projected once per camera. The global gid is then
+    // camera * total_splats + splat, see helpers::camera_of_gid.
+
     // Tile rendering setup.
     let sh_degree = sh_degree_from_coeffs(sh_coeffs.shape.dims[1] as u32);
     let total_splats = means.shape.dims[0] as u32;
+    let num_cameras = cameras.len();
+    let clip_planes = clip_volume.map(|c| c.planes()).unwrap_or_default();
     let uniforms_buffer = create_uniform_buffer(
         shaders::helpers::RenderUniforms {
-            viewmat: camera.world_to_local().to_cols_array_2d(),
-            focal: camera.focal(img_size).into(),
-            pixel_center: camera.center(img_size).into(),
             img_size: img_size.into(),
             tile_bounds: tile_bounds.into(),
-            num_visible: 0,
             background: [background.x, background.y, background.z, 0.0],
             sh_degree,
+            num_visible: 0,
             total_splats,
-            padding: 0,
+            num_cameras: num_cameras as u32,
+            num_clip_planes: clip_planes.len() as u32,
+            has_flags: flags.is_some() as u32,
+            pad_0: 0,
+            pad_1: 0,
         },
         device,
         &client,
     );
+    let cameras: Vec<_> = cameras
+        .iter()
+        .map(|c| camera_uniforms(c, img_size))
+        .collect();
+    let cameras_buffer = create_uniform_array(&cameras, device, &client);
 
     let device = &means.device.clone();
 
-    let num_points = means.shape.dims[0];
+    // Kernels always need a flags buffer bound, so use a dummy if there are none.
+    let flags = flags.unwrap_or_else(|| PrimaryBackend::int_zeros([1].into(), device));
+
+    let num_points = means.shape.dims[0] * num_cameras;
     let client = &means.client.clone();
 
     let (global_from_compact_gid, num_visible) = {
         let global_from_presort_gid = create_tensor([num_points], device, client);
         let depths = create_tensor::<f32, 1, _>([num_points], device, client);
 
+        // Storage buffers can't be empty, so always upload at least one plane.
+        let num_planes = clip_planes.len().max(1);
+        let clip_planes: Vec<f32> = clip_planes
+            .iter()
+            .flat_map(|p| p.to_array())
+            .chain(std::iter::repeat(0.0))
+            .take(num_planes * 4)
+            .collect();
+        let clip_planes =
+            PrimaryBackend::float_from_data(TensorData::new(clip_planes, [num_planes, 4]), device);
+
         tracing::trace_span!("ProjectSplats", sync_burn = true).in_scope(||
             // SAFETY: wgsl FFI, kernel checked to have no OOB.
             unsafe {
@@ -137,6 +219,10 @@ fn render_forward(
                     quats.clone().handle.binding(),
                     global_from_presort_gid.clone().handle.binding(),
                     depths.clone().handle.binding(),
+                    clip_planes.handle.binding(),
+                    flags.clone().handle.binding(),
+                    filter_3d.clone().handle.binding(),
+                    cameras_buffer.clone().handle.binding(),
                 ],
             );
         });
@@ -148,16 +234,26 @@ fn render_forward(
             &[num_vis_field_offset..num_vis_field_offset + 1],
         ));
 
-        let (_, global_from_compact_gid) = tracing::trace_span!("DepthSort", sync_burn = true)
-            .in_scope(|| {
-                // Interpret the depth as a u32. This is fine for a radix sort, as long as the depth > 0.0,
-                // which we know to be the case given how we cull splats.
+        // The visible splats are written in whatever order the threads ran. Sort them by their
+        // global id first, so that the depth sort always breaks ties in the same way.
+        let (global_from_presort_gid, depths) = if deterministic {
+            tracing::trace_span!("IdSort", sync_burn = true).in_scope(|| {
                 radix_argsort(
-                    bitcast_tensor(depths),
                     global_from_presort_gid,
+                    bitcast_tensor(depths),
                     num_visible.clone(),
-                    32,
+                    u32::BITS - (num_points as u32).leading_zeros(),
                 )
+            })
+        } else {
+            (global_from_presort_gid, bitcast_tensor(depths))
+        };
+
+        let (_, global_from_compact_gid) = tracing::trace_span!("DepthSort", sync_burn = true)
+            .in_scope(|| {
+                // Interpret the depth as a u32. This is fine for a radix sort, as long as the depth > 0.0,
+                // which we know to be the case given how we cull splats.
+                radix_argsort(depths, global_from_presort_gid, num_visible.clone(), 32)
             });
 
         (global_from_compact_gid, num_visible)
@@ -170,21 +266,34 @@ fn render_forward(
     let num_tiles_hit = bitcast_tensor(PrimaryBackend::int_zeros([num_points].into(), device));
     let num_vis_wg = create_dispatch_buffer(num_visible.clone(), [shaders::helpers::MAIN_WG, 1, 1]);
 
+    // To resort the splats per pixel, the rasterizer needs the inverse covariance of each splat.
+    let ray_depths = resort.then(|| {
+        let ray_depth_size = size_of::<shaders::helpers::SplatRayDepth>() / size_of::<f32>();
+        create_tensor::<f32, 2, _>([num_points, ray_depth_size], device, client)
+    });
+
     tracing::trace_span!("ProjectVisibile", sync_burn = true).in_scope(|| unsafe {
+        let mut handles = vec![
+            uniforms_buffer.clone().handle.binding(),
+            means.handle.binding(),
+            log_scales.handle.binding(),
+            quats.handle.binding(),
+            sh_coeffs.handle.binding(),
+            raw_opacities.handle.binding(),
+            global_from_compact_gid.handle.clone().binding(),
+            projected_splats.handle.clone().binding(),
+            num_tiles_hit.handle.clone().binding(),
+            filter_3d.handle.binding(),
+            cameras_buffer.clone().handle.binding(),
+        ];
+        if let Some(ray_depths) = &ray_depths {
+            handles.push(ray_depths.handle.clone().binding());
+        }
+
         client.execute_unchecked(
-            ProjectVisible::task(),
+            ProjectVisible::task(resort),
             CubeCount::Dynamic(num_vis_wg.clone().handle.binding()),
-            vec![
-                uniforms_buffer.clone().handle.binding(),
-                means.handle.binding(),
-                log_scales.handle.binding(),
-                quats.handle.binding(),
-                sh_coeffs.handle.binding(),
-                raw_opacities.handle.binding(),
-                global_from_compact_gid.handle.clone().binding(),
-                projected_splats.handle.clone().binding(),
-                num_tiles_hit.handle.clone().binding(),
-            ],
+            handles,
         );
     });
 
@@ -198,20 +307,50 @@ fn render_forward(
         &[num_points - 1..num_points],
     ));
 
-    let num_tiles = tile_bounds[0] * tile_bounds[1];
+    let num_tiles = tile_bounds[0] * tile_bounds[1] * num_cameras as u32;
+
+    // On wasm, we cannot do a sync readback at all.
+    // Instead, estimate a max number of intersects. All the kernels only handle the actual
+    // count of intersects, and spin up empty threads for the rest atm. In the future, could use indirect
+    // dispatch to avoid this.
+    let estimated_intersects = cfg!(target_family = "wasm").then(|| {
+        num_points
+            .saturating_mul((tile_bounds.x * tile_bounds.y) as usize)
+            .min(128 * 65535)
+    });
+    #[cfg(test)]
+    let estimated_intersects = TEST_INTERSECTION_CAPACITY.get().or(estimated_intersects);
+
+    // Size the intersection buffers.
+    let (max_intersects, num_intersections_clamped) = if let Some(max_intersects) =
+        estimated_intersects
+    {
+        // The estimate can be too small for dense scenes. Clamp the count so that the following
+        // kernels stay within the buffers, which drops the remaining intersections. This can be
+        // detected with [`RenderAux::read_intersections_truncated`].
+        let clamped = bitcast_tensor(PrimaryBackend::int_clamp_max(
+            bitcast_tensor(num_intersections.clone()),
+            max_intersects as i32,
+        ));
+        (max_intersects, clamped)
+    } else {
+        // Otherwise, read back the exact number of intersections, so the buffers are always
+        // exactly large enough. This costs a stall on every render: the CPU waits until the
+        // GPU has projected the splats and summed their tile counts, and only then submits
+        // the rest of the render, so the GPU idles while that is recorded.
+        let count = tracing::trace_span!("ReadNumIntersections", sync_burn = true).in_scope(|| {
+            Tensor::<PrimaryBackend, 1, Int>::from_primitive(bitcast_tensor(
+                num_intersections.clone(),
+            ))
+            .into_scalar()
+            .elem::<u32>()
+        });
+        // Always allocate at least one element, as empty buffers can't be bound.
+        ((count as usize).max(1), num_intersections.clone())
+    };
 
     // Each intersection maps to a gaussian.
     let (tile_bins, compact_gid_from_isect) = {
-        // On wasm, we cannot do a sync readback at all.
-        // Instead, can just estimate a max number of intersects. All the kernels only handle the actual
-        // cound of intersects, and spin up empty threads for the rest atm. In the future, could use indirect
-        // dispatch to avoid this.
-        // Estimating the max number of intersects can be a bad hack though... The worst case sceneario is so massive
-        // that it's easy to run out of memory... How do we actually properly deal with this :/
-        let max_intersects = num_points
-            .saturating_mul(num_tiles as usize)
-            .min(128 * 65535);
-
         let tile_id_from_isect = create_tensor::<u32, 1, _>([max_intersects], device, client);
         let compact_gid_from_isect = create_tensor::<u32, 1, _>([max_intersects], device, client);
 
@@ -225,6 +364,7 @@ fn render_forward(
                     cum_tiles_hit.handle.clone().binding(),
                     tile_id_from_isect.handle.clone().binding(),
                     compact_gid_from_isect.handle.clone().binding(),
+                    global_from_compact_gid.handle.clone().binding(),
                 ],
             );
         });
@@ -238,15 +378,21 @@ fn render_forward(
                 radix_argsort(
                     tile_id_from_isect,
                     compact_gid_from_isect,
-                    num_intersections.clone(),
+                    num_intersections_clamped.clone(),
                     bits,
                 )
             });
 
         let _span = tracing::trace_span!("GetTileBinEdges", sync_burn = true).entered();
 
+        // The tiles of each camera are stacked vertically.
         let tile_bins = bitcast_tensor(PrimaryBackend::int_zeros(
-            [tile_bounds.y as usize, tile_bounds.x as usize, 2].into(),
+            [
+                tile_bounds.y as usize * num_cameras,
+                tile_bounds.x as usize,
+                2,
+            ]
+            .into(),
             device,
         ));
         unsafe {
@@ -254,7 +400,7 @@ fn render_forward(
                 GetTileBinEdges::task(),
                 CubeCount::Dynamic(
                     create_dispatch_buffer(
-                        num_intersections.clone(),
+                        num_intersections_clamped.clone(),
                         GetTileBinEdges::WORKGROUP_SIZE,
                     )
                     .handle
@@ -262,7 +408,7 @@ fn render_forward(
                 ),
                 vec![
                     tile_id_from_isect.handle.clone().binding(),
-                    num_intersections.handle.clone().binding(),
+                    num_intersections_clamped.handle.clone().binding(),
                     tile_bins.handle.clone().binding(),
                 ],
             );
@@ -281,7 +427,12 @@ fn render_forward(
     };
 
     let out_img = create_tensor(
-        [img_size.y as usize, img_size.x as usize, out_dim],
+        [
+            num_cameras,
+            img_size.y as usize,
+            img_size.x as usize,
+            out_dim,
+        ],
         device,
         client,
     );
@@ -298,17 +449,32 @@ fn render_forward(
     ];
 
     // Record the final visible splat per tile.
-    let final_index =
-        create_tensor::<u32, 2, _>([img_size.x as usize, img_size.y as usize], device, client);
+    let final_index = create_tensor::<u32, 2, _>(
+        [img_size.x as usize, img_size.y as usize * num_cameras],
+        device,
+        client,
+    );
 
-    if !raster_u32 {
+    if raster_u32 {
+        // The display buffer tints selected splats.
+        handles.push(global_from_compact_gid.handle.clone().binding());
+        handles.push(flags.handle.binding());
+    } else {
         handles.push(final_index.handle.clone().binding());
     }
 
+    if let Some(ray_depths) = &ray_depths {
+        handles.push(ray_depths.handle.clone().binding());
+        handles.push(cameras_buffer.clone().handle.binding());
+    }
+
     unsafe {
         client.execute_unchecked(
-            Rasterize::task(raster_u32),
-            calc_cube_count([img_size.x, img_size.y], Rasterize::WORKGROUP_SIZE),
+            Rasterize::task(raster_u32, resort),
+            calc_cube_count(
+                [img_size.x, img_size.y, num_cameras as u32],
+                Rasterize::WORKGROUP_SIZE,
+            ),
             handles,
         );
     }
@@ -317,18 +483,122 @@ fn render_forward(
         out_img,
         RenderAux {
             uniforms_buffer,
+            cameras_buffer,
             num_visible,
             num_intersections,
+            intersection_capacity: max_intersects as u32,
             tile_bins,
             cum_tiles_hit,
             projected_splats,
             final_index,
             compact_gid_from_isect,
             global_from_compact_gid,
+            ray_depths,
         },
     )
 }
 
+// Lets tests size the intersection buffers like on wasm, with a fixed capacity.
+#[cfg(test)]
+thread_local! {
+    static TEST_INTERSECTION_CAPACITY: std::cell::Cell<Option<usize>> =
+        const { std::cell::Cell::new(None) };
+}
+
+fn render_features_forward(
+    aux: &RenderAux,
+    img_size: glam::UVec2,
+    features: JitTensor<WgpuRuntime, f32>,
+) -> anyhow::Result<JitTensor<WgpuRuntime, f32>> {
+    let device = &features.device.clone();
+    let client = features.client.clone();
+
+    let _span = tracing::trace_span!("RasterizeFeatures", sync_burn = true).entered();
+
+    anyhow::ensure!(
+        aux.ray_depths.is_none(),
+        "Features can't be blended in the per pixel order of a resorted render"
+    );
+    let camera_size = size_of::<shaders::helpers::CameraUniforms>() / size_of::<u32>();
+    anyhow::ensure!(
+        aux.cameras_buffer.shape.dims[0] == camera_size,
+        "Features can only be blended for a single camera render"
+    );
+
+    DimCheck::new().check_dims(&features, &["D".into(), "C".into()]);
+    let num_channels = features.shape.dims[1];
+
+    let feature_uniforms = create_uniform_buffer(
+        shaders::rasterize_features::FeatureUniforms {
+            num_channels: num_channels as u32,
+        },
+        device,
+        &client,
+    );
+
+    // The kernel accumulates into the output, so this has to be zeroed.
+    let out_features = PrimaryBackend::float_zeros(
+        [img_size.y as usize, img_size.x as usize, num_channels].into(),
+        device,
+    );
+
+    // SAFETY: wgsl FFI, kernel checked to have no OOB.
+    unsafe {
+        client.execute_unchecked(
+            RasterizeFeatures::task(false, false),
+            calc_cube_count([img_size.x, img_size.y], RasterizeFeatures::WORKGROUP_SIZE),
+            vec![
+                aux.uniforms_buffer.clone().handle.binding(),
+                feature_uniforms.handle.binding(),
+                aux.compact_gid_from_isect.clone().handle.binding(),
+                aux.tile_bins.clone().handle.binding(),
+                aux.projected_splats.clone().handle.binding(),
+                aux.global_from_compact_gid.clone().handle.binding(),
+                features.handle.binding(),
+                out_features.handle.clone().binding(),
+            ],
+        );
+    }
+
+    out_features
+}
+
+fn render_splats_primary(
+    cameras: &[Camera],
+    img_size: glam::UVec2,
+    means: Tensor<PrimaryBackend, 2>,
+    log_scales: Tensor<PrimaryBackend, 2>,
+    quats: Tensor<PrimaryBackend, 2>,
+    sh_coeffs: Tensor<PrimaryBackend, 3>,
+    raw_opacity: Tensor<PrimaryBackend, 1>,
+    flags: Option<Tensor<PrimaryBackend, 1, Int>>,
+    filter_3d: Option<Tensor<PrimaryBackend, 1>>,
+    background: glam::Vec3,
+    options: RenderOptions<PrimaryBackend>,
+) -> (Tensor<PrimaryBackend, 4>, RenderAux) {
+    // Without a 3D filter, use a zero sized filter.
+    let filter_3d = filter_3d.unwrap_or_else(|| Tensor::zeros([means.dims()[0]], &means.device()));
+
+    let (out_img, aux) = render_forward(
+        cameras,
+        img_size,
+        means.into_primitive().tensor(),
+        log_scales.into_primitive().tensor(),
+        quats.into_primitive().tensor(),
+        sh_coeffs.into_primitive().tensor(),
+        raw_opacity.into_primitive().tensor(),
+        flags.map(|f| f.into_primitive()),
+        filter_3d.into_primitive().tensor(),
+        background,
+        options.clip_volume,
+        options.render_u32_buffer,
+        options.deterministic,
+        options.resort,
+    );
+
+    (Tensor::from_primitive(TensorPrimitive::Float(out_img)), aux)
+}
+
 impl Backend for PrimaryBackend {
     fn render_splats(
         camera: &Camera,
@@ -340,22 +610,64 @@ impl Backend for PrimaryBackend {
         quats: Tensor<Self, 2>,
         sh_coeffs: Tensor<Self, 3>,
         raw_opacity: Tensor<Self, 1>,
+        flags: Option<Tensor<Self, 1, Int>>,
+        filter_3d: Option<Tensor<Self, 1>>,
         background: glam::Vec3,
-        render_u32_buffer: bool,
+        options: RenderOptions<Self>,
     ) -> (Tensor<Self, 3>, RenderAux) {
-        let (out_img, aux) = render_forward(
-            camera,
+        let (out_img, aux) = render_splats_primary(
+            std::slice::from_ref(camera),
             img_size,
-            means.into_primitive().tensor(),
-            log_scales.into_primitive().tensor(),
-            quats.into_primitive().tensor(),
-            sh_coeffs.into_primitive().tensor(),
-            raw_opacity.into_primitive().tensor(),
+            means,
+            log_scales,
+            quats,
+            sh_coeffs,
+            raw_opacity,
+            flags,
+            filter_3d,
             background,
-            render_u32_buffer,
+            options,
         );
+        (out_img.squeeze(0), aux)
+    }
+
+    fn render_splats_batch(
+        cameras: &[Camera],
+        img_size: glam::UVec2,
+        means: Tensor<Self, 2>,
+        _xy_dummy: Tensor<Self, 2>,
+        _xy_norm_dummy: Tensor<Self, 1>,
+        log_scales: Tensor<Self, 2>,
+        quats: Tensor<Self, 2>,
+        sh_coeffs: Tensor<Self, 3>,
+        raw_opacity: Tensor<Self, 1>,
+        flags: Option<Tensor<Self, 1, Int>>,
+        filter_3d: Option<Tensor<Self, 1>>,
+        background: glam::Vec3,
+        options: RenderOptions<Self>,
+    ) -> (Tensor<Self, 4>, RenderAux) {
+        render_splats_primary(
+            cameras,
+            img_size,
+            means,
+            log_scales,
+            quats,
+            sh_coeffs,
+            raw_opacity,
+            flags,
+            filter_3d,
+            background,
+            options,
+        )
+    }
 
-        (Tensor::from_primitive(TensorPrimitive::Float(out_img)), aux)
+    fn render_features(
+        aux: &RenderAux,
+        img_size: glam::UVec2,
+        features: Tensor<Self, 2>,
+    ) -> anyhow::Result<Tensor<Self, 3>> {
+        let out = render_features_forward(aux, img_size, features.into_primitive().tensor())?;
+        Ok(Tensor::from_primitive(TensorPrimitive::Float(out)))
     }
 }
 
@@ -365,7 +677,9 @@ struct GaussianBackwardState {
     log_scales: NodeID,
     quats: NodeID,
     raw_opac: NodeID,
+    filter_3d: JitTensor<WgpuRuntime, f32>,
     sh_degree: u32,
+    deterministic: bool,
     out_img: JitTensor<WgpuRuntime, f32>,
     aux: RenderAux,
 }
@@ -373,6 +687,136 @@ struct GaussianBackwardState {
 #[derive(Debug)]
 struct RenderBackwards;
 
+#[derive(Debug, Clone)]
+struct FeatureBackwardState {
+    aux: RenderAux,
+    img_size: glam::UVec2,
+    num_points: usize,
+    num_channels: usize,
+}
+
+#[derive(Debug)]
+struct RenderFeaturesBackwards;
+
+fn render_splats_autodiff<C: CheckpointStrategy>(
+    cameras: &[Camera],
+    img_size: glam::UVec2,
+    means: Tensor<Autodiff<PrimaryBackend, C>, 2>,
+    xy_dummy: Tensor<Autodiff<PrimaryBackend, C>, 2>,
+    xy_norm_dummy: Tensor<Autodiff<PrimaryBackend, C>, 1>,
+    log_scales: Tensor<Autodiff<PrimaryBackend, C>, 2>,
+    quats: Tensor<Autodiff<PrimaryBackend, C>, 2>,
+    sh_coeffs: Tensor<Autodiff<PrimaryBackend, C>, 3>,
+    raw_opacity: Tensor<Autodiff<PrimaryBackend, C>, 1>,
+    flags: Option<Tensor<Autodiff<PrimaryBackend, C>, 1, Int>>,
+    filter_3d: Option<Tensor<Autodiff<PrimaryBackend, C>, 1>>,
+    background: glam::Vec3,
+    options: RenderOptions<Autodiff<PrimaryBackend, C>>,
+) -> (Tensor<Autodiff<PrimaryBackend, C>, 4>, RenderAux) {
+    // Resorting takes precedence, see render_forward.
+    let deterministic = options.deterministic && !options.resort;
+
+    // Without a view matrix or intrinsics, use untracked dummies so the number of inputs is fixed.
+    let viewmat = options
+        .camera_grads
+        .viewmat
+        .unwrap_or_else(|| Tensor::zeros([4, 4], &means.device()))
+        .into_primitive()
+        .tensor();
+    let intrinsics = options
+        .camera_grads
+        .intrinsics
+        .unwrap_or_else(|| Tensor::zeros([4], &means.device()))
+        .into_primitive()
+        .tensor();
+
+    // Get backend tensors & dequantize if needed. Could try and support quantized inputs
+    // in the future.
+    let means = means.into_primitive().tensor();
+    let xy_dummy = xy_dummy.into_primitive().tensor();
+    let xy_norm_dummy = xy_norm_dummy.into_primitive().tensor();
+    let log_scales = log_scales.into_primitive().tensor();
+    let quats = quats.into_primitive().tensor();
+    let sh_coeffs = sh_coeffs.into_primitive().tensor();
+    let raw_opacity = raw_opacity.into_primitive().tensor();
+
+    // The filter isn't differentiable, so can be passed as a plain tensor.
+    let filter_3d = filter_3d
+        .map(|f| f.into_primitive().tensor().into_primitive())
+        .unwrap_or_else(|| {
+            PrimaryBackend::float_zeros(
+                [means.primitive.shape.dims[0]].into(),
+                &means.primitive.device,
+            )
+        });
+
+    // Render complete forward pass.
+    let (out_img, aux) = render_forward(
+        cameras,
+        img_size,
+        means.clone().into_primitive(),
+        log_scales.clone().into_primitive(),
+        quats.clone().into_primitive(),
+        sh_coeffs.clone().into_primitive(),
+        raw_opacity.clone().into_primitive(),
+        flags.map(|f| f.into_primitive()),
+        filter_3d.clone(),
+        background,
+        options.clip_volume,
+        options.render_u32_buffer,
+        options.deterministic,
+        options.resort,
+    );
+
+    // Prepare backward pass, and check if we even need to do it. Store nodes that need gradients.
+    let prep_nodes = RenderBackwards
+        .prepare::<C>([
+            means.clone().node,
+            xy_dummy.clone().node,
+            xy_norm_dummy.clone().node,
+            log_scales.clone().node,
+            quats.clone().node,
+            sh_coeffs.clone().node,
+            raw_opacity.clone().node,
+            viewmat.node,
+            intrinsics.node,
+        ])
+        .compute_bound()
+        .stateful();
+
+    let sh_degree = sh_degree_from_coeffs(sh_coeffs.primitive.shape.dims[1] as u32);
+
+    match prep_nodes {
+        OpsKind::Tracked(mut prep) => {
+            // Save state needed for backward pass.
+            let state = GaussianBackwardState {
+                means: prep.checkpoint(&means),
+                log_scales: prep.checkpoint(&log_scales),
+                quats: prep.checkpoint(&quats),
+                raw_opac: prep.checkpoint(&raw_opacity),
+                filter_3d,
+                sh_degree,
+                deterministic,
+                aux: aux.clone(),
+                out_img: out_img.clone(),
+            };
+
+            (
+                Tensor::from_primitive(TensorPrimitive::Float(prep.finish(state, out_img))),
+                aux,
+            )
+        }
+        OpsKind::UnTracked(prep) => {
+            // When no node is tracked, we can just use the original operation without
+            // keeping any state.
+            (
+                Tensor::from_primitive(TensorPrimitive::Float(prep.finish(out_img))),
+                aux,
+            )
+        }
+    }
+}
+
 impl<C: CheckpointStrategy> Backend for Autodiff<PrimaryBackend, C> {
     fn render_splats(
         camera: &Camera,
@@ -384,84 +828,165 @@ impl<C: CheckpointStrategy> Backend for Autodiff<PrimaryBackend, C> {
         quats: Tensor<Self, 2>,
         sh_coeffs: Tensor<Self, 3>,
         raw_opacity: Tensor<Self, 1>,
+        flags: Option<Tensor<Self, 1, Int>>,
+        filter_3d: Option<Tensor<Self, 1>>,
         background: glam::Vec3,
-        render_u32_buffer: bool,
+        options: RenderOptions<Self>,
     ) -> (Tensor<Self, 3>, RenderAux) {
-        // Get backend tensors & dequantize if needed. Could try and support quantized inputs
-        // in the future.
-        let means = means.into_primitive().tensor();
-        let xy_dummy = xy_dummy.into_primitive().tensor();
-        let xy_norm_dummy = xy_norm_dummy.into_primitive().tensor();
-        let log_scales = log_scales.into_primitive().tensor();
-        let quats = quats.into_primitive().tensor();
-        let sh_coeffs = sh_coeffs.into_primitive().tensor();
-        let raw_opacity = raw_opacity.into_primitive().tensor();
-
-        // Render complete forward pass.
-        let (out_img, aux) = render_forward(
-            camera,
+        let (out_img, aux) = render_splats_autodiff(
+            std::slice::from_ref(camera),
+            img_size,
+            means,
+            xy_dummy,
+            xy_norm_dummy,
+            log_scales,
+            quats,
+            sh_coeffs,
+            raw_opacity,
+            flags,
+            filter_3d,
+            background,
+            options,
+        );
+        (out_img.squeeze(0), aux)
+    }
+
+    fn render_splats_batch(
+        cameras: &[Camera],
+        img_size: glam::UVec2,
+        means: Tensor<Self, 2>,
+        xy_dummy: Tensor<Self, 2>,
+        xy_norm_dummy: Tensor<Self, 1>,
+        log_scales: Tensor<Self, 2>,
+        quats: Tensor<Self, 2>,
+        sh_coeffs: Tensor<Self, 3>,
+        raw_opacity: Tensor<Self, 1>,
+        flags: Option<Tensor<Self, 1, Int>>,
+        filter_3d: Option<Tensor<Self, 1>>,
+        background: glam::Vec3,
+        mut options: RenderOptions<Self>,
+    ) -> (Tensor<Self, 4>, RenderAux) {
+        // The camera gradients are summed over all splats, which would mix up the cameras.
+        let grads = &options.camera_grads;
+        if grads.viewmat.is_some() || grads.intrinsics.is_some() {
+            log::warn!("Batched rendering doesn't support camera gradients, ignoring them.");
+            options.camera_grads = CameraGrads::default();
+        }
+        render_splats_autodiff(
+            cameras,
             img_size,
-            means.clone().into_primitive(),
-            log_scales.clone().into_primitive(),
-            quats.clone().into_primitive(),
-            sh_coeffs.clone().into_primitive(),
-            raw_opacity.clone().into_primitive(),
+            means,
+            xy_dummy,
+            xy_norm_dummy,
+            log_scales,
+            quats,
+            sh_coeffs,
+            raw_opacity,
+            flags,
+            filter_3d,
             background,
-            render_u32_buffer,
-        );
-
-        // Prepare backward pass, and check if we even need to do it. Store nodes that need gradients.
-        let prep_nodes = RenderBackwards
-            .prepare::<C>([
-                means.clone().node,
-                xy_dummy.clone().node,
-                xy_norm_dummy.clone().node,
-                log_scales.clone().node,
-                quats.clone().node,
-                sh_coeffs.clone().node,
-                raw_opacity.clone().node,
-            ])
+            options,
+        )
+    }
+
+    fn render_features(
+        aux: &RenderAux,
+        img_size: glam::UVec2,
+        features: Tensor<Self, 2>,
+    ) -> anyhow::Result<Tensor<Self, 3>> {
+        let features = features.into_primitive().tensor();
+        let num_points = features.primitive.shape.dims[0];
+        let num_channels = features.primitive.shape.dims[1];
+
+        let out = render_features_forward(aux, img_size, features.clone().into_primitive())?;
+
+        let prep_nodes = RenderFeaturesBackwards
+            .prepare::<C>([features.node])
             .compute_bound()
             .stateful();
 
-        let sh_degree = sh_degree_from_coeffs(sh_coeffs.primitive.shape.dims[1] as u32);
-
-        match prep_nodes {
-            OpsKind::Tracked(mut prep) => {
-                // Save state needed for backward pass.
-                let state = GaussianBackwardState {
-                    means: prep.checkpoint(&means),
-                    log_scales: prep.checkpoint(&log_scales),
-                    quats: prep.checkpoint(&quats),
-                    raw_opac: prep.checkpoint(&raw_opacity),
-                    sh_degree,
+        let out = match prep_nodes {
+            OpsKind::Tracked(prep) => {
+                let state = FeatureBackwardState {
                     aux: aux.clone(),
-                    out_img: out_img.clone(),
+                    img_size,
+                    num_points,
+                    num_channels,
                 };
-
-                (
-                    Tensor::from_primitive(TensorPrimitive::Float(prep.finish(state, out_img))),
-                    aux,
-                )
-            }
-            OpsKind::UnTracked(prep) => {
-                // When no node is tracked, we can just use the original operation without
-                // keeping any state.
-                (
-                    Tensor::from_primitive(TensorPrimitive::Float(prep.finish(out_img))),
-                    aux,
-                )
+                prep.finish(state, out)
             }
+            OpsKind::UnTracked(prep) => prep.finish(out),
+        };
+        Ok(Tensor::from_primitive(TensorPrimitive::Float(out)))
+    }
+}
+
+impl Backward<PrimaryBackend, 1> for RenderFeaturesBackwards {
+    type State = FeatureBackwardState;
+
+    fn backward(
+        self,
+        ops: Ops<Self::State, 1>,
+        grads: &mut Gradients,
+        _checkpointer: &mut Checkpointer,
+    ) {
+        let _span = tracing::trace_span!("render_features backwards").entered();
+
+        let state = ops.state;
+        let aux = state.aux;
+
+        let v_output = grads.consume::<PrimaryBackend>(&ops.node);
+        let client = &v_output.client;
+        let device = &v_output.device;
+
+        let feature_uniforms = create_uniform_buffer(
+            shaders::rasterize_features::FeatureUniforms {
+                num_channels: state.num_channels as u32,
+            },
+            device,
+            client,
+        );
+
+        // Gradients are atomically added to so important to zero them.
+        let v_features =
+            PrimaryBackend::float_zeros([state.num_points, state.num_channels].into(), device);
+
+        let hard_float = !cfg!(target_family = "wasm") && !cfg!(target_os = "android");
+
+        tracing::trace_span!("RasterizeFeaturesBackwards", sync_burn = true).in_scope(|| unsafe {
+            client.execute_unchecked(
+                RasterizeFeatures::task(true, hard_float),
+                calc_cube_count(
+                    [state.img_size.x, state.img_size.y],
+                    RasterizeFeatures::WORKGROUP_SIZE,
+                ),
+                vec![
+                    aux.uniforms_buffer.handle.binding(),
+                    feature_uniforms.handle.binding(),
+                    aux.compact_gid_from_isect.handle.binding(),
+                    aux.tile_bins.handle.binding(),
+                    aux.projected_splats.handle.binding(),
+                    aux.global_from_compact_gid.handle.binding(),
+                    v_output.handle.binding(),
+                    v_features.handle.clone().binding(),
+                ],
+            );
+        });
+
+        let [features_parent] = ops.parents;
+
+        if let Some(node) = features_parent {
+            grads.register::<PrimaryBackend>(node.id, v_features);
         }
     }
 }
 
-impl Backward<PrimaryBackend, 7> for RenderBackwards {
+impl Backward<PrimaryBackend, 9> for RenderBackwards {
     type State = GaussianBackwardState;
 
     fn backward(
         self,
-        ops: Ops<Self::State, 7>,
+        ops: Ops<Self::State, 9>,
         grads: &mut Gradients,
         checkpointer: &mut Checkpointer,
     ) {
@@ -471,7 +996,8 @@ impl Backward<PrimaryBackend, 7> for RenderBackwards {
         let aux = state.aux;
 
         let img_dimgs = state.out_img.shape.dims;
-        let img_size = glam::uvec2(img_dimgs[1] as u32, img_dimgs[0] as u32);
+        let num_cameras = img_dimgs[0];
+        let img_size = glam::uvec2(img_dimgs[2] as u32, img_dimgs[1] as u32);
 
         let v_output = grads.consume::<PrimaryBackend>(&ops.node);
         let client = &v_output.client;
@@ -484,9 +1010,10 @@ impl Backward<PrimaryBackend, 7> for RenderBackwards {
         let raw_opac =
             checkpointer.retrieve_node_output::<FloatTensor<PrimaryBackend>>(state.raw_opac);
 
-        let num_points = means.shape.dims[0];
+        // Gradients are calculated per splat and camera, and summed per splat at the end.
+        let num_points = means.shape.dims[0] * num_cameras;
 
-        let (v_xys, v_xys_global, v_xys_norm, v_conics, v_coeffs, v_opacities) = {
+        let (v_xys, v_xys_global, v_xys_norm, v_conics, v_colors, v_coeffs, v_opacities) = {
             let tile_bounds = uvec2(
                 img_size.x.div_ceil(shaders::helpers::TILE_WIDTH),
                 img_size.y.div_ceil(shaders::helpers::TILE_WIDTH),
@@ -501,25 +1028,90 @@ impl Backward<PrimaryBackend, 7> for RenderBackwards {
 
             let hard_float = !cfg!(target_family = "wasm") && !cfg!(target_os = "android");
 
+            let mut handles = vec![
+                aux.uniforms_buffer.clone().handle.binding(),
+                aux.compact_gid_from_isect.clone().handle.binding(),
+                aux.tile_bins.handle.binding(),
+                aux.projected_splats.handle.binding(),
+                aux.final_index.handle.binding(),
+                state.out_img.handle.binding(),
+                v_output.handle.binding(),
+            ];
+
+            // Floating point atomics add up the gradients in a different order every time. In
+            // deterministic mode, write out the gradient per intersection instead, and sum
+            // those per splat in a fixed order.
+            let isect_grads = if state.deterministic {
+                let projected_size =
+                    size_of::<shaders::helpers::ProjectedSplat>() / size_of::<f32>();
+                let isect_grads = create_tensor::<f32, 2, _>(
+                    [aux.intersection_capacity as usize, projected_size],
+                    device,
+                    client,
+                );
+                handles.push(isect_grads.clone().handle.binding());
+                Some(isect_grads)
+            } else {
+                handles.push(v_xys_local.clone().handle.binding());
+                handles.push(v_conics.clone().handle.binding());
+                handles.push(v_colors.clone().handle.binding());
+                None
+            };
+
+            let resort = aux.ray_depths.is_some();
+            if let Some(ray_depths) = &aux.ray_depths {
+                handles.push(ray_depths.handle.clone().binding());
+                handles.push(aux.cameras_buffer.clone().handle.binding());
+            }
+
             tracing::trace_span!("RasterizeBackwards", sync_burn = true).in_scope(|| unsafe {
                 client.execute_unchecked(
-                    RasterizeBackwards::task(hard_float),
-                    CubeCount::Static(invocations, 1, 1),
-                    vec![
-                        aux.uniforms_buffer.clone().handle.binding(),
-                        aux.compact_gid_from_isect.handle.binding(),
-                        aux.tile_bins.handle.binding(),
-                        aux.projected_splats.handle.binding(),
-                        aux.final_index.handle.binding(),
-                        state.out_img.handle.binding(),
-                        v_output.handle.binding(),
-                        v_xys_local.clone().handle.binding(),
-                        v_conics.clone().handle.binding(),
-                        v_colors.clone().handle.binding(),
-                    ],
+                    RasterizeBackwards::task(hard_float, state.deterministic, resort),
+                    CubeCount::Static(invocations, num_cameras as u32, 1),
+                    handles,
                 );
             });
 
+            if let Some(isect_grads) = isect_grads {
+                let _span = tracing::trace_span!("SumIsectGrads", sync_burn = true).entered();
+
+                // Group the intersections by splat. The sort is stable, so the intersections
+                // of a splat stay in tile order.
+                let num_isects = aux.intersection_capacity as usize;
+                let isect_ids =
+                    bitcast_tensor(PrimaryBackend::int_arange(0..num_isects as i64, device));
+                let num_intersections = bitcast_tensor(PrimaryBackend::int_clamp_max(
+                    bitcast_tensor(aux.num_intersections.clone()),
+                    num_isects as i32,
+                ));
+                let (_, isect_from_gid_sorted) = radix_argsort(
+                    aux.compact_gid_from_isect.clone(),
+                    isect_ids,
+                    num_intersections,
+                    u32::BITS - (num_points as u32).leading_zeros(),
+                );
+
+                let num_vis_wg = create_dispatch_buffer(
+                    bitcast_tensor(aux.num_visible.clone()),
+                    SumIsectGrads::WORKGROUP_SIZE,
+                );
+                unsafe {
+                    client.execute_unchecked(
+                        SumIsectGrads::task(),
+                        CubeCount::Dynamic(num_vis_wg.handle.binding()),
+                        vec![
+                            aux.uniforms_buffer.clone().handle.binding(),
+                            aux.cum_tiles_hit.clone().handle.binding(),
+                            isect_from_gid_sorted.handle.binding(),
+                            isect_grads.handle.binding(),
+                            v_xys_local.clone().handle.binding(),
+                            v_conics.clone().handle.binding(),
+                            v_colors.clone().handle.binding(),
+                        ],
+                    );
+                }
+            }
+
             let v_coeffs = PrimaryBackend::float_zeros(
                 [
                     num_points,
@@ -555,6 +1147,9 @@ impl Backward<PrimaryBackend, 7> for RenderBackwards {
                         v_opacities.handle.clone().binding(),
                         v_xys_global.handle.clone().binding(),
                         v_xys_norm.handle.clone().binding(),
+                        log_scales.clone().handle.binding(),
+                        state.filter_3d.clone().handle.binding(),
+                        aux.cameras_buffer.clone().handle.binding(),
                     ],
                 );
             }
@@ -564,6 +1159,7 @@ impl Backward<PrimaryBackend, 7> for RenderBackwards {
                 v_xys_global,
                 v_xys_norm,
                 v_conics,
+                v_colors,
                 v_coeffs,
                 v_opacities,
             )
@@ -577,6 +1173,12 @@ impl Backward<PrimaryBackend, 7> for RenderBackwards {
         let v_scales = PrimaryBackend::float_zeros([num_points, 3].into(), device);
         let v_quats = PrimaryBackend::float_zeros([num_points, 4].into(), device);
 
+        // The camera gradients are summed per workgroup, and the workgroups summed after.
+        let num_workgroups = (num_points as u32).div_ceil(ProjectBackwards::WORKGROUP_SIZE[0]);
+        let v_viewmats =
+            PrimaryBackend::float_zeros([num_workgroups as usize, 3, 4].into(), device);
+        let v_intrinsics = PrimaryBackend::float_zeros([num_workgroups as usize, 4].into(), device);
+
         tracing::trace_span!("ProjectBackwards", sync_burn = true).in_scope(|| unsafe {
             client.execute_unchecked(
                 ProjectBackwards::task(),
@@ -592,13 +1194,27 @@ impl Backward<PrimaryBackend, 7> for RenderBackwards {
                     v_means.handle.clone().binding(),
                     v_scales.handle.clone().binding(),
                     v_quats.handle.clone().binding(),
+                    raw_opac.handle.binding(),
+                    v_colors.handle.binding(),
+                    state.filter_3d.handle.binding(),
+                    v_viewmats.handle.clone().binding(),
+                    v_intrinsics.handle.clone().binding(),
+                    aux.cameras_buffer.handle.binding(),
                 ],
             );
         });
 
+        let v_means = sum_camera_grads::<2>(v_means, num_cameras);
+        let v_xys_global = sum_camera_grads::<2>(v_xys_global, num_cameras);
+        let v_xys_norm = sum_camera_grads::<1>(v_xys_norm, num_cameras);
+        let v_scales = sum_camera_grads::<2>(v_scales, num_cameras);
+        let v_quats = sum_camera_grads::<2>(v_quats, num_cameras);
+        let v_coeffs = sum_camera_grads::<3>(v_coeffs, num_cameras);
+        let v_opacities = sum_camera_grads::<1>(v_opacities, num_cameras);
+
         // Register gradients for parent nodes (This code is already skipped entirely
         // if no parent nodes require gradients).
-        let [mean_parent, xys_parent, xys_norm_parent, log_scales_parent, quats_parent, coeffs_parent, raw_opacity_parent] =
+        let [mean_parent, xys_parent, xys_norm_parent, log_scales_parent, quats_parent, coeffs_parent, raw_opacity_parent, viewmat_parent, intrinsics_parent] =
             ops.parents;
 
         if let Some(node) = mean_parent {
@@ -629,6 +1245,32 @@ impl Backward<PrimaryBackend, 7> for RenderBackwards {
         if let Some(node) = raw_opacity_parent {
             grads.register::<PrimaryBackend>(node.id, v_opacities);
         }
+
+        if let Some(node) = viewmat_parent {
+            // Each row is a column of the rotation, followed by an element of the translation.
+            let v_viewmats =
+                Tensor::<PrimaryBackend, 3>::from_primitive(TensorPrimitive::Float(v_viewmats))
+                    .sum_dim(0)
+                    .reshape([3, 4]);
+            let v_rotation = v_viewmats.clone().slice([0..3, 0..3]).transpose();
+            let v_translation = v_viewmats.slice([0..3, 3..4]);
+            let v_viewmat = Tensor::cat(
+                vec![
+                    Tensor::cat(vec![v_rotation, v_translation], 1),
+                    Tensor::zeros([1, 4], device),
+                ],
+                0,
+            );
+            grads.register::<PrimaryBackend>(node.id, v_viewmat.into_primitive().tensor());
+        }
+
+        if let Some(node) = intrinsics_parent {
+            let v_intrinsics =
+                Tensor::<PrimaryBackend, 2>::from_primitive(TensorPrimitive::Float(v_intrinsics))
+                    .sum_dim(0)
+                    .reshape([4]);
+            grads.register::<PrimaryBackend>(node.id, v_intrinsics.into_primitive().tensor());
+        }
     }
 }
 
@@ -638,9 +1280,14 @@ mod tests {
     use std::io::Read;
 
     use crate::{
-        camera::{focal_to_fov, fov_to_focal},
-        gaussian_splats::Splats,
+        bounding_box::{BoundingBox, OrientedBox},
+        camera::{focal_to_fov, fov_to_focal, DEFAULT_FAR, DEFAULT_NEAR},
+        gaussian_splats::{
+            merge_splats, MergePart, RandomSplatsConfig, Splats, SPLAT_HIDDEN, SPLAT_SELECTED,
+        },
         safetensor_utils::safetensor_to_burn,
+        sh::sh_basis,
+        CameraGrads,
     };
 
     use super::*;
@@ -649,6 +1296,7 @@ mod tests {
     use brush_rerun::{BurnToImage, BurnToRerun};
     use burn::tensor::{Float, Int};
     use burn_wgpu::WgpuDevice;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     type DiffBack = Autodiff<PrimaryBackend>;
     use anyhow::{Context, Result};
@@ -687,8 +1335,10 @@ mod tests {
             quats,
             sh_coeffs,
             raw_opacity,
+            None,
+            None,
             glam::vec3(0.123, 0.123, 0.123),
-            false,
+            RenderOptions::default(),
         );
         let rgb = output.clone().slice([0..32, 0..32, 0..3]);
         let alpha = output.clone().slice([0..32, 0..32, 3..4]);
@@ -699,24 +1349,840 @@ mod tests {
     }
 
     #[test]
-    fn test_reference() -> Result<()> {
+    fn features_match_colors() {
         let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(0, &device);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 48);
+        let (img, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+
+        // With degree 0 SH the colors are view independent, so compositing them as
+        // features, with a constant 1 for the alpha, should give back the image.
+        let num_points = splats.num_splats();
+        let colors = splats.sh_coeffs.val().reshape([num_points, 3]) * SH_C0 + 0.5;
+        let features = Tensor::cat(vec![colors, Tensor::ones([num_points, 1], &device)], 1);
+        let rendered = PrimaryBackend::render_features(&aux, img_size, features).unwrap();
+
+        let diff = (img.clone() - rendered).abs().max().into_scalar();
+        assert!(img.mean().into_scalar() > 0.01, "Nothing was rendered");
+        assert!(diff < 1e-4, "Features differ from colors by {diff}");
+    }
 
-        let crab_img = image::open("./test_cases/crab.png")?;
-        // Convert the image to RGB format
-        // Get the raw buffer
-        let raw_buffer = crab_img.to_rgb8().into_raw();
-        let crab_tens: Tensor<DiffBack, 3> = Tensor::<_, 1>::from_floats(
-            raw_buffer
-                .iter()
-                .map(|&b| b as f32 / 255.0)
-                .collect::<Vec<_>>()
-                .as_slice(),
-            &device,
-        )
-        .reshape([crab_img.height() as usize, crab_img.width() as usize, 3]);
+    #[test]
+    fn features_refuse_resorted_render() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(0, &device);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 48);
+        let options = RenderOptions {
+            resort: true,
+            ..Default::default()
+        };
+        let (_, aux) = splats.render_with(&cam, img_size, glam::Vec3::ZERO, options);
+        let features = Tensor::ones([splats.num_splats(), 1], &device);
+        let err = PrimaryBackend::render_features(&aux, img_size, features).unwrap_err();
+        assert!(err.to_string().contains("resorted render"));
+    }
 
-        let rec = rerun::RecordingStreamBuilder::new("render test").connect();
+    #[test]
+    fn features_backward_is_transpose() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(0, &device);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 48);
+        let (_, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+
+        let num_points = splats.num_splats();
+        let channels = 5;
+        let uniform = burn::tensor::Distribution::Uniform(-1.0, 1.0);
+        let features =
+            Tensor::<DiffBack, 2>::random([num_points, channels], uniform, &device).require_grad();
+        let weights = Tensor::<DiffBack, 3>::random(
+            [img_size.y as usize, img_size.x as usize, channels],
+            uniform,
+            &device,
+        );
+        let loss = (DiffBack::render_features(&aux, img_size, features.clone()).unwrap()
+            * weights.clone())
+        .sum();
+        let grads = loss.backward();
+        let v_features = features.grad(&grads).expect("Features need a gradient");
+
+        // The render is linear in the features, so <grad, x> == <weights, render(x)>.
+        let probe = Tensor::<PrimaryBackend, 2>::random([num_points, channels], uniform, &device);
+        let lhs = (v_features * probe.clone()).sum().into_scalar();
+        let rhs = (PrimaryBackend::render_features(&aux, img_size, probe).unwrap()
+            * weights.inner())
+        .sum()
+        .into_scalar();
+        assert!(
+            (lhs - rhs).abs() < 1e-3 * rhs.abs().max(1.0),
+            "Gradient mismatch {lhs} vs {rhs}"
+        );
+    }
+
+    #[test]
+    fn hidden_splats_are_culled() {
+        let device = WgpuDevice::BestAvailable;
+        let mut splats = random_splats(0, &device);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 48);
+
+        let num_splats = splats.num_splats();
+        let flags: Vec<u32> = (0..num_splats)
+            .map(|i| if i % 2 == 0 { SPLAT_HIDDEN } else { 0 })
+            .collect();
+        splats.set_flags(&flags);
+        let (_, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let visible = visible_gids(&aux);
+        assert!(!visible.is_empty());
+        assert!(visible.iter().all(|gid| gid % 2 == 1));
+
+        // Selecting only changes the display buffer.
+        splats.set_flags(&vec![SPLAT_SELECTED; num_splats]);
+        let (img, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        splats.clear_flags();
+        let (reference, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let diff = (img - reference).abs().max().into_scalar();
+        assert!(diff < 1e-6, "Selection changed the render by {diff}");
+    }
+
+    #[test]
+    fn selection_tints_display_buffer() {
+        let device = WgpuDevice::BestAvailable;
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 64);
+
+        // Two gray splats side by side, only the right one is selected.
+        let means =
+            Tensor::<PrimaryBackend, 1>::from_floats([-0.6, 0.0, 5.0, 0.6, 0.0, 5.0], &device)
+                .reshape([2, 3]);
+        let log_scales = Tensor::ones([2, 3], &device) * -1.5;
+        let quats = Tensor::<_, 1, _>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, 2);
+        let sh_coeffs = Tensor::zeros([2, 1, 3], &device);
+        let raw_opacity = Tensor::ones([2], &device) * 4.0;
+        let mut splats =
+            Splats::from_data(means, sh_coeffs, quats, raw_opacity, log_scales, &device);
+        splats.set_flags(&[0, SPLAT_SELECTED]);
+
+        let (img, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, true);
+        assert_eq!(img.dims(), [64, 64, 1]);
+        let pixels: Vec<[u8; 4]> = img
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap()
+            .iter()
+            .map(|p| p.to_bits().to_le_bytes())
+            .collect();
+
+        let offset = (0.6 / 5.0 * cam.focal(img_size).x).round() as u32;
+        let pixel = |x: u32| pixels[(32 * img_size.x + x) as usize];
+        let [r, g, b, a] = pixel(32 - offset).map(i32::from);
+        assert!(a > 240);
+        assert!(
+            (r - g).abs() <= 2 && (g - b).abs() <= 2,
+            "Unselected splat is tinted"
+        );
+
+        // Selected splats are blended halfway to orange.
+        let [r, g, b, a] = pixel(32 + offset).map(i32::from);
+        assert!(a > 240);
+        assert!(r > g + 40 && g > b + 40, "Selected splat isn't tinted");
+    }
+
+    #[test]
+    fn truncated_intersections_are_detected() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(0, &device);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 48);
+
+        let (_, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let num_intersections = task::block_on(aux.read_num_intersections());
+        assert_eq!(aux.intersection_capacity, num_intersections);
+        assert!(!task::block_on(aux.read_intersections_truncated()));
+
+        // With buffers that are too small, the render drops intersections but still works.
+        TEST_INTERSECTION_CAPACITY.set(Some(num_intersections as usize / 4));
+        let (img, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        TEST_INTERSECTION_CAPACITY.set(None);
+
+        assert_eq!(aux.intersection_capacity, num_intersections / 4);
+        assert_eq!(
+            task::block_on(aux.read_num_intersections()),
+            num_intersections
+        );
+        assert!(task::block_on(aux.read_intersections_truncated()));
+        assert!(img.into_data().iter::<f32>().all(f32::is_finite));
+    }
+
+    #[test]
+    fn near_far_culls_splats() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(0, &device);
+        let img_size = glam::uvec2(64, 48);
+        let (near, far) = (4.5, 5.5);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        )
+        .with_clip_planes(near, far);
+
+        // The camera looks down +z from the origin, so the depth of a splat is its z.
+        let means = splats.means.val().into_data().to_vec::<f32>().unwrap();
+        let (_, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let visible = visible_gids(&aux);
+        assert!(!visible.is_empty());
+        for gid in visible {
+            let depth = means[gid as usize * 3 + 2];
+            assert!(
+                depth > near && depth < far,
+                "Splat at depth {depth} wasn't culled"
+            );
+        }
+
+        // Without the clip planes, more splats are visible.
+        let (_, aux) = splats.render(
+            &cam.clone().with_clip_planes(DEFAULT_NEAR, DEFAULT_FAR),
+            img_size,
+            glam::Vec3::ZERO,
+            false,
+        );
+        let all_visible = visible_gids(&aux);
+        assert!(all_visible.iter().any(|&gid| {
+            let depth = means[gid as usize * 3 + 2];
+            depth < near || depth > far
+        }));
+    }
+
+    #[test]
+    fn clip_volume_culls_splats() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(0, &device);
+        let img_size = glam::uvec2(64, 48);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+
+        let means = splats.means.val().into_data().to_vec::<f32>().unwrap();
+        let (_, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let unclipped = visible_gids(&aux);
+
+        let volumes = [
+            ClipVolume::Box(OrientedBox::new(
+                glam::vec3(0.0, 0.0, 5.0),
+                glam::vec3(1.0, 0.8, 1.2),
+                glam::Quat::from_rotation_y(0.5),
+            )),
+            // The half space x >= 0.5, up to a depth of 5.
+            ClipVolume::Planes(vec![
+                glam::vec4(1.0, 0.0, 0.0, -0.5),
+                glam::vec4(0.0, 0.0, -1.0, 5.0),
+            ]),
+        ];
+
+        for volume in volumes {
+            let (_, aux) = splats.render_with(
+                &cam,
+                img_size,
+                glam::Vec3::ZERO,
+                RenderOptions {
+                    clip_volume: Some(&volume),
+                    ..Default::default()
+                },
+            );
+            let num_visible = task::block_on(aux.read_num_visible()) as usize;
+            let mut visible = visible_gids(&aux);
+            visible.sort();
+
+            // Exactly the splats that were visible before and are inside the volume remain.
+            let mut expected: Vec<_> = unclipped
+                .iter()
+                .copied()
+                .filter(|&gid| {
+                    let i = gid as usize * 3;
+                    volume.contains(glam::Vec3::from_slice(&means[i..i + 3]))
+                })
+                .collect();
+            expected.sort();
+
+            assert!(!expected.is_empty() && expected.len() < unclipped.len());
+            assert_eq!(num_visible, expected.len());
+            assert_eq!(visible, expected);
+        }
+    }
+
+    #[test]
+    fn viewmat_grad_matches_finite_difference() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(0, &device);
+        let splats = Splats::<DiffBack>::from_data(
+            Tensor::from_inner(splats.means.val()),
+            Tensor::from_inner(splats.sh_coeffs.val()),
+            Tensor::from_inner(splats.rotation.val()),
+            Tensor::from_inner(splats.raw_opacity.val()),
+            Tensor::from_inner(splats.log_scales.val()),
+            &device,
+        );
+        let img_size = glam::uvec2(64, 48);
+        let cam = Camera::new(
+            glam::vec3(0.1, -0.2, 0.0),
+            glam::Quat::from_rotation_y(0.05),
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+
+        let viewmat_tensor = |cam: &Camera| {
+            // Row major, so upload the columns of the transpose.
+            Tensor::<DiffBack, 1>::from_floats(
+                cam.world_to_local().transpose().to_cols_array(),
+                &device,
+            )
+            .reshape([4, 4])
+        };
+        let loss = |cam: &Camera, viewmat: Tensor<DiffBack, 2>| {
+            let (img, _) = splats.render_with(
+                cam,
+                img_size,
+                glam::Vec3::ZERO,
+                RenderOptions {
+                    camera_grads: CameraGrads {
+                        viewmat: Some(viewmat),
+                        intrinsics: None,
+                    },
+                    ..Default::default()
+                },
+            );
+            img.slice([0..48, 0..64, 0..3]).powf_scalar(2.0).sum()
+        };
+
+        let viewmat = viewmat_tensor(&cam).require_grad();
+        let grads = loss(&cam, viewmat.clone()).backward();
+        let v_viewmat = viewmat.grad(&grads).expect("Viewmat needs a gradient");
+
+        for offset in [glam::Vec3::X, glam::Vec3::Y, glam::Vec3::Z] {
+            let eps = 1e-2;
+            let mut cam_pos = cam.clone();
+            cam_pos.position += offset * eps;
+            let mut cam_neg = cam.clone();
+            cam_neg.position -= offset * eps;
+
+            let finite_diff = (loss(&cam_pos, viewmat_tensor(&cam_pos))
+                - loss(&cam_neg, viewmat_tensor(&cam_neg)))
+            .into_scalar()
+                / (2.0 * eps);
+
+            let d_viewmat =
+                (viewmat_tensor(&cam_pos) - viewmat_tensor(&cam_neg)).inner() / (2.0 * eps);
+            let analytic = (v_viewmat.clone() * d_viewmat).sum().into_scalar();
+
+            let err = (analytic - finite_diff).abs() / finite_diff.abs().max(1e-3);
+            assert!(
+                err < 0.1,
+                "Viewmat gradient {analytic} doesn't match finite difference {finite_diff}"
+            );
+        }
+    }
+
+    #[test]
+    fn intrinsics_grad_matches_finite_difference() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(0, &device);
+        let splats = Splats::<DiffBack>::from_data(
+            Tensor::from_inner(splats.means.val()),
+            Tensor::from_inner(splats.sh_coeffs.val()),
+            Tensor::from_inner(splats.rotation.val()),
+            Tensor::from_inner(splats.raw_opacity.val()),
+            Tensor::from_inner(splats.log_scales.val()),
+            &device,
+        );
+        let img_size = glam::uvec2(64, 48);
+        let cam = Camera::new(
+            glam::vec3(0.1, -0.2, 0.0),
+            glam::Quat::from_rotation_y(0.05),
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+
+        let intrinsics_tensor = |cam: &Camera| {
+            let focal = cam.focal(img_size);
+            let center = cam.center(img_size);
+            Tensor::<DiffBack, 1>::from_floats([focal.x, focal.y, center.x, center.y], &device)
+        };
+        let loss = |cam: &Camera, intrinsics: Tensor<DiffBack, 1>| {
+            let (img, _) = splats.render_with(
+                cam,
+                img_size,
+                glam::Vec3::ZERO,
+                RenderOptions {
+                    camera_grads: CameraGrads {
+                        viewmat: None,
+                        intrinsics: Some(intrinsics),
+                    },
+                    ..Default::default()
+                },
+            );
+            img.slice([0..48, 0..64, 0..3]).powf_scalar(2.0).sum()
+        };
+
+        let intrinsics = intrinsics_tensor(&cam).require_grad();
+        let grads = loss(&cam, intrinsics.clone()).backward();
+        let v_intrinsics = intrinsics.grad(&grads).expect("Intrinsics need a gradient");
+
+        let perturbations: [fn(&mut Camera, f32); 4] = [
+            |c, eps| c.fov.x += eps,
+            |c, eps| c.fov.y += eps,
+            |c, eps| c.center_uv.x += eps,
+            |c, eps| c.center_uv.y += eps,
+        ];
+
+        for perturb in perturbations {
+            let eps = 1e-3;
+            let mut cam_pos = cam.clone();
+            perturb(&mut cam_pos, eps);
+            let mut cam_neg = cam.clone();
+            perturb(&mut cam_neg, -eps);
+
+            let finite_diff = (loss(&cam_pos, intrinsics_tensor(&cam_pos))
+                - loss(&cam_neg, intrinsics_tensor(&cam_neg)))
+            .into_scalar()
+                / (2.0 * eps);
+
+            let d_intrinsics =
+                (intrinsics_tensor(&cam_pos) - intrinsics_tensor(&cam_neg)).inner() / (2.0 * eps);
+            let analytic = (v_intrinsics.clone() * d_intrinsics).sum().into_scalar();
+
+            let err = (analytic - finite_diff).abs() / finite_diff.abs().max(1e-3);
+            assert!(
+                err < 0.1,
+                "Intrinsics gradient {analytic} doesn't match finite difference {finite_diff}"
+            );
+        }
+    }
+
+    #[test]
+    fn deterministic_grads_are_identical() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(1, &device);
+        let splats = Splats::<DiffBack>::from_data(
+            Tensor::from_inner(splats.means.val()),
+            Tensor::from_inner(splats.sh_coeffs.val()),
+            Tensor::from_inner(splats.rotation.val()),
+            Tensor::from_inner(splats.raw_opacity.val()),
+            Tensor::from_inner(splats.log_scales.val()),
+            &device,
+        );
+        let img_size = glam::uvec2(64, 48);
+        let cam = Camera::new(
+            glam::vec3(0.1, -0.2, 0.0),
+            glam::Quat::from_rotation_y(0.05),
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+
+        let splat_grads = || {
+            let (img, _) = splats.render_with(
+                &cam,
+                img_size,
+                glam::Vec3::ZERO,
+                RenderOptions {
+                    deterministic: true,
+                    ..Default::default()
+                },
+            );
+            let grads = img.powf_scalar(2.0).sum().backward();
+            let v_means = splats.means.grad(&grads).expect("Means need a gradient");
+            let v_coeffs = splats
+                .sh_coeffs
+                .grad(&grads)
+                .expect("Coeffs need a gradient");
+            (
+                v_means.into_data().to_vec::<f32>().unwrap(),
+                v_coeffs.into_data().to_vec::<f32>().unwrap(),
+            )
+        };
+
+        let (v_means, v_coeffs) = splat_grads();
+        assert!(v_means.iter().any(|&g| g != 0.0));
+
+        for _ in 0..4 {
+            let (v_means_again, v_coeffs_again) = splat_grads();
+            assert_eq!(v_means, v_means_again);
+            assert_eq!(v_coeffs, v_coeffs_again);
+        }
+    }
+
+    #[test]
+    fn batch_matches_single_renders() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(1, &device);
+        let splats = Splats::<DiffBack>::from_data(
+            Tensor::from_inner(splats.means.val()),
+            Tensor::from_inner(splats.sh_coeffs.val()),
+            Tensor::from_inner(splats.rotation.val()),
+            Tensor::from_inner(splats.raw_opacity.val()),
+            Tensor::from_inner(splats.log_scales.val()),
+            &device,
+        );
+        // Not a multiple of the tile size, so the last tiles of each camera are partial.
+        let img_size = glam::uvec2(40, 36);
+        let cameras: Vec<_> = [-0.3, 0.0, 0.2]
+            .into_iter()
+            .map(|angle| {
+                Camera::new(
+                    glam::vec3(angle, 0.1, 0.0),
+                    glam::Quat::from_rotation_y(angle),
+                    glam::vec2(0.8, 0.8),
+                    glam::vec2(0.5, 0.5),
+                )
+            })
+            .collect();
+        let weights = Tensor::<DiffBack, 4>::random(
+            [cameras.len(), img_size.y as usize, img_size.x as usize, 4],
+            burn::tensor::Distribution::Uniform(-1.0, 1.0),
+            &device,
+        );
+
+        // Deterministic, non-deterministic and resorted renders each take their own path.
+        for (deterministic, resort) in [(true, false), (false, false), (false, true)] {
+            let options = || RenderOptions {
+                deterministic,
+                resort,
+                ..Default::default()
+            };
+            let (batch, _) = splats.render_batch(&cameras, img_size, glam::Vec3::ZERO, options());
+            let batch_grads = (batch.clone() * weights.clone()).sum().backward();
+
+            let singles: Vec<_> = cameras
+                .iter()
+                .map(|cam| {
+                    splats
+                        .render_with(cam, img_size, glam::Vec3::ZERO, options())
+                        .0
+                })
+                .collect();
+            let singles = Tensor::stack(singles, 0);
+            let single_grads = (singles.clone() * weights.clone()).sum().backward();
+
+            let diff = (batch.inner() - singles.inner()).abs().max().into_scalar();
+            assert!(
+                diff < 1e-5,
+                "Batched render differs by {diff} ({deterministic}, {resort})"
+            );
+
+            for (batch_grad, single_grad) in [
+                (
+                    splats.means.grad(&batch_grads),
+                    splats.means.grad(&single_grads),
+                ),
+                (
+                    splats.log_scales.grad(&batch_grads),
+                    splats.log_scales.grad(&single_grads),
+                ),
+            ] {
+                let batch_grad = batch_grad.expect("Batch needs a gradient");
+                let single_grad = single_grad.expect("Singles need a gradient");
+                let scale = single_grad.clone().abs().max().into_scalar();
+                let diff = (batch_grad - single_grad).abs().max().into_scalar();
+                assert!(scale > 0.0);
+                assert!(
+                    diff < 1e-4 * scale,
+                    "Batched gradients differ by {diff} ({deterministic}, {resort})"
+                );
+            }
+
+            let batch_coeffs = splats.sh_coeffs.grad(&batch_grads).unwrap();
+            let single_coeffs = splats.sh_coeffs.grad(&single_grads).unwrap();
+            let diff = (batch_coeffs - single_coeffs).abs().max().into_scalar();
+            assert!(
+                diff < 1e-4,
+                "Batched coeff gradients differ by {diff} ({deterministic}, {resort})"
+            );
+        }
+    }
+
+    #[test]
+    fn filter_3d_bakes_into_splats() {
+        let device = WgpuDevice::BestAvailable;
+        let mut splats = random_splats(0, &device);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 48);
+
+        splats.update_filter_3d([(&cam, img_size)]);
+        let filter = splats.filter_3d.clone().expect("Filter should be set");
+        assert!(filter.min().into_scalar() > 0.0);
+
+        let (filtered, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        splats.bake_filter_3d();
+        assert!(splats.filter_3d.is_none());
+        let (baked, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let diff = (filtered - baked).abs().max().into_scalar();
+        assert!(diff < 1e-4, "Baked filter changed the render by {diff}");
+    }
+
+    #[test]
+    fn merge_keeps_filter_and_flags() {
+        let device = WgpuDevice::BestAvailable;
+        let mut splats = random_splats(0, &device);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 48);
+
+        splats.update_filter_3d([(&cam, img_size)]);
+        let flags: Vec<u32> = (0..splats.num_splats())
+            .map(|i| if i % 3 == 0 { SPLAT_HIDDEN } else { 0 })
+            .collect();
+        splats.set_flags(&flags);
+        let (reference, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+
+        let merged = task::block_on(merge_splats(vec![MergePart::new(splats.clone())])).unwrap();
+        let (img, _) = merged.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let diff = (img - reference).abs().max().into_scalar();
+        assert!(diff < 1e-6, "Merging changed the render by {diff}");
+
+        // Parts without a filter or flags are padded with zeros.
+        let plain = random_splats(0, &device);
+        let merged = task::block_on(merge_splats(vec![
+            MergePart::new(splats.clone()),
+            MergePart::new(plain),
+        ]))
+        .unwrap();
+        let n = splats.num_splats();
+        let filter = merged.filter_3d.expect("Filter should be kept");
+        let flags = merged.flags.expect("Flags should be kept");
+        assert_eq!(filter.dims(), [2 * n]);
+        assert_eq!(flags.dims(), [2 * n]);
+        assert_eq!(filter.slice([n..2 * n]).abs().max().into_scalar(), 0.0);
+        assert_eq!(flags.slice([n..2 * n]).max().into_scalar(), 0);
+    }
+
+    #[test]
+    fn merge_transforms_and_pads_sh() {
+        let device = WgpuDevice::BestAvailable;
+        let low = random_splats(0, &device);
+        let high = random_splats(1, &device);
+        let n = low.num_splats();
+
+        let merged = task::block_on(merge_splats(vec![
+            MergePart::new(low.clone()),
+            MergePart::new(high.clone()).with_transform(test_transform()),
+        ]))
+        .unwrap();
+        assert_eq!(merged.num_splats(), 2 * n);
+
+        // The second part is transformed like Splats::transform does.
+        let mut transformed = high.clone();
+        transformed.transform(test_transform());
+        let diff = (merged.means.val().slice([n..2 * n, 0..3]) - transformed.means.val())
+            .abs()
+            .max()
+            .into_scalar();
+        assert!(diff < 1e-5, "Transformed means differ by {diff}");
+        let diff = (merged.log_scales.val().slice([n..2 * n, 0..3]) - transformed.log_scales.val())
+            .abs()
+            .max()
+            .into_scalar();
+        assert!(diff < 1e-5, "Transformed scales differ by {diff}");
+
+        // The degree 0 part is padded up to degree 1 with zeros.
+        let coeffs = merged.sh_coeffs.val();
+        assert_eq!(coeffs.dims(), [2 * n, 4, 3]);
+        let diff = (coeffs.clone().slice([0..n, 0..1, 0..3]) - low.sh_coeffs.val())
+            .abs()
+            .max()
+            .into_scalar();
+        assert_eq!(diff, 0.0);
+        let padding = coeffs.slice([0..n, 1..4, 0..3]).abs().max().into_scalar();
+        assert_eq!(padding, 0.0);
+    }
+
+    #[test]
+    fn merge_crops_parts() {
+        let device = WgpuDevice::BestAvailable;
+        let splats = random_splats(0, &device);
+
+        // Keeps the front part of the splats, which lie between z = 3 and z = 7.
+        let crop = OrientedBox::new(
+            glam::vec3(0.0, 0.0, 4.0),
+            glam::vec3(3.0, 3.0, 1.0),
+            glam::Quat::IDENTITY,
+        );
+        let means = splats.means.val().into_data().to_vec::<f32>().unwrap();
+        let expected = means
+            .chunks_exact(3)
+            .filter(|m| crop.contains(glam::Vec3::from_slice(m)))
+            .count();
+        assert!(expected > 0 && expected < splats.num_splats());
+
+        let merged = task::block_on(merge_splats(vec![
+            MergePart::new(splats.clone()).with_crop(crop)
+        ]))
+        .unwrap();
+        assert_eq!(merged.num_splats(), expected);
+
+        // Nothing is left with a crop away from all splats.
+        let empty = OrientedBox::new(
+            glam::vec3(0.0, 0.0, -10.0),
+            glam::Vec3::ONE,
+            glam::Quat::IDENTITY,
+        );
+        let result = task::block_on(merge_splats(vec![MergePart::new(splats).with_crop(empty)]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn safetensors_round_trip() {
+        let device = WgpuDevice::BestAvailable;
+        let mut splats = random_splats(1, &device);
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+
+        for with_filter in [false, true] {
+            if with_filter {
+                splats.update_filter_3d([(&cam, glam::uvec2(64, 48))]);
+            }
+
+            let bytes = task::block_on(splats.to_safetensors()).unwrap();
+            let tensors = SafeTensors::deserialize(&bytes).unwrap();
+            let loaded = Splats::<PrimaryBackend>::from_safetensors(&tensors, &device).unwrap();
+
+            let same = |a: Tensor<PrimaryBackend, 1>, b: Tensor<PrimaryBackend, 1>| {
+                a.into_data() == b.into_data()
+            };
+            assert!(same(
+                splats.means.val().flatten(0, 1),
+                loaded.means.val().flatten(0, 1)
+            ));
+            assert!(same(
+                splats.log_scales.val().flatten(0, 1),
+                loaded.log_scales.val().flatten(0, 1)
+            ));
+            assert!(same(
+                splats.sh_coeffs.val().flatten(0, 2),
+                loaded.sh_coeffs.val().flatten(0, 2)
+            ));
+            assert!(same(
+                splats.rotation.val().flatten(0, 1),
+                loaded.rotation.val().flatten(0, 1)
+            ));
+            assert!(same(splats.raw_opacity.val(), loaded.raw_opacity.val()));
+            assert_eq!(loaded.sh_coeffs.dims(), splats.sh_coeffs.dims());
+
+            match (&splats.filter_3d, &loaded.filter_3d) {
+                (None, None) => assert!(!with_filter),
+                (Some(a), Some(b)) => assert!(with_filter && same(a.clone(), b.clone())),
+                _ => panic!("The 3D filter wasn't round tripped"),
+            }
+        }
+    }
+
+    #[test]
+    fn pick_pixel_front_to_back() {
+        let device = WgpuDevice::BestAvailable;
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 64);
+
+        // Two overlapping splats, with the first one behind the second.
+        let means =
+            Tensor::<PrimaryBackend, 1>::from_floats([0.0, 0.0, 5.0, 0.0, 0.0, 3.0], &device)
+                .reshape([2, 3]);
+        let log_scales = Tensor::ones([2, 3], &device) * -1.0;
+        let quats = Tensor::<_, 1, _>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+            .unsqueeze_dim(0)
+            .repeat_dim(0, 2);
+        let sh_coeffs = Tensor::ones([2, 1, 3], &device);
+        let raw_opacity = Tensor::zeros([2], &device);
+        let splats = Splats::from_data(means, sh_coeffs, quats, raw_opacity, log_scales, &device);
+
+        let (_, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let picked = task::block_on(aux.pick_pixel(glam::uvec2(32, 32)));
+
+        assert_eq!(picked.len(), 2);
+        assert_eq!(picked[0].global_gid, 1);
+        assert_eq!(picked[1].global_gid, 0);
+        // Sigmoid(0) opacity, near the center of the splats.
+        assert!((picked[0].alpha - 0.5).abs() < 0.05);
+        assert!(picked[1].weight < picked[1].alpha);
+
+        // Nothing is in the corner.
+        let picked = task::block_on(aux.pick_pixel(glam::uvec2(0, 0)));
+        assert!(picked.is_empty());
+    }
+
+    #[test]
+    fn test_reference() -> Result<()> {
+        let device = WgpuDevice::BestAvailable;
+
+        let crab_img = image::open("./test_cases/crab.png")?;
+        // Convert the image to RGB format
+        // Get the raw buffer
+        let raw_buffer = crab_img.to_rgb8().into_raw();
+        let crab_tens: Tensor<DiffBack, 3> = Tensor::<_, 1>::from_floats(
+            raw_buffer
+                .iter()
+                .map(|&b| b as f32 / 255.0)
+                .collect::<Vec<_>>()
+                .as_slice(),
+            &device,
+        )
+        .reshape([crab_img.height() as usize, crab_img.width() as usize, 3]);
+
+        let rec = rerun::RecordingStreamBuilder::new("render test").connect();
 
         for (i, path) in ["tiny_case", "basic_case", "mix_case"].iter().enumerate() {
             println!("path {path}");
@@ -834,102 +2300,507 @@ mod tests {
         Ok(())
     }
 
-    // #[test]
-    // fn test_mean_grads() {
-    //     let cam = Camera::new(glam::vec3(0.0, 0.0, -5.0), glam::Quat::IDENTITY, 0.5, 0.5);
-    //     let num_points = 1;
-
-    //     let img_size = glam::uvec2(16, 16);
-    //     let device = WgpuDevice::BestAvailable;
-
-    //     let means = Tensor::<DiffBack, 2, _>::zeros([num_points, 3], &device).require_grad();
-    //     let log_scales = Tensor::ones([num_points, 3], &device).require_grad();
-    //     let quats = Tensor::from_data(glam::Quat::IDENTITY.to_array(), &device)
-    //         .unsqueeze_dim(0)
-    //         .repeat(0, num_points)
-    //         .require_grad();
-    //     let sh_coeffs = Tensor::zeros([num_points, 4], &device).require_grad();
-    //     let raw_opacity = Tensor::zeros([num_points], &device).require_grad();
-
-    //     let mut dloss_dmeans_stat = Tensor::zeros([num_points], &device);
-
-    //     // Calculate a stochasic gradient estimation by perturbing random dimensions.
-    //     let num_iters = 50;
-
-    //     for _ in 0..num_iters {
-    //         let eps = 1e-4;
-
-    //         let flip_vec = Tensor::<DiffBack, 1>::random(
-    //             [num_points],
-    //             burn::tensor::Distribution::Uniform(-1.0, 1.0),
-    //             &device,
-    //         );
-    //         let seps = flip_vec * eps;
-
-    //         let l1 = render(
-    //             &cam,
-    //             img_size,
-    //             means.clone(),
-    //             log_scales.clone(),
-    //             quats.clone(),
-    //             sh_coeffs.clone(),
-    //             raw_opacity.clone() - seps.clone(),
-    //             glam::Vec3::ZERO,
-    //         )
-    //         .0
-    //         .mean();
-
-    //         let l2 = render(
-    //             &cam,
-    //             img_size,
-    //             means.clone(),
-    //             log_scales.clone(),
-    //             quats.clone(),
-    //             sh_coeffs.clone(),
-    //             raw_opacity.clone() + seps.clone(),
-    //             glam::Vec3::ZERO,
-    //         )
-    //         .0
-    //         .mean();
-
-    //         let df = l2 - l1;
-    //         dloss_dmeans_stat = dloss_dmeans_stat + df * (seps * 2.0).recip();
-    //     }
-
-    //     dloss_dmeans_stat = dloss_dmeans_stat / (num_iters as f32);
-    //     let dloss_dmeans_stat = dloss_dmeans_stat.into_data().value;
-
-    //     let loss = render(
-    //         &cam,
-    //         img_size,
-    //         means.clone(),
-    //         log_scales.clone(),
-    //         quats.clone(),
-    //         sh_coeffs.clone(),
-    //         raw_opacity.clone(),
-    //         glam::Vec3::ZERO,
-    //     )
-    //     .0
-    //     .mean();
-    //     // calculate numerical gradients.
-    //     // Compare to reference value.
-
-    //     // Check if rendering doesn't hard crash or anything.
-    //     // These are some zero-sized gaussians, so we know
-    //     // what the result should look like.
-    //     let grads = loss.backward();
-
-    //     // Get the gradient of the rendered image.
-    //     let dloss_dmeans = (Tensor::<BurnBack, 1>::from_primitive(
-    //         grads.get(&raw_opacity.clone().into_primitive()).unwrap(),
-    //     ))
-    //     .into_data()
-    //     .value;
-
-    //     println!("Stat grads {dloss_dmeans_stat:.5?}");
-    //     println!("Calc grads {dloss_dmeans:.5?}");
-
-    //     // TODO: These results don't make sense at all currently, which is either
-    //     // mildly bad news or very bad news :)
-    // }
+    // The global ids of the splats that passed culling.
+    fn visible_gids(aux: &RenderAux) -> Vec<i32> {
+        Tensor::<PrimaryBackend, 1, Int>::from_primitive(bitcast_tensor(
+            aux.global_from_compact_gid.clone(),
+        ))
+        .slice([0..task::block_on(aux.read_num_visible()) as usize])
+        .to_data()
+        .to_vec::<i32>()
+        .unwrap()
+    }
+
+    fn random_splats(sh_degree: u32, device: &WgpuDevice) -> Splats<PrimaryBackend> {
+        let mut rng = StdRng::seed_from_u64(4);
+        Splats::from_random_config(
+            RandomSplatsConfig::new()
+                .with_init_count(500)
+                .with_sh_degree(sh_degree),
+            BoundingBox::from_min_max(glam::vec3(-2.0, -2.0, 3.0), glam::vec3(2.0, 2.0, 7.0)),
+            &mut rng,
+            device,
+        )
+    }
+
+    fn test_transform() -> glam::Affine3A {
+        glam::Affine3A::from_scale_rotation_translation(
+            glam::Vec3::splat(2.5),
+            glam::Quat::from_euler(glam::EulerRot::YXZ, 0.4, -0.9, 1.7),
+            glam::vec3(1.0, -3.0, 0.5),
+        )
+    }
+
+    #[test]
+    fn transform_render_invariance() {
+        let device = WgpuDevice::BestAvailable;
+
+        // With SH, this also checks the SH rotation, as the view direction moves with the camera.
+        let mut splats = random_splats(2, &device);
+        Splats::map_param(&mut splats.sh_coeffs, |c| {
+            Tensor::random(
+                c.shape(),
+                burn::tensor::Distribution::Uniform(-0.5, 0.5),
+                &c.device(),
+            )
+        });
+
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 64);
+        let (before, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+
+        let transform = test_transform();
+        let (_, rotation, _) = transform.to_scale_rotation_translation();
+        splats.transform(transform);
+
+        let moved_cam = Camera::new(
+            transform.transform_point3(cam.position),
+            rotation * cam.rotation,
+            cam.fov,
+            cam.center_uv,
+        );
+        let (after, _) = splats.render(&moved_cam, img_size, glam::Vec3::ZERO, false);
+
+        let diff = (before.clone() - after).abs().mean().into_scalar();
+        assert!(before.mean().into_scalar() > 0.01, "Nothing was rendered");
+        assert!(
+            diff < 1e-4,
+            "Render changed after transform, mean diff {diff}"
+        );
+    }
+
+    #[test]
+    fn transform_rotates_sh() {
+        let device = WgpuDevice::BestAvailable;
+        let degree = 3;
+        let mut splats = random_splats(degree, &device);
+        Splats::map_param(&mut splats.sh_coeffs, |c| {
+            Tensor::random(
+                c.shape(),
+                burn::tensor::Distribution::Uniform(-1.0, 1.0),
+                &c.device(),
+            )
+        });
+
+        let num_coeffs = sh_coeffs_for_degree(degree) as usize;
+        let coeffs_before = splats.sh_coeffs.val().to_data().to_vec::<f32>().unwrap();
+        let transform = test_transform();
+        splats.transform(transform);
+        let coeffs_after = splats.sh_coeffs.val().to_data().to_vec::<f32>().unwrap();
+        let (_, rotation, _) = transform.to_scale_rotation_translation();
+
+        let eval = |coeffs: &[f32], splat: usize, channel: usize, dir: glam::Vec3| -> f32 {
+            sh_basis(degree, dir)
+                .iter()
+                .enumerate()
+                .map(|(i, b)| b * coeffs[(splat * num_coeffs + i) * 3 + channel])
+                .sum()
+        };
+
+        for splat in [0, 17, 321] {
+            for channel in 0..3 {
+                let dir = glam::vec3(0.2, -0.4, 0.9).normalize();
+                let before = eval(&coeffs_before, splat, channel, dir);
+                let after = eval(&coeffs_after, splat, channel, rotation * dir);
+                assert_approx_eq!(before, after, 1e-4);
+            }
+        }
+    }
+
+    // Inputs of [`Backend::render_splats`] for the gradient checks, as flat data.
+    #[derive(Clone)]
+    struct GradCheckScene {
+        means: Vec<f32>,
+        log_scales: Vec<f32>,
+        quats: Vec<f32>,
+        sh_coeffs: Vec<f32>,
+        raw_opacity: Vec<f32>,
+        filter_3d: Option<Vec<f32>>,
+    }
+
+    struct GradCheckTensors<B: Backend> {
+        means: Tensor<B, 2>,
+        xy_dummy: Tensor<B, 2>,
+        xy_norm_dummy: Tensor<B, 1>,
+        log_scales: Tensor<B, 2>,
+        quats: Tensor<B, 2>,
+        sh_coeffs: Tensor<B, 3>,
+        raw_opacity: Tensor<B, 1>,
+        filter_3d: Option<Tensor<B, 1>>,
+    }
+
+    fn uniform_vec(rng: &mut StdRng, len: usize, min: f32, max: f32) -> Vec<f32> {
+        (0..len).map(|_| rng.gen_range(min..max)).collect()
+    }
+
+    impl GradCheckScene {
+        fn random(num_points: usize, sh_degree: u32, rng: &mut StdRng) -> Self {
+            // Keep the splats in front of the camera and in view, and large enough to cover
+            // a good number of pixels.
+            let means = (0..num_points)
+                .flat_map(|_| {
+                    [
+                        rng.gen_range(-1.0..1.0),
+                        rng.gen_range(-0.7..0.7),
+                        rng.gen_range(4.0..6.0),
+                    ]
+                })
+                .collect();
+            let log_scales = uniform_vec(rng, num_points * 3, -1.4, -0.6);
+            let quats = (0..num_points)
+                .flat_map(|_| {
+                    glam::Vec4::from_array(uniform_vec(rng, 4, -1.0, 1.0).try_into().unwrap())
+                        .normalize()
+                        .to_array()
+                })
+                .collect();
+            let num_coeffs = sh_coeffs_for_degree(sh_degree) as usize;
+            let sh_coeffs = uniform_vec(rng, num_points * num_coeffs * 3, -0.5, 0.5);
+            // Stay away from the 0.99 alpha clamp.
+            let raw_opacity = uniform_vec(rng, num_points, -2.0, 1.0);
+
+            Self {
+                means,
+                log_scales,
+                quats,
+                sh_coeffs,
+                raw_opacity,
+                filter_3d: None,
+            }
+        }
+
+        fn tensors<B: Backend>(&self, device: &B::Device) -> GradCheckTensors<B> {
+            let num_points = self.raw_opacity.len();
+            let num_coeffs = self.sh_coeffs.len() / (num_points * 3);
+            GradCheckTensors {
+                means: Tensor::<B, 1>::from_floats(self.means.as_slice(), device)
+                    .reshape([num_points, 3]),
+                xy_dummy: Tensor::zeros([num_points, 2], device),
+                xy_norm_dummy: Tensor::zeros([num_points], device),
+                log_scales: Tensor::<B, 1>::from_floats(self.log_scales.as_slice(), device)
+                    .reshape([num_points, 3]),
+                quats: Tensor::<B, 1>::from_floats(self.quats.as_slice(), device)
+                    .reshape([num_points, 4]),
+                sh_coeffs: Tensor::<B, 1>::from_floats(self.sh_coeffs.as_slice(), device)
+                    .reshape([num_points, num_coeffs, 3]),
+                raw_opacity: Tensor::from_floats(self.raw_opacity.as_slice(), device),
+                filter_3d: self
+                    .filter_3d
+                    .as_ref()
+                    .map(|f| Tensor::from_floats(f.as_slice(), device)),
+            }
+        }
+    }
+
+    fn render_grad_check<B: Backend>(
+        tensors: &GradCheckTensors<B>,
+        cam: &Camera,
+        img_size: glam::UVec2,
+        resort: bool,
+    ) -> Tensor<B, 3> {
+        let (img, _) = B::render_splats(
+            cam,
+            img_size,
+            tensors.means.clone(),
+            tensors.xy_dummy.clone(),
+            tensors.xy_norm_dummy.clone(),
+            tensors.log_scales.clone(),
+            tensors.quats.clone(),
+            tensors.sh_coeffs.clone(),
+            tensors.raw_opacity.clone(),
+            None,
+            tensors.filter_3d.clone(),
+            glam::Vec3::ZERO,
+            RenderOptions {
+                resort,
+                ..Default::default()
+            },
+        );
+        img
+    }
+
+    fn assert_grads_close(name: &str, analytic: &[f64], numeric: &[f64]) {
+        let norm = numeric.iter().map(|n| n * n).sum::<f64>().sqrt();
+        let err = analytic
+            .iter()
+            .zip(numeric)
+            .map(|(a, n)| (a - n).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        assert!(
+            norm > 1e-6,
+            "{name}: finite differences are all zero, the scene doesn't exercise this input"
+        );
+        // The alpha cutoff isn't differentiable, so allow for some error.
+        assert!(
+            err <= 0.15 * norm,
+            "{name}: gradients {analytic:?} don't match finite differences {numeric:?}"
+        );
+    }
+
+    /// Compare the gradients of all inputs of [`Backend::render_splats`] to central finite
+    /// differences, on a small random scene. The loss is a random weighting of the
+    /// rendered image, so all channels and pixels matter. With `filter_3d`, the splats get a
+    /// random 3D filter, which isn't trained but changes the scales and opacities.
+    fn check_render_grads(cam: &Camera, sh_degree: u32, seed: u64, resort: bool, filter_3d: bool) {
+        let device = WgpuDevice::BestAvailable;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let img_size = glam::uvec2(32, 24);
+        let mut scene = GradCheckScene::random(16, sh_degree, &mut rng);
+        if filter_3d {
+            scene.filter_3d = Some(uniform_vec(&mut rng, 16, 0.05, 0.3));
+        }
+        let weights = uniform_vec(&mut rng, (img_size.x * img_size.y * 4) as usize, -1.0, 1.0);
+
+        // Sum the loss on the CPU in double precision, to keep the finite differences accurate.
+        let loss = |scene: &GradCheckScene, cam: &Camera| -> f64 {
+            let tensors = scene.tensors::<PrimaryBackend>(&device);
+            let img = render_grad_check(&tensors, cam, img_size, resort);
+            let img = img.into_data().to_vec::<f32>().unwrap();
+            img.iter()
+                .zip(&weights)
+                .map(|(&i, &w)| i as f64 * w as f64)
+                .sum()
+        };
+
+        let tensors = scene.tensors::<DiffBack>(&device);
+        let tensors = GradCheckTensors {
+            means: tensors.means.require_grad(),
+            xy_dummy: tensors.xy_dummy.require_grad(),
+            xy_norm_dummy: tensors.xy_norm_dummy.require_grad(),
+            log_scales: tensors.log_scales.require_grad(),
+            quats: tensors.quats.require_grad(),
+            sh_coeffs: tensors.sh_coeffs.require_grad(),
+            raw_opacity: tensors.raw_opacity.require_grad(),
+            filter_3d: tensors.filter_3d,
+        };
+        let img = render_grad_check(&tensors, cam, img_size, resort);
+        let weights_tensor = Tensor::<DiffBack, 1>::from_floats(weights.as_slice(), &device)
+            .reshape([img_size.y as usize, img_size.x as usize, 4]);
+        let grads = (img * weights_tensor).sum().backward();
+
+        fn grad_data<const D: usize>(
+            tensor: &Tensor<DiffBack, D>,
+            grads: &<DiffBack as burn::tensor::backend::AutodiffBackend>::Gradients,
+        ) -> Vec<f32> {
+            tensor
+                .grad(grads)
+                .expect("Input needs a gradient")
+                .into_data()
+                .to_vec::<f32>()
+                .unwrap()
+        }
+
+        type Field = fn(&mut GradCheckScene) -> &mut Vec<f32>;
+        let inputs: [(&str, Field, Vec<f32>); 5] = [
+            ("means", |s| &mut s.means, grad_data(&tensors.means, &grads)),
+            (
+                "log_scales",
+                |s| &mut s.log_scales,
+                grad_data(&tensors.log_scales, &grads),
+            ),
+            ("quats", |s| &mut s.quats, grad_data(&tensors.quats, &grads)),
+            (
+                "sh_coeffs",
+                |s| &mut s.sh_coeffs,
+                grad_data(&tensors.sh_coeffs, &grads),
+            ),
+            (
+                "raw_opacity",
+                |s| &mut s.raw_opacity,
+                grad_data(&tensors.raw_opacity, &grads),
+            ),
+        ];
+
+        let eps = 1e-3;
+        for (name, field, analytic_grads) in inputs {
+            let mut analytic = vec![];
+            let mut numeric = vec![];
+
+            for _ in 0..12 {
+                let index = rng.gen_range(0..analytic_grads.len());
+                let mut scene_pos = scene.clone();
+                field(&mut scene_pos)[index] += eps;
+                let mut scene_neg = scene.clone();
+                field(&mut scene_neg)[index] -= eps;
+
+                analytic.push(analytic_grads[index] as f64);
+                numeric.push((loss(&scene_pos, cam) - loss(&scene_neg, cam)) / (2.0 * eps as f64));
+            }
+            assert_grads_close(name, &analytic, &numeric);
+        }
+
+        // The xy dummy carries the gradient of the projected splat centers. Moving the
+        // pixel center moves all splats by the same number of pixels, so its derivative is
+        // the sum of the xy gradients.
+        let v_xy = grad_data(&tensors.xy_dummy, &grads);
+        let pixel_eps = 1e-2;
+        let mut analytic = vec![];
+        let mut numeric = vec![];
+        for axis in 0..2 {
+            let offset = glam::Vec2::AXES[axis] * pixel_eps / img_size.as_vec2();
+            let mut cam_pos = cam.clone();
+            cam_pos.center_uv += offset;
+            let mut cam_neg = cam.clone();
+            cam_neg.center_uv -= offset;
+
+            analytic.push(v_xy.chunks_exact(2).map(|xy| xy[axis] as f64).sum());
+            numeric
+                .push((loss(&scene, &cam_pos) - loss(&scene, &cam_neg)) / (2.0 * pixel_eps as f64));
+        }
+        assert_grads_close("xy_dummy", &analytic, &numeric);
+
+        // The xy norm is the length of the xy gradient in normalized screen coordinates.
+        let v_xy_norm = grad_data(&tensors.xy_norm_dummy, &grads);
+        for (xy, norm) in v_xy.chunks_exact(2).zip(v_xy_norm) {
+            let expected = (glam::vec2(xy[0], xy[1]) * img_size.as_vec2() / 2.0).length();
+            assert_approx_eq!(norm, expected, 1e-4 * expected.max(1.0));
+        }
+    }
+
+    #[test]
+    fn grads_match_finite_differences() {
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.6),
+            glam::vec2(0.5, 0.5),
+        );
+        check_render_grads(&cam, 1, 0, false, false);
+    }
+
+    #[test]
+    fn fisheye_grads_match_finite_differences() {
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.6),
+            glam::vec2(0.5, 0.5),
+        )
+        .with_model(CameraModel::Fisheye {
+            distortion: glam::vec4(0.05, -0.01, 0.0, 0.0),
+        });
+        check_render_grads(&cam, 0, 1, false, false);
+    }
+
+    #[test]
+    fn orthographic_grads_match_finite_differences() {
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.6),
+            glam::vec2(0.5, 0.5),
+        )
+        .with_model(CameraModel::Orthographic {
+            extent: glam::vec2(2.8, 2.1),
+        });
+        check_render_grads(&cam, 1, 4, false, false);
+    }
+
+    #[test]
+    fn resorted_grads_match_finite_differences() {
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.6),
+            glam::vec2(0.5, 0.5),
+        );
+        check_render_grads(&cam, 1, 2, true, false);
+    }
+
+    #[test]
+    fn filtered_grads_match_finite_differences() {
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.6),
+            glam::vec2(0.5, 0.5),
+        );
+        check_render_grads(&cam, 1, 3, false, true);
+    }
+
+    #[test]
+    fn resort_orders_by_ray_depth() {
+        let device = WgpuDevice::BestAvailable;
+
+        // A large flat splat, tilted such that its center is behind a small splat, but its
+        // surface is in front of it along the center ray.
+        let tangent = glam::vec3(-1.0, 0.0, -1.5).normalize();
+        let normal = tangent.cross(glam::Vec3::Y);
+        let rotation =
+            glam::Quat::from_mat3(&glam::Mat3::from_cols(tangent, glam::Vec3::Y, normal));
+
+        let means: [[f32; 3]; 2] = [[1.0, 0.0, 6.0], [0.0, 0.0, 5.0]];
+        let log_scales =
+            [glam::vec3(3.0, 3.0, 0.01), glam::Vec3::splat(0.05)].map(|s| s.ln().to_array());
+        let quats = [
+            [rotation.w, rotation.x, rotation.y, rotation.z],
+            [1.0, 0.0, 0.0, 0.0],
+        ];
+        // Green for the flat splat, red for the small splat.
+        let colors = [glam::vec3(0.0, 1.0, 0.0), glam::vec3(1.0, 0.0, 0.0)]
+            .map(|c| ((c - 0.5) / SH_C0).to_array());
+
+        let splats = Splats::<PrimaryBackend>::from_data(
+            Tensor::<PrimaryBackend, 1>::from_floats(means.concat().as_slice(), &device)
+                .reshape([2, 3]),
+            Tensor::<PrimaryBackend, 1>::from_floats(colors.concat().as_slice(), &device)
+                .reshape([2, 1, 3]),
+            Tensor::<PrimaryBackend, 1>::from_floats(quats.concat().as_slice(), &device)
+                .reshape([2, 4]),
+            Tensor::from_floats([5.0, 5.0], &device),
+            Tensor::<PrimaryBackend, 1>::from_floats(log_scales.concat().as_slice(), &device)
+                .reshape([2, 3]),
+            &device,
+        );
+
+        let cam = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(32, 32);
+        let center_pixel = |img: Tensor<PrimaryBackend, 3>| {
+            let pixel = img
+                .slice([16..17, 16..17, 0..3])
+                .into_data()
+                .to_vec::<f32>()
+                .unwrap();
+            glam::vec3(pixel[0], pixel[1], pixel[2])
+        };
+
+        let (sorted, aux) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let sorted = center_pixel(sorted);
+        assert!(
+            sorted.x > sorted.y,
+            "Expected the small splat in front, got {sorted}"
+        );
+        let picked = task::block_on(aux.pick_pixel(glam::uvec2(16, 16)));
+        assert_eq!(picked[0].global_gid, 1);
+
+        let (resorted, aux) = splats.render_with(
+            &cam,
+            img_size,
+            glam::Vec3::ZERO,
+            RenderOptions {
+                resort: true,
+                ..Default::default()
+            },
+        );
+        let resorted = center_pixel(resorted);
+        assert!(
+            resorted.y > resorted.x,
+            "Expected the flat splat in front, got {resorted}"
+        );
+
+        // Picking follows the same order.
+        let picked = task::block_on(aux.pick_pixel(glam::uvec2(16, 16)));
+        assert_eq!(picked[0].global_gid, 0);
+    }
 }
@@ -0,0 +1,1180 @@
+//! A CPU implementation of the splat rasterizer.
+//!
+//! This mirrors the wgsl kernels step by step (projection, depth sort, tile binning,
+//! rasterization and the backward pass), so it can serve as a ground truth for the GPU
+//! implementation in tests.
+//!
+//! It only covers the core rasterizer: all camera models and the near/far planes, but not
+//! the 3D filter, splat flags, clip volumes or per pixel resorting, so it can only be compared
+//! to GPU renders that don't use those. It also isn't wired in as a fallback, rendering
+//! still needs a GPU adapter.
+//!
+//! Nb: Some quirks of the kernels are deliberately kept, eg. the forward and backward
+//! pass clamp alpha differently, to stay numerically close to the GPU results.
+use glam::{Mat2, Mat3, Mat4, UVec2, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+
+use crate::{
+    camera::{Camera, CameraModel},
+    gaussian_splats::Splats,
+    render::sh_degree_from_coeffs,
+    sh::sh_basis,
+    shaders::helpers::{
+        CameraUniforms, SplatRayDepth, CAMERA_FISHEYE, CAMERA_ORTHOGRAPHIC, COV_BLUR,
+        RESORT_QUEUE_SIZE, TILE_WIDTH,
+    },
+    Backend,
+};
+
+/// Splat data in flat buffers, laid out like the tensors passed to [`Backend::render_splats`].
+#[derive(Clone, Debug, Default)]
+pub struct CpuSplats {
+    /// [N, 3]
+    pub means: Vec<f32>,
+    /// [N, 3]
+    pub log_scales: Vec<f32>,
+    /// [N, 4], stored as [w, x, y, z].
+    pub quats: Vec<f32>,
+    /// [N, C, 3]
+    pub sh_coeffs: Vec<f32>,
+    /// [N]
+    pub raw_opacities: Vec<f32>,
+}
+
+impl CpuSplats {
+    pub async fn from_splats<B: Backend>(splats: &Splats<B>) -> anyhow::Result<Self> {
+        let read = |data: burn::tensor::TensorData| {
+            data.to_vec::<f32>()
+                .map_err(|_| anyhow::anyhow!("Failed to read splat data"))
+        };
+
+        Ok(Self {
+            means: read(splats.means.val().into_data_async().await)?,
+            log_scales: read(splats.log_scales.val().into_data_async().await)?,
+            quats: read(splats.rotation.val().into_data_async().await)?,
+            sh_coeffs: read(splats.sh_coeffs.val().into_data_async().await)?,
+            raw_opacities: read(splats.raw_opacity.val().into_data_async().await)?,
+        })
+    }
+
+    pub fn num_splats(&self) -> usize {
+        self.raw_opacities.len()
+    }
+
+    pub fn num_coeffs(&self) -> usize {
+        self.sh_coeffs.len() / (self.num_splats() * 3).max(1)
+    }
+
+    pub fn sh_degree(&self) -> u32 {
+        sh_degree_from_coeffs(self.num_coeffs() as u32)
+    }
+
+    fn mean(&self, gid: usize) -> Vec3 {
+        Vec3::from_slice(&self.means[gid * 3..])
+    }
+
+    fn scale(&self, gid: usize) -> Vec3 {
+        Vec3::from_slice(&self.log_scales[gid * 3..]).exp()
+    }
+
+    fn quat(&self, gid: usize) -> Vec4 {
+        Vec4::from_slice(&self.quats[gid * 4..])
+    }
+
+    fn opacity(&self, gid: usize) -> f32 {
+        sigmoid(self.raw_opacities[gid])
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuProjectedSplat {
+    pub xy: Vec2,
+    pub conic: Vec3,
+    pub color: Vec4,
+}
+
+/// Intermediate results of the forward pass, matching the buffers in [`crate::RenderAux`].
+#[derive(Clone, Debug, Default)]
+pub struct CpuRenderAux {
+    pub projected_splats: Vec<CpuProjectedSplat>,
+    pub global_from_compact_gid: Vec<u32>,
+    pub compact_gid_from_isect: Vec<u32>,
+    /// [tiles_y, tiles_x] ranges of intersections per tile.
+    pub tile_bins: Vec<[u32; 2]>,
+    /// [H, W] index of the last intersection that contributed to a pixel.
+    pub final_index: Vec<u32>,
+}
+
+impl CpuRenderAux {
+    pub fn num_visible(&self) -> usize {
+        self.global_from_compact_gid.len()
+    }
+
+    pub fn num_intersections(&self) -> usize {
+        self.compact_gid_from_isect.len()
+    }
+}
+
+/// Gradients of the splat parameters, laid out like the [`CpuSplats`] buffers.
+#[derive(Clone, Debug, Default)]
+pub struct CpuSplatGrads {
+    pub v_means: Vec<f32>,
+    pub v_log_scales: Vec<f32>,
+    pub v_quats: Vec<f32>,
+    pub v_coeffs: Vec<f32>,
+    pub v_raw_opacities: Vec<f32>,
+    /// [N, 2] screenspace gradients, as tracked by the xy dummy input.
+    pub v_xy: Vec<f32>,
+    /// [N] screenspace gradient norm, as tracked by the xy norm dummy input.
+    pub v_xy_norm: Vec<f32>,
+}
+
+// Mirrors the RenderUniforms and CameraUniforms.
+struct Uniforms {
+    viewmat: Mat4,
+    focal: Vec2,
+    img_size: UVec2,
+    tile_bounds: UVec2,
+    pixel_center: Vec2,
+    background: Vec3,
+    sh_degree: u32,
+    camera_model: CameraModel,
+    near: f32,
+    far: f32,
+}
+
+impl Uniforms {
+    fn new(camera: &Camera, img_size: UVec2, background: Vec3, sh_degree: u32) -> Self {
+        Self {
+            viewmat: camera.world_to_local(),
+            focal: camera.focal(img_size),
+            img_size,
+            tile_bounds: UVec2::new(
+                img_size.x.div_ceil(TILE_WIDTH),
+                img_size.y.div_ceil(TILE_WIDTH),
+            ),
+            pixel_center: camera.center(img_size),
+            background,
+            sh_degree,
+            camera_model: camera.model,
+            near: camera.near,
+            far: camera.far,
+        }
+    }
+
+    fn rotation(&self) -> Mat3 {
+        Mat3::from_mat4(self.viewmat)
+    }
+
+    fn to_view(&self, mean: Vec3) -> Vec3 {
+        self.rotation() * mean + self.viewmat.w_axis.xyz()
+    }
+
+    fn is_ortho(&self) -> bool {
+        matches!(self.camera_model, CameraModel::Orthographic { .. })
+    }
+
+    fn fisheye_distortion(&self) -> Option<Vec4> {
+        match self.camera_model {
+            CameraModel::Fisheye { distortion } => Some(distortion),
+            _ => None,
+        }
+    }
+
+    fn sort_depth(&self, p_view: Vec3) -> f32 {
+        if self.fisheye_distortion().is_some() {
+            return p_view.length();
+        }
+        p_view.z
+    }
+
+    fn viewdir(&self, mean: Vec3) -> Vec3 {
+        if self.is_ortho() {
+            return self.rotation().row(2);
+        }
+        let camera_pos = -(self.rotation().transpose() * self.viewmat.w_axis.xyz());
+        (mean - camera_pos).normalize()
+    }
+
+    fn project_pix(&self, p_view: Vec3) -> Vec2 {
+        if self.is_ortho() {
+            return p_view.xy() * self.focal + self.pixel_center;
+        }
+        if let Some(k) = self.fisheye_distortion() {
+            return p_view.xy() * fisheye_scale(p_view, k).x * self.focal + self.pixel_center;
+        }
+        p_view.xy() / (p_view.z + 1e-6) * self.focal + self.pixel_center
+    }
+
+    fn project_pix_vjp(&self, p_view: Vec3, v_xy: Vec2) -> Vec3 {
+        let v_proj = self.focal * v_xy;
+        if self.is_ortho() {
+            return v_proj.extend(0.0);
+        }
+        if let Some(k) = self.fisheye_distortion() {
+            return fisheye_jacobian(self.focal, p_view, k).transpose() * v_xy.extend(0.0);
+        }
+        let rw = 1.0 / (p_view.z + 1e-6);
+        Vec3::new(
+            v_proj.x * rw,
+            v_proj.y * rw,
+            -(v_proj.x * p_view.x + v_proj.y * p_view.y) * rw * rw,
+        )
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+// wgsl sign(), which unlike signum() is 0 for 0.
+fn sign(v: Vec2) -> Vec2 {
+    let sign = |x: f32| {
+        if x > 0.0 {
+            1.0
+        } else if x < 0.0 {
+            -1.0
+        } else {
+            0.0
+        }
+    };
+    Vec2::new(sign(v.x), sign(v.y))
+}
+
+fn quat_to_rotmat(quat: Vec4) -> Mat3 {
+    let [w, x, y, z] = quat.to_array();
+    Mat3::from_cols(
+        Vec3::new(
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y + w * z),
+            2.0 * (x * z - w * y),
+        ),
+        Vec3::new(
+            2.0 * (x * y - w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z + w * x),
+        ),
+        Vec3::new(
+            2.0 * (x * z + w * y),
+            2.0 * (y * z - w * x),
+            1.0 - 2.0 * (x * x + y * y),
+        ),
+    )
+}
+
+fn fisheye_theta_d(theta: f32, k: Vec4) -> f32 {
+    let t2 = theta * theta;
+    theta * (1.0 + t2 * (k.x + t2 * (k.y + t2 * (k.z + t2 * k.w))))
+}
+
+fn fisheye_theta_d_deriv(theta: f32, k: Vec4) -> f32 {
+    let t2 = theta * theta;
+    1.0 + t2 * (3.0 * k.x + t2 * (5.0 * k.y + t2 * (7.0 * k.z + t2 * 9.0 * k.w)))
+}
+
+// Returns the fisheye scale s = theta_d / r, ds/dr and ds/dz.
+fn fisheye_scale(p_view: Vec3, k: Vec4) -> Vec3 {
+    let r = p_view.xy().length();
+
+    if r < 1e-6 {
+        return Vec3::new(1.0 / p_view.z, 0.0, -1.0 / (p_view.z * p_view.z));
+    }
+
+    let theta = r.atan2(p_view.z);
+    let theta_d = fisheye_theta_d(theta, k);
+    let dtheta_d = fisheye_theta_d_deriv(theta, k);
+    let rho2 = r * r + p_view.z * p_view.z;
+
+    let s = theta_d / r;
+    let ds_dr = (dtheta_d * p_view.z / rho2 - s) / r;
+    let ds_dz = -dtheta_d / rho2;
+    Vec3::new(s, ds_dr, ds_dz)
+}
+
+fn fisheye_jacobian(focal: Vec2, p_view: Vec3, k: Vec4) -> Mat3 {
+    let scale = fisheye_scale(p_view, k);
+    let r = p_view.xy().length().max(1e-6);
+    let a = p_view.xy();
+    let d = scale.y / r;
+
+    Mat3::from_cols(
+        Vec3::new(
+            focal.x * (scale.x + a.x * a.x * d),
+            focal.y * a.y * a.x * d,
+            0.0,
+        ),
+        Vec3::new(
+            focal.x * a.x * a.y * d,
+            focal.y * (scale.x + a.y * a.y * d),
+            0.0,
+        ),
+        Vec3::new(focal.x * a.x * scale.z, focal.y * a.y * scale.z, 0.0),
+    )
+}
+
+// Central differences of the Jacobian, like the kernel.
+fn fisheye_jacobian_vjp(focal: Vec2, p_view: Vec3, k: Vec4, v_j: Mat3) -> Vec3 {
+    let h = (p_view.length() * 1e-3).max(1e-5);
+    let mut v_p = Vec3::ZERO;
+
+    for i in 0..3 {
+        let mut dp = Vec3::ZERO;
+        dp[i] = h;
+        let dj_0 = fisheye_jacobian(focal, p_view - dp, k);
+        let dj_1 = fisheye_jacobian(focal, p_view + dp, k);
+
+        let v = (0..3)
+            .map(|c| v_j.col(c).dot(dj_1.col(c) - dj_0.col(c)))
+            .sum::<f32>();
+        v_p[i] = v / (2.0 * h);
+    }
+    v_p
+}
+
+fn calc_cov2d(uniforms: &Uniforms, p_view: Vec3, scale: Vec3, quat: Vec4) -> Vec3 {
+    let focal = uniforms.focal;
+
+    let mut m = quat_to_rotmat(quat);
+    m.x_axis *= scale.x;
+    m.y_axis *= scale.y;
+    m.z_axis *= scale.z;
+    let v = m * m.transpose();
+
+    let j = if uniforms.is_ortho() {
+        Mat3::from_diagonal(focal.extend(0.0))
+    } else if let Some(k) = uniforms.fisheye_distortion() {
+        fisheye_jacobian(focal, p_view, k)
+    } else {
+        let img_size = uniforms.img_size.as_vec2();
+        let tan_fov = 0.5 * img_size / focal;
+
+        let lims_neg = uniforms.pixel_center / focal + 0.3 * tan_fov;
+        let lims_pos = (img_size - uniforms.pixel_center) / focal + 0.3 * tan_fov;
+
+        // Get ndc coords +- clipped to the frustum.
+        let t = p_view.z * (p_view.xy() / p_view.z).max(-lims_neg).min(lims_pos);
+
+        Mat3::from_cols(
+            Vec3::new(focal.x, 0.0, 0.0),
+            Vec3::new(0.0, focal.y, 0.0),
+            (-focal * t / p_view.z).extend(0.0),
+        ) * (1.0 / p_view.z)
+    };
+
+    let t = j * uniforms.rotation();
+    let cov = t * v * t.transpose();
+
+    Vec3::new(
+        cov.x_axis.x + COV_BLUR,
+        cov.x_axis.y,
+        cov.y_axis.y + COV_BLUR,
+    )
+}
+
+fn cov_to_conic(cov2d: Vec3) -> Vec3 {
+    let det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
+    Vec3::new(cov2d.z, -cov2d.y, cov2d.x) / det
+}
+
+fn radius_from_conic(conic: Vec3) -> u32 {
+    let det = 1.0 / (conic.x * conic.z - conic.y * conic.y);
+    let cov2d = Vec3::new(conic.z, -conic.y, conic.x) * det;
+    let b = 0.5 * (cov2d.x + cov2d.z);
+    let v1 = b + (b * b - det).max(0.1).sqrt();
+    let v2 = b - (b * b - det).max(0.1).sqrt();
+    let radius = 3.0 * v1.max(v2).max(0.0).sqrt();
+    radius.ceil() as u32
+}
+
+// Returns the [min, max) tile range covered by a splat.
+fn get_tile_bbox(xy: Vec2, radius: u32, tile_bounds: UVec2) -> (UVec2, UVec2) {
+    let tile_center = xy / TILE_WIDTH as f32;
+    let tile_radius = Vec2::splat(radius as f32 / TILE_WIDTH as f32);
+    let bounds = tile_bounds.as_ivec2();
+    let min = (tile_center - tile_radius)
+        .as_ivec2()
+        .clamp(glam::IVec2::ZERO, bounds);
+    let max = (tile_center + tile_radius + 1.0)
+        .as_ivec2()
+        .clamp(glam::IVec2::ZERO, bounds);
+    (min.as_uvec2(), max.as_uvec2())
+}
+
+fn quad_form(v: Vec2, m: Mat2) -> f32 {
+    v.dot(m * v)
+}
+
+fn check_edge(p1: Vec2, p2: Vec2, center: Vec2, conic: Mat2) -> bool {
+    let edge = p2 - p1;
+    let f = p1 - center;
+    let a = quad_form(edge, conic);
+    let b = 2.0 * f.dot(conic * edge);
+    let c = quad_form(f, conic) - 1.0;
+    let discriminant = b * b - 4.0 * a * c;
+
+    if discriminant < 0.0 {
+        return false;
+    }
+
+    let sqrt_discriminant = discriminant.sqrt();
+    let t1 = (-b - sqrt_discriminant) / (2.0 * a);
+    let t2 = (-b + sqrt_discriminant) / (2.0 * a);
+    (0.0..=1.0).contains(&t1) || (0.0..=1.0).contains(&t2)
+}
+
+fn ellipse_intersects_aabb(box_pos: Vec2, box_extent: Vec2, center: Vec2, conic: Mat2) -> bool {
+    let d = center - box_pos;
+
+    if d.abs().cmple(box_extent).all() {
+        return true;
+    }
+
+    let corner_sign = sign(d);
+    let nearest_corner = box_pos + corner_sign * box_extent;
+
+    if quad_form(nearest_corner - center, conic) <= 1.0 {
+        return true;
+    }
+
+    let edge1_end = nearest_corner - Vec2::new(corner_sign.x * 2.0 * box_extent.x, 0.0);
+    let edge2_end = nearest_corner - Vec2::new(0.0, corner_sign.y * 2.0 * box_extent.y);
+
+    check_edge(nearest_corner, edge1_end, center, conic)
+        || check_edge(nearest_corner, edge2_end, center, conic)
+}
+
+fn can_be_visible(tile: UVec2, xy: Vec2, conic: Vec3, opac: f32) -> bool {
+    let sigma = (opac * 255.0).ln();
+    if sigma <= 0.0 {
+        return false;
+    }
+    let conic_scaled = conic / (2.0 * sigma);
+    let tile_extent = Vec2::splat(TILE_WIDTH as f32) / 2.0;
+    let tile_center = (tile * TILE_WIDTH).as_vec2() + tile_extent;
+    let conic = Mat2::from_cols(conic_scaled.xy(), conic_scaled.yz());
+    ellipse_intersects_aabb(tile_center, tile_extent, xy, conic)
+}
+
+fn calc_sigma(delta: Vec2, conic: Vec3) -> f32 {
+    0.5 * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y
+}
+
+/// Opacity of a splat at a pixel as blended by the rasterizer, or zero if it's skipped. Same
+/// as `splat_alpha` in the shaders.
+pub(crate) fn splat_alpha(xy: Vec2, conic: Vec3, opacity: f32, pixel_coord: Vec2) -> f32 {
+    let sigma = calc_sigma(xy - pixel_coord, conic);
+    let alpha = (opacity * (-sigma).exp()).min(0.999);
+
+    if sigma < 0.0 || alpha < 1.0 / 255.0 {
+        return 0.0;
+    }
+    alpha
+}
+
+/// View space origin and direction of the ray through a pixel, as `pixel_ray_origin` and
+/// `pixel_ray_dir` in the shaders.
+pub(crate) fn pixel_ray(camera: &CameraUniforms, pixel_coord: Vec2) -> (Vec3, Vec3) {
+    let focal = Vec2::from(camera.focal);
+    let uv = (pixel_coord - Vec2::from(camera.pixel_center)) / focal;
+
+    match camera.camera_model {
+        CAMERA_ORTHOGRAPHIC => (uv.extend(0.0), Vec3::Z),
+        CAMERA_FISHEYE => {
+            let theta_d = uv.length();
+            if theta_d < 1e-6 {
+                return (Vec3::ZERO, Vec3::Z);
+            }
+            let k = Vec4::from(camera.distortion);
+            let mut theta = theta_d;
+            for _ in 0..4 {
+                theta -= (fisheye_theta_d(theta, k) - theta_d) / fisheye_theta_d_deriv(theta, k);
+            }
+            (Vec3::ZERO, (theta.sin() * uv / theta_d).extend(theta.cos()))
+        }
+        _ => (Vec3::ZERO, uv.extend(1.0)),
+    }
+}
+
+/// Depth along a ray where a splat contributes most, as `ray_max_depth` in the shaders.
+pub(crate) fn ray_max_depth(ray_origin: Vec3, ray_dir: Vec3, splat: &SplatRayDepth) -> f32 {
+    let inv_cov = Mat3::from_cols(
+        Vec3::new(splat.inv_xx, splat.inv_xy, splat.inv_xz),
+        Vec3::new(splat.inv_xy, splat.inv_yy, splat.inv_yz),
+        Vec3::new(splat.inv_xz, splat.inv_yz, splat.inv_zz),
+    );
+    let inv_mean = Vec3::new(splat.inv_mean_x, splat.inv_mean_y, splat.inv_mean_z);
+    let inv_dir = inv_cov * ray_dir;
+    (ray_dir.dot(inv_mean) - inv_dir.dot(ray_origin)) / ray_dir.dot(inv_dir)
+}
+
+/// The queue of splats a pixel holds back when resorting, as `resort_push` in the shaders.
+#[derive(Default)]
+pub(crate) struct ResortQueue {
+    // Sorted by depth, stable for equal depths.
+    queue: Vec<(f32, u32)>,
+}
+
+impl ResortQueue {
+    /// Push a splat, returning the splat to blend next once the queue is full.
+    pub(crate) fn push(&mut self, depth: f32, id: u32) -> Option<u32> {
+        let full = self.queue.len() == RESORT_QUEUE_SIZE as usize;
+
+        if full && depth < self.queue[0].0 {
+            return Some(id);
+        }
+
+        let front = full.then(|| self.queue.remove(0).1);
+        let index = self.queue.partition_point(|&(d, _)| d <= depth);
+        self.queue.insert(index, (depth, id));
+        front
+    }
+
+    /// The splats still held back, nearest first.
+    pub(crate) fn into_remaining(self) -> impl Iterator<Item = u32> {
+        self.queue.into_iter().map(|(_, id)| id)
+    }
+}
+
+/// Render splats on the CPU, returning an [H, W, 4] image and the intermediate buffers
+/// needed for [`render_backward_cpu`].
+pub fn render_forward_cpu(
+    camera: &Camera,
+    img_size: UVec2,
+    splats: &CpuSplats,
+    background: Vec3,
+) -> (Vec<f32>, CpuRenderAux) {
+    let uniforms = Uniforms::new(camera, img_size, background, splats.sh_degree());
+    let num_coeffs = splats.num_coeffs();
+
+    // ProjectSplats: cull splats, and sort the rest by depth.
+    let mut visible: Vec<(f32, u32)> = (0..splats.num_splats())
+        .filter_map(|gid| {
+            let p_view = uniforms.to_view(splats.mean(gid));
+            let depth = uniforms.sort_depth(p_view);
+            if depth <= uniforms.near || depth >= uniforms.far {
+                return None;
+            }
+            let cov2d = calc_cov2d(&uniforms, p_view, splats.scale(gid), splats.quat(gid));
+            let det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
+            if det == 0.0 {
+                return None;
+            }
+            let conic = cov_to_conic(cov2d);
+            let xy = uniforms.project_pix(p_view);
+            let (tile_min, tile_max) =
+                get_tile_bbox(xy, radius_from_conic(conic), uniforms.tile_bounds);
+            if tile_max.x == tile_min.x || tile_max.y == tile_min.y {
+                return None;
+            }
+            Some((depth, gid as u32))
+        })
+        .collect();
+    // Stable sort, so equal depths stay in order of their global id.
+    visible.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let global_from_compact_gid: Vec<u32> = visible.into_iter().map(|(_, gid)| gid).collect();
+
+    // ProjectVisible & MapGaussiansToIntersect.
+    let mut projected_splats = Vec::with_capacity(global_from_compact_gid.len());
+    let mut isects: Vec<(u32, u32)> = vec![];
+
+    for (compact_gid, &global_gid) in global_from_compact_gid.iter().enumerate() {
+        let gid = global_gid as usize;
+        let mean = splats.mean(gid);
+        let opac = splats.opacity(gid);
+        let p_view = uniforms.to_view(mean);
+        let cov2d = calc_cov2d(&uniforms, p_view, splats.scale(gid), splats.quat(gid));
+        let conic = cov_to_conic(cov2d);
+        let xy = uniforms.project_pix(p_view);
+
+        let basis = sh_basis(uniforms.sh_degree, uniforms.viewdir(mean));
+        let coeffs = &splats.sh_coeffs[gid * num_coeffs * 3..(gid + 1) * num_coeffs * 3];
+        let color = basis
+            .iter()
+            .zip(coeffs.chunks_exact(3))
+            .fold(Vec3::ZERO, |acc, (b, c)| acc + *b * Vec3::from_slice(c))
+            + 0.5;
+
+        projected_splats.push(CpuProjectedSplat {
+            xy,
+            conic,
+            color: color.extend(opac),
+        });
+
+        let (tile_min, tile_max) =
+            get_tile_bbox(xy, radius_from_conic(conic), uniforms.tile_bounds);
+        for ty in tile_min.y..tile_max.y {
+            for tx in tile_min.x..tile_max.x {
+                if can_be_visible(UVec2::new(tx, ty), xy, conic, opac) {
+                    isects.push((tx + ty * uniforms.tile_bounds.x, compact_gid as u32));
+                }
+            }
+        }
+    }
+
+    // Tile sort, stable to keep the depth order within a tile.
+    isects.sort_by_key(|&(tile_id, _)| tile_id);
+
+    let num_tiles = (uniforms.tile_bounds.x * uniforms.tile_bounds.y) as usize;
+    let mut tile_bins = vec![[0u32; 2]; num_tiles];
+    for (isect_id, &(tile_id, _)) in isects.iter().enumerate() {
+        let bin = &mut tile_bins[tile_id as usize];
+        if isect_id == 0 || isects[isect_id - 1].0 != tile_id {
+            bin[0] = isect_id as u32;
+        }
+        bin[1] = isect_id as u32 + 1;
+    }
+    let compact_gid_from_isect: Vec<u32> = isects.into_iter().map(|(_, gid)| gid).collect();
+
+    // Rasterize.
+    let (w, h) = (img_size.x as usize, img_size.y as usize);
+    let mut out_img = vec![0.0; w * h * 4];
+    let mut final_index = vec![0u32; w * h];
+
+    for y in 0..h {
+        for x in 0..w {
+            let tile_id = x / TILE_WIDTH as usize
+                + (y / TILE_WIDTH as usize) * uniforms.tile_bounds.x as usize;
+            let [start, end] = tile_bins[tile_id];
+            let pixel_coord = Vec2::new(x as f32, y as f32) + 0.5;
+
+            let mut t = 1.0;
+            let mut pix_out = Vec3::ZERO;
+            let mut final_idx = 0;
+
+            for isect_id in start..end {
+                let projected =
+                    projected_splats[compact_gid_from_isect[isect_id as usize] as usize];
+                let alpha = splat_alpha(
+                    projected.xy,
+                    projected.conic,
+                    projected.color.w,
+                    pixel_coord,
+                );
+
+                if alpha > 0.0 {
+                    let next_t = t * (1.0 - alpha);
+                    if next_t <= 1e-4 {
+                        break;
+                    }
+                    pix_out += projected.color.xyz() * alpha * t;
+                    t = next_t;
+                    final_idx = isect_id;
+                }
+            }
+
+            let pix_id = x + y * w;
+            let color = (pix_out + t * uniforms.background).extend(1.0 - t);
+            out_img[pix_id * 4..pix_id * 4 + 4].copy_from_slice(&color.to_array());
+            final_index[pix_id] = final_idx;
+        }
+    }
+
+    (
+        out_img,
+        CpuRenderAux {
+            projected_splats,
+            global_from_compact_gid,
+            compact_gid_from_isect,
+            tile_bins,
+            final_index,
+        },
+    )
+}
+
+fn quat_to_rotmat_vjp(quat: Vec4, v_r: Mat3) -> Vec4 {
+    let [w, x, y, z] = quat.to_array();
+    // Index as column, row like wgsl.
+    let r = |c: usize, r: usize| v_r.col(c)[r];
+
+    Vec4::new(
+        2.0 * (x * (r(1, 2) - r(2, 1)) + y * (r(2, 0) - r(0, 2)) + z * (r(0, 1) - r(1, 0))),
+        2.0 * (-2.0 * x * (r(1, 1) + r(2, 2))
+            + y * (r(0, 1) + r(1, 0))
+            + z * (r(0, 2) + r(2, 0))
+            + w * (r(1, 2) - r(2, 1))),
+        2.0 * (x * (r(0, 1) + r(1, 0)) - 2.0 * y * (r(0, 0) + r(2, 2))
+            + z * (r(1, 2) + r(2, 1))
+            + w * (r(2, 0) - r(0, 2))),
+        2.0 * (x * (r(0, 2) + r(2, 0)) + y * (r(1, 2) + r(2, 1)) - 2.0 * z * (r(0, 0) + r(1, 1))
+            + w * (r(0, 1) - r(1, 0))),
+    )
+}
+
+fn cov2d_to_conic_vjp(conic: Vec3, v_conic: Vec3) -> Vec3 {
+    let x = Mat2::from_cols(conic.xy(), conic.yz());
+    let g = Mat2::from_cols(
+        Vec2::new(v_conic.x, v_conic.y / 2.0),
+        Vec2::new(v_conic.y / 2.0, v_conic.z),
+    );
+    let v_sigma = x * g * x;
+    -Vec3::new(
+        v_sigma.x_axis.x,
+        v_sigma.y_axis.x + v_sigma.x_axis.y,
+        v_sigma.y_axis.y,
+    )
+}
+
+/// Calculates the gradients of the splat parameters given the gradient of the rendered image.
+///
+/// `out_img` and `aux` should be the results of [`render_forward_cpu`] with the same arguments.
+pub fn render_backward_cpu(
+    camera: &Camera,
+    img_size: UVec2,
+    splats: &CpuSplats,
+    background: Vec3,
+    out_img: &[f32],
+    aux: &CpuRenderAux,
+    v_output: &[f32],
+) -> CpuSplatGrads {
+    let uniforms = Uniforms::new(camera, img_size, background, splats.sh_degree());
+    let num_points = splats.num_splats();
+    let num_coeffs = splats.num_coeffs();
+    let num_visible = aux.num_visible();
+
+    // RasterizeBackwards.
+    let mut v_xys_local = vec![Vec2::ZERO; num_visible];
+    let mut v_conics = vec![Vec3::ZERO; num_visible];
+    let mut v_colors = vec![Vec4::ZERO; num_visible];
+
+    let (w, h) = (img_size.x as usize, img_size.y as usize);
+
+    for y in 0..h {
+        for x in 0..w {
+            let tile_id = x / TILE_WIDTH as usize
+                + (y / TILE_WIDTH as usize) * uniforms.tile_bounds.x as usize;
+            let [start, end] = aux.tile_bins[tile_id];
+            let pix_id = x + y * w;
+            let pixel_coord = Vec2::new(x as f32, y as f32) + 0.5;
+
+            let t_final = 1.0 - out_img[pix_id * 4 + 3];
+            let final_isect = aux.final_index[pix_id];
+            let v_out = Vec4::from_slice(&v_output[pix_id * 4..]);
+
+            let mut t = t_final;
+            let mut buffer = Vec3::ZERO;
+
+            for isect_id in (start..end).rev() {
+                if isect_id > final_isect {
+                    continue;
+                }
+
+                let compact_gid = aux.compact_gid_from_isect[isect_id as usize] as usize;
+                let projected = aux.projected_splats[compact_gid];
+                let conic = projected.conic;
+                let color = projected.color;
+
+                let delta = projected.xy - pixel_coord;
+                let sigma = calc_sigma(delta, conic);
+                let vis = (-sigma).exp();
+                let alpha = (color.w * vis).min(0.99);
+
+                if sigma >= 0.0 && alpha >= 1.0 / 255.0 {
+                    let ra = 1.0 / (1.0 - alpha);
+                    t *= ra;
+                    let fac = alpha * t;
+
+                    let mut v_alpha = (color.xyz() * t - buffer * ra).dot(v_out.xyz());
+                    v_alpha += t_final * ra * v_out.w;
+                    v_alpha -= (t_final * ra * uniforms.background).dot(v_out.xyz());
+
+                    buffer += color.xyz() * fac;
+
+                    let v_sigma = -color.w * vis * v_alpha;
+
+                    v_xys_local[compact_gid] += v_sigma
+                        * Vec2::new(
+                            conic.x * delta.x + conic.y * delta.y,
+                            conic.y * delta.x + conic.z * delta.y,
+                        );
+                    v_conics[compact_gid] += Vec3::new(
+                        0.5 * v_sigma * delta.x * delta.x,
+                        v_sigma * delta.x * delta.y,
+                        0.5 * v_sigma * delta.y * delta.y,
+                    );
+                    v_colors[compact_gid] += (fac * v_out.xyz()).extend(vis * v_alpha);
+                }
+            }
+        }
+    }
+
+    let mut grads = CpuSplatGrads {
+        v_means: vec![0.0; num_points * 3],
+        v_log_scales: vec![0.0; num_points * 3],
+        v_quats: vec![0.0; num_points * 4],
+        v_coeffs: vec![0.0; num_points * num_coeffs * 3],
+        v_raw_opacities: vec![0.0; num_points],
+        v_xy: vec![0.0; num_points * 2],
+        v_xy_norm: vec![0.0; num_points],
+    };
+
+    let focal = uniforms.focal;
+    let w_rot = uniforms.rotation();
+
+    for (compact_gid, &global_gid) in aux.global_from_compact_gid.iter().enumerate() {
+        let gid = global_gid as usize;
+        let mean = splats.mean(gid);
+
+        // GatherGrads.
+        let v_color = v_colors[compact_gid];
+        let basis = sh_basis(uniforms.sh_degree, uniforms.viewdir(mean));
+        for (i, b) in basis.iter().enumerate() {
+            let v_coeff = *b * v_color.xyz();
+            let base = (gid * num_coeffs + i) * 3;
+            grads.v_coeffs[base..base + 3].copy_from_slice(&v_coeff.to_array());
+        }
+
+        let opac = splats.opacity(gid);
+        grads.v_raw_opacities[gid] = v_color.w * opac * (1.0 - opac);
+
+        let v_xy = v_xys_local[compact_gid];
+        grads.v_xy[gid * 2..gid * 2 + 2].copy_from_slice(&v_xy.to_array());
+        grads.v_xy_norm[gid] = (v_xy * img_size.as_vec2() / 2.0).length();
+
+        // ProjectBackwards.
+        let scale = splats.scale(gid);
+        let quat = splats.quat(gid);
+        let p_view = uniforms.to_view(mean);
+        let mut v_mean = w_rot.transpose() * uniforms.project_pix_vjp(p_view, v_xy);
+
+        let cov2d = calc_cov2d(&uniforms, p_view, scale, quat);
+        let conic = cov_to_conic(cov2d);
+        let v_cov2d = cov2d_to_conic_vjp(conic, v_conics[compact_gid]);
+
+        let rz = 1.0 / p_view.z;
+        let rz2 = rz * rz;
+
+        let j = if uniforms.is_ortho() {
+            Mat3::from_diagonal(focal.extend(0.0))
+        } else if let Some(k) = uniforms.fisheye_distortion() {
+            fisheye_jacobian(focal, p_view, k)
+        } else {
+            Mat3::from_cols(
+                Vec3::new(focal.x * rz, 0.0, 0.0),
+                Vec3::new(0.0, focal.y * rz, 0.0),
+                Vec3::new(-focal.x * p_view.x * rz2, -focal.y * p_view.y * rz2, 0.0),
+            )
+        };
+
+        let r = quat_to_rotmat(quat);
+        let s = Mat3::from_diagonal(scale);
+        let m = r * s;
+        let v = m * m.transpose();
+
+        let v_cov = Mat3::from_cols(
+            Vec3::new(v_cov2d.x, 0.5 * v_cov2d.y, 0.0),
+            Vec3::new(0.5 * v_cov2d.y, v_cov2d.z, 0.0),
+            Vec3::ZERO,
+        );
+
+        let t = j * w_rot;
+        let v_v = t.transpose() * v_cov * t;
+        let v_t = v_cov * t * v.transpose() + v_cov.transpose() * t * v;
+
+        let v_j = v_t * w_rot.transpose();
+        let rz3 = rz2 * rz;
+        let v_tz = Vec3::new(
+            -focal.x * rz2 * v_j.z_axis.x,
+            -focal.y * rz2 * v_j.z_axis.y,
+            -focal.x * rz2 * v_j.x_axis.x + 2.0 * focal.x * p_view.x * rz3 * v_j.z_axis.x
+                - focal.y * rz2 * v_j.y_axis.y
+                + 2.0 * focal.y * p_view.y * rz3 * v_j.z_axis.y,
+        );
+        // The orthographic Jacobian doesn't depend on the mean.
+        if let Some(k) = uniforms.fisheye_distortion() {
+            v_mean += w_rot.transpose() * fisheye_jacobian_vjp(focal, p_view, k, v_j);
+        } else if !uniforms.is_ortho() {
+            v_mean += Vec3::new(
+                v_tz.dot(w_rot.x_axis),
+                v_tz.dot(w_rot.y_axis),
+                v_tz.dot(w_rot.z_axis),
+            );
+        }
+
+        // Symmetrize the covariance gradient, like the kernel does through the upper triangular elements.
+        let v_v_symm = (v_v + v_v.transpose()) * 0.5;
+        let v_m = 2.0 * v_v_symm * m;
+
+        let v_scale = Vec3::new(
+            r.x_axis.dot(v_m.x_axis),
+            r.y_axis.dot(v_m.y_axis),
+            r.z_axis.dot(v_m.z_axis),
+        );
+        let v_r = v_m * s;
+        let v_quat = quat_to_rotmat_vjp(quat, v_r);
+
+        grads.v_means[gid * 3..gid * 3 + 3].copy_from_slice(&v_mean.to_array());
+        grads.v_log_scales[gid * 3..gid * 3 + 3].copy_from_slice(&(v_scale * scale).to_array());
+        grads.v_quats[gid * 4..gid * 4 + 4].copy_from_slice(&v_quat.to_array());
+    }
+
+    grads
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use std::fs::File;
+    use std::io::Read;
+
+    use super::*;
+    use crate::{
+        bounding_box::BoundingBox,
+        camera::{focal_to_fov, fov_to_focal},
+        gaussian_splats::RandomSplatsConfig,
+        PrimaryBackend,
+    };
+    use anyhow::{Context, Result};
+    use async_std::task;
+    use burn::{backend::Autodiff, tensor::Tensor};
+    use burn_wgpu::WgpuDevice;
+    use rand::{rngs::StdRng, SeedableRng};
+    use safetensors::SafeTensors;
+
+    type DiffBack = Autodiff<PrimaryBackend>;
+
+    fn read_floats(tensors: &SafeTensors, name: &str) -> Result<Vec<f32>> {
+        Ok(tensors
+            .tensor(name)?
+            .data()
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+
+    fn assert_all_close(name: &str, values: &[f32], reference: &[f32], rtol: f32, atol: f32) {
+        assert_eq!(values.len(), reference.len(), "{name} has wrong length");
+        for (i, (v, r)) in values.iter().zip(reference).enumerate() {
+            assert!(
+                (v - r).abs() <= atol + rtol * r.abs(),
+                "{name} mismatch at {i}: {v} vs {r}"
+            );
+        }
+    }
+
+    fn reference_camera(w: u32, h: u32) -> Camera {
+        let focal = fov_to_focal(std::f32::consts::PI * 0.5, w);
+        Camera::new(
+            glam::vec3(0.0, 0.0, -8.0),
+            glam::Quat::IDENTITY,
+            glam::vec2(focal_to_fov(focal, w), focal_to_fov(focal, h)),
+            glam::vec2(0.5, 0.5),
+        )
+    }
+
+    #[test]
+    fn test_reference_cpu() -> Result<()> {
+        let crab_img = image::open("./test_cases/crab.png")?;
+        let crab: Vec<f32> = crab_img
+            .to_rgb8()
+            .into_raw()
+            .iter()
+            .map(|&b| b as f32 / 255.0)
+            .collect();
+
+        for path in ["tiny_case", "basic_case"] {
+            let mut buffer = Vec::new();
+            let _ =
+                File::open(format!("./test_cases/{path}.safetensors"))?.read_to_end(&mut buffer)?;
+            let tensors = SafeTensors::deserialize(&buffer)?;
+
+            let splats = CpuSplats {
+                means: read_floats(&tensors, "means")?,
+                log_scales: read_floats(&tensors, "scales")?,
+                quats: read_floats(&tensors, "quats")?,
+                sh_coeffs: read_floats(&tensors, "coeffs")?,
+                raw_opacities: read_floats(&tensors, "opacities")?,
+            };
+
+            let img_shape = tensors.tensor("out_img")?.shape().to_vec();
+            let (h, w) = (img_shape[0], img_shape[1]);
+            let img_size = glam::uvec2(w as u32, h as u32);
+            let cam = reference_camera(img_size.x, img_size.y);
+
+            let (out, aux) = render_forward_cpu(&cam, img_size, &splats, Vec3::ZERO);
+
+            let xys_ref = read_floats(&tensors, "xys")?;
+            let conics_ref = read_floats(&tensors, "conics")?;
+            for (projected, &gid) in aux
+                .projected_splats
+                .iter()
+                .zip(&aux.global_from_compact_gid)
+            {
+                let gid = gid as usize;
+                assert_all_close(
+                    "xys",
+                    &projected.xy.to_array(),
+                    &xys_ref[gid * 2..gid * 2 + 2],
+                    1e-5,
+                    1e-5,
+                );
+                assert_all_close(
+                    "conics",
+                    &projected.conic.to_array(),
+                    &conics_ref[gid * 3..gid * 3 + 3],
+                    1e-5,
+                    1e-6,
+                );
+            }
+
+            let out_rgb: Vec<f32> = out
+                .chunks_exact(4)
+                .flat_map(|c| [c[0], c[1], c[2]])
+                .collect();
+            assert_all_close(
+                "out_img",
+                &out_rgb,
+                &read_floats(&tensors, "out_img")?,
+                1e-5,
+                1e-4,
+            );
+
+            // Gradient of mean((out_rgb - crab)^2).
+            let norm = 2.0 / (h * w * 3) as f32;
+            let v_output: Vec<f32> = out
+                .chunks_exact(4)
+                .zip(crab.chunks_exact(3))
+                .flat_map(|(o, c)| {
+                    [
+                        (o[0] - c[0]) * norm,
+                        (o[1] - c[1]) * norm,
+                        (o[2] - c[2]) * norm,
+                        0.0,
+                    ]
+                })
+                .collect();
+
+            let grads =
+                render_backward_cpu(&cam, img_size, &splats, Vec3::ZERO, &out, &aux, &v_output);
+
+            let ref_grads = [
+                ("v_xy", &grads.v_xy),
+                ("v_opacities", &grads.v_raw_opacities),
+                ("v_coeffs", &grads.v_coeffs),
+                ("v_quats", &grads.v_quats),
+                ("v_scales", &grads.v_log_scales),
+                ("v_means", &grads.v_means),
+            ];
+            for (name, values) in ref_grads {
+                assert_all_close(name, values, &read_floats(&tensors, name)?, 1e-5, 1e-6);
+            }
+        }
+        Ok(())
+    }
+
+    fn compare_with_gpu(cam: &Camera) -> Result<()> {
+        let device = WgpuDevice::BestAvailable;
+        let mut rng = StdRng::seed_from_u64(4);
+        let splats = Splats::<DiffBack>::from_random_config(
+            RandomSplatsConfig::new()
+                .with_init_count(500)
+                .with_sh_degree(2),
+            BoundingBox::from_min_max(glam::vec3(-2.0, -2.0, 3.0), glam::vec3(2.0, 2.0, 7.0)),
+            &mut rng,
+            &device,
+        );
+
+        let img_size = glam::uvec2(64, 48);
+        let background = glam::vec3(0.1, 0.2, 0.3);
+
+        let (out, _) = splats.render(cam, img_size, background, false);
+        let grads = out.clone().mean().backward();
+
+        let cpu_splats = task::block_on(CpuSplats::from_splats(&splats))?;
+        let (cpu_out, cpu_aux) = render_forward_cpu(cam, img_size, &cpu_splats, background);
+
+        let read = |t: Tensor<PrimaryBackend, 1>| t.into_data().to_vec::<f32>().unwrap();
+        let gpu_out = read(out.inner().flatten(0, 2));
+        assert_all_close("out_img", &cpu_out, &gpu_out, 1e-4, 1e-5);
+
+        let v_output = vec![1.0 / cpu_out.len() as f32; cpu_out.len()];
+        let cpu_grads = render_backward_cpu(
+            cam,
+            img_size,
+            &cpu_splats,
+            background,
+            &cpu_out,
+            &cpu_aux,
+            &v_output,
+        );
+
+        let v_means = splats.means.grad(&grads).context("means grad")?;
+        let v_scales = splats.log_scales.grad(&grads).context("scales grad")?;
+        let v_quats = splats.rotation.grad(&grads).context("quats grad")?;
+        let v_coeffs = splats.sh_coeffs.grad(&grads).context("coeffs grad")?;
+        let v_opacities = splats.raw_opacity.grad(&grads).context("opacities grad")?;
+
+        // Gradients are summed in a different order on the GPU, so allow for some more error.
+        assert_all_close(
+            "v_means",
+            &cpu_grads.v_means,
+            &read(v_means.flatten(0, 1)),
+            1e-3,
+            1e-6,
+        );
+        assert_all_close(
+            "v_scales",
+            &cpu_grads.v_log_scales,
+            &read(v_scales.flatten(0, 1)),
+            1e-3,
+            1e-6,
+        );
+        assert_all_close(
+            "v_quats",
+            &cpu_grads.v_quats,
+            &read(v_quats.flatten(0, 1)),
+            1e-3,
+            1e-6,
+        );
+        assert_all_close(
+            "v_coeffs",
+            &cpu_grads.v_coeffs,
+            &read(v_coeffs.flatten(0, 2)),
+            1e-3,
+            1e-6,
+        );
+        assert_all_close(
+            "v_opacities",
+            &cpu_grads.v_raw_opacities,
+            &read(v_opacities),
+            1e-3,
+            1e-6,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn matches_gpu() -> Result<()> {
+        compare_with_gpu(&Camera::new(
+            Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        ))
+    }
+
+    #[test]
+    fn matches_gpu_orthographic() -> Result<()> {
+        let cam = Camera::new(
+            Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        )
+        .with_model(CameraModel::Orthographic {
+            extent: glam::vec2(4.0, 3.0),
+        });
+        compare_with_gpu(&cam)
+    }
+
+    #[test]
+    fn matches_gpu_fisheye() -> Result<()> {
+        let cam = Camera::new(
+            Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(2.0, 2.0),
+            glam::vec2(0.5, 0.5),
+        )
+        .with_model(CameraModel::Fisheye {
+            distortion: glam::vec4(0.05, -0.01, 0.0, 0.0),
+        });
+        compare_with_gpu(&cam)
+    }
+}
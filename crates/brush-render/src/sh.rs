@@ -0,0 +1,226 @@
+use glam::{Mat3, Vec3};
+
+use crate::render::sh_coeffs_for_degree;
+
+/// Evaluates the SH basis functions up to the given degree for a direction.
+///
+/// This mirrors the basis used in project_visible.wgsl, including its sign conventions,
+/// such that `sum(basis[i] * coeffs[i])` gives the same color as the shader (before the +0.5 offset).
+pub fn sh_basis(degree: u32, dir: Vec3) -> Vec<f32> {
+    let mut basis = Vec::with_capacity(sh_coeffs_for_degree(degree) as usize);
+    basis.push(0.282_094_8);
+
+    if degree == 0 {
+        return basis;
+    }
+
+    let Vec3 { x, y, z } = dir;
+
+    let f_tmp0a = 0.488_602_5;
+    basis.extend([-f_tmp0a * y, f_tmp0a * z, -f_tmp0a * x]);
+
+    if degree == 1 {
+        return basis;
+    }
+
+    let z2 = z * z;
+    let f_tmp0b = -1.092_548_4 * z;
+    let f_tmp1a = 0.546_274_2;
+    let f_c1 = x * x - y * y;
+    let f_s1 = 2.0 * x * y;
+    let p_sh6 = 0.946_174_7 * z2 - 0.315_391_57;
+    let p_sh7 = f_tmp0b * x;
+    let p_sh5 = f_tmp0b * y;
+    let p_sh8 = f_tmp1a * f_c1;
+    let p_sh4 = f_tmp1a * f_s1;
+    basis.extend([p_sh4, p_sh5, p_sh6, p_sh7, p_sh8]);
+
+    if degree == 2 {
+        return basis;
+    }
+
+    let f_tmp0c = -2.285_229 * z2 + 0.457_045_8;
+    let f_tmp1b = 1.445_305_7 * z;
+    let f_tmp2a = -0.590_043_6;
+    let f_c2 = x * f_c1 - y * f_s1;
+    let f_s2 = x * f_s1 + y * f_c1;
+    let p_sh12 = z * (1.865_881_7 * z2 - 1.119_529);
+    let p_sh13 = f_tmp0c * x;
+    let p_sh11 = f_tmp0c * y;
+    let p_sh14 = f_tmp1b * f_c1;
+    let p_sh10 = f_tmp1b * f_s1;
+    let p_sh15 = f_tmp2a * f_c2;
+    let p_sh9 = f_tmp2a * f_s2;
+    basis.extend([p_sh9, p_sh10, p_sh11, p_sh12, p_sh13, p_sh14, p_sh15]);
+
+    if degree == 3 {
+        return basis;
+    }
+
+    let f_tmp0d = z * (-4.683_326 * z2 + 2.007_139_6);
+    let f_tmp1c = 3.311_611_4 * z2 - 0.473_087_34;
+    let f_tmp2b = -1.770_130_8 * z;
+    let f_tmp3a = 0.625_835_7;
+    let f_c3 = x * f_c2 - y * f_s2;
+    let f_s3 = x * f_s2 + y * f_c2;
+    let p_sh20 = 1.984_313_5 * z * p_sh12 - 1.006_230_6 * p_sh6;
+    let p_sh21 = f_tmp0d * x;
+    let p_sh19 = f_tmp0d * y;
+    let p_sh22 = f_tmp1c * f_c1;
+    let p_sh18 = f_tmp1c * f_s1;
+    let p_sh23 = f_tmp2b * f_c2;
+    let p_sh17 = f_tmp2b * f_s2;
+    let p_sh24 = f_tmp3a * f_c3;
+    let p_sh16 = f_tmp3a * f_s3;
+    basis.extend([
+        p_sh16, p_sh17, p_sh18, p_sh19, p_sh20, p_sh21, p_sh22, p_sh23, p_sh24,
+    ]);
+
+    basis
+}
+
+// Solves A x = b for a small dense system in place, with partial pivoting.
+// A is row-major n x n, b is n x m, and is overwritten with the solution.
+fn solve_in_place(a: &mut [f64], b: &mut [f64], n: usize, m: usize) {
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&r1, &r2| a[r1 * n + col].abs().total_cmp(&a[r2 * n + col].abs()))
+            .expect("Empty system");
+
+        for k in 0..n {
+            a.swap(col * n + k, pivot * n + k);
+        }
+        for k in 0..m {
+            b.swap(col * m + k, pivot * m + k);
+        }
+
+        let diag = a[col * n + col];
+
+        for row in 0..n {
+            if row == col {
+                continue;
+            }
+            let factor = a[row * n + col] / diag;
+            for k in 0..n {
+                a[row * n + k] -= factor * a[col * n + k];
+            }
+            for k in 0..m {
+                b[row * m + k] -= factor * b[col * m + k];
+            }
+        }
+    }
+
+    for row in 0..n {
+        let diag = a[row * n + row];
+        for k in 0..m {
+            b[row * m + k] /= diag;
+        }
+    }
+}
+
+/// Computes the matrices that rotate SH coefficients, one for each band up to `degree`.
+///
+/// Each band `l` gets a row-major `(2l + 1) x (2l + 1)` matrix D, such that rotating the
+/// coefficients of that band as `c' = D c` gives a function `f'(rotation * dir) = f(dir)`.
+///
+/// These are the Wigner-D matrices expressed in the real SH basis of [`sh_basis`]. Rather than
+/// deriving them analytically for the sign conventions used here, they're fit from evaluating the
+/// basis at a fixed set of directions, which is exact up to float precision as the bands are closed
+/// under rotation.
+pub fn sh_rotation_matrices(rotation: Mat3, degree: u32) -> Vec<Vec<f32>> {
+    // Fibonacci sphere, so the directions are well spread out, and the fit is well conditioned.
+    let num_dirs = 64;
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    let dirs: Vec<Vec3> = (0..num_dirs)
+        .map(|i| {
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / num_dirs as f32;
+            let r = (1.0 - y * y).sqrt();
+            let theta = golden_angle * i as f32;
+            Vec3::new(theta.cos() * r, y, theta.sin() * r)
+        })
+        .collect();
+
+    let inv_rotation = rotation.transpose();
+    let basis: Vec<_> = dirs.iter().map(|&d| sh_basis(degree, d)).collect();
+    let basis_rotated: Vec<_> = dirs
+        .iter()
+        .map(|&d| sh_basis(degree, inv_rotation * d))
+        .collect();
+
+    (0..=degree)
+        .map(|l| {
+            let start = (l * l) as usize;
+            let n = (2 * l + 1) as usize;
+
+            // Least squares fit of A D = B where A & B are the basis values at the
+            // directions, and the inverse rotated directions.
+            let mut ata = vec![0.0f64; n * n];
+            let mut atb = vec![0.0f64; n * n];
+
+            for (a, b) in basis.iter().zip(&basis_rotated) {
+                let a = &a[start..start + n];
+                let b = &b[start..start + n];
+
+                for i in 0..n {
+                    for j in 0..n {
+                        ata[i * n + j] += a[i] as f64 * a[j] as f64;
+                        atb[i * n + j] += a[i] as f64 * b[j] as f64;
+                    }
+                }
+            }
+
+            solve_in_place(&mut ata, &mut atb, n, n);
+            atb.into_iter().map(|x| x as f32).collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    fn eval(coeffs: &[f32], degree: u32, dir: Vec3) -> f32 {
+        sh_basis(degree, dir)
+            .iter()
+            .zip(coeffs)
+            .map(|(b, c)| b * c)
+            .sum()
+    }
+
+    #[test]
+    fn rotated_sh_matches() {
+        let degree = 4;
+        let num_coeffs = sh_coeffs_for_degree(degree) as usize;
+        let coeffs: Vec<f32> = (0..num_coeffs)
+            .map(|i| (i as f32 * 1.37).sin() * 0.5)
+            .collect();
+
+        let rotation = Quat::from_euler(glam::EulerRot::YXZ, 0.3, -1.2, 2.1);
+        let rotation = Mat3::from_quat(rotation);
+
+        let mut rotated = vec![0.0; num_coeffs];
+        for (l, d) in sh_rotation_matrices(rotation, degree).iter().enumerate() {
+            let start = l * l;
+            let n = 2 * l + 1;
+            for i in 0..n {
+                rotated[start + i] = (0..n).map(|j| d[i * n + j] * coeffs[start + j]).sum();
+            }
+        }
+
+        for dir in [
+            Vec3::X,
+            Vec3::Y,
+            Vec3::Z,
+            Vec3::new(0.3, -0.5, 0.8).normalize(),
+            Vec3::new(-0.9, 0.1, -0.2).normalize(),
+        ] {
+            let original = eval(&coeffs, degree, dir);
+            let moved = eval(&rotated, degree, rotation * dir);
+            assert!(
+                (original - moved).abs() < 1e-4,
+                "SH rotation mismatch {original} vs {moved}"
+            );
+        }
+    }
+}
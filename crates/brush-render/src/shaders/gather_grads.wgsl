@@ -13,6 +13,10 @@
 @group(0) @binding(8) var<storage, read_write> v_xy_global: array<vec2f>;
 @group(0) @binding(9) var<storage, read_write> v_xy_norm: array<f32>;
 
+@group(0) @binding(10) var<storage, read> log_scales: array<helpers::PackedVec3>;
+@group(0) @binding(11) var<storage, read> filter_3d: array<f32>;
+@group(0) @binding(12) var<storage, read> cameras: array<helpers::CameraUniforms>;
+
 const SH_C0: f32 = 0.2820947917738781f;
 
 fn sh_coeffs_to_color_fast_vjp(
@@ -175,13 +179,15 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     // Load colors gradients.
     var v_color = v_colors[compact_gid];
 
-    // Convert RGB to global SH gradients.
+    // Convert RGB to global SH gradients. With multiple cameras, the gradients are written
+    // per camera, and summed afterwards.
     let global_gid = global_from_compact_gid[compact_gid];
+    let splat_id = helpers::splat_of_gid(global_gid, uniforms.total_splats);
+    let camera = cameras[helpers::camera_of_gid(global_gid, uniforms.total_splats)];
 
-    let mean = helpers::as_vec(means[global_gid]);
+    let mean = helpers::as_vec(means[splat_id]);
 
-    let camera_pos = uniforms.viewmat[3].xyz;
-    let viewdir = normalize(mean - camera_pos);
+    let viewdir = helpers::sh_view_dir(camera.camera_model, camera.viewmat, mean);
 
     let sh_degree = uniforms.sh_degree;
     let v_coeff = sh_coeffs_to_color_fast_vjp(sh_degree, viewdir, v_color.xyz);
@@ -223,8 +229,9 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     }
 
     // Transform alpha gradient to opacity gradient.
-    let raw_opac = raw_opacities[global_gid];
-    let v_opac = v_color.w * v_sigmoid(raw_opac);
+    let raw_opac = raw_opacities[splat_id];
+    let compensation = helpers::filter_compensation(exp(helpers::as_vec(log_scales[splat_id])), filter_3d[splat_id]);
+    let v_opac = v_color.w * compensation * v_sigmoid(raw_opac);
     v_opacs[global_gid] = v_opac;
 
     // Scatter the xy gradients, as later operations need them to be global.
@@ -4,26 +4,29 @@ const TILE_SIZE: u32 = TILE_WIDTH * TILE_WIDTH;
 
 const MAIN_WG: u32 = 256u;
 
+// Camera models, see CameraUniforms::camera_model.
+const CAMERA_PINHOLE: u32 = 0u;
+const CAMERA_ORTHOGRAPHIC: u32 = 1u;
+const CAMERA_FISHEYE: u32 = 2u;
+
+// Per splat flags, see RenderUniforms::has_flags.
+const SPLAT_HIDDEN: u32 = 1u;
+const SPLAT_SELECTED: u32 = 2u;
+const SPLAT_LOCKED: u32 = 4u;
+
 struct RenderUniforms {
-    // View matrix transform world to view position.
+    // Img resolution (w, h), shared by all cameras.
     // Offset 0.
-    viewmat: mat4x4f,
-    // Focal of camera (fx, fy)
-    // offset 64
-    focal: vec2f,
-    // Img resolution (w, h)
-    // offset 72
     img_size: vec2u,
-    // offset 80
+    // Number of tiles in the image of each camera. The tiles of the cameras in a batch
+    // follow each other, see camera_tile_id.
+    // offset 8
     tile_bounds: vec2u,
-    // Camera center (cx, cy).
-    // offset 88
-    pixel_center: vec2f,
     // Background color of the scene.
-    // Offset 96.
+    // Offset 16.
     background: vec4f,
     // Degree of sh coeffecients used.
-    // Offset 112
+    // Offset 32
     sh_degree: u32,
 #ifdef UNIFORM_WRITE
     // Number of visible gaussians, written by project_forward.
@@ -32,13 +35,66 @@ struct RenderUniforms {
     num_visible: atomic<u32>,
 #else
     // Number of visible gaussians.
-    // Offset 116
+    // Offset 36
     num_visible: u32,
 #endif
-    // Offset 120
+    // Offset 40
     total_splats: u32,
-    // Offset 124
-    padding: u32,
+    // Number of cameras rendered at once. The splats are projected once per camera,
+    // see camera_of_gid.
+    // Offset 44
+    num_cameras: u32,
+    // Number of planes splats are clipped against.
+    // Offset 48
+    num_clip_planes: u32,
+    // Whether a buffer of per splat flags is bound.
+    // Offset 52
+    has_flags: u32,
+    // Offset 56
+    pad_0: u32,
+    pad_1: u32,
+}
+
+// Everything that differs between the cameras of a batch.
+struct CameraUniforms {
+    // View matrix transform world to view position.
+    // Offset 0.
+    viewmat: mat4x4f,
+    // Focal of camera (fx, fy)
+    // offset 64
+    focal: vec2f,
+    // Camera center (cx, cy).
+    // offset 72
+    pixel_center: vec2f,
+    // Fisheye distortion coefficients (k1, k2, k3, k4).
+    // Offset 80
+    distortion: vec4f,
+    // Projection used by the camera, one of the CAMERA_ constants.
+    // Offset 96
+    camera_model: u32,
+    // Depth range of splats that are rendered.
+    // Offset 100
+    near: f32,
+    // Offset 104
+    far: f32,
+    // Offset 108
+    pad_0: u32,
+}
+
+// When rendering multiple cameras, the splats are projected once per camera. The global id
+// of a projected splat is then camera * total_splats + splat.
+fn camera_of_gid(global_gid: u32, total_splats: u32) -> u32 {
+    return global_gid / total_splats;
+}
+
+fn splat_of_gid(global_gid: u32, total_splats: u32) -> u32 {
+    return global_gid % total_splats;
+}
+
+// The tiles of each camera come after those of the previous camera, as if the images were
+// stacked vertically.
+fn camera_tile_id(camera: u32, tile: vec2u, tile_bounds: vec2u) -> u32 {
+    return tile.x + (tile.y + camera * tile_bounds.y) * tile_bounds.x;
 }
 
 // nb: this struct has a bunch of padding but that's probably fine.
@@ -58,6 +114,21 @@ fn create_projected_splat(xy: vec2f, conic: vec3f, color: vec4f) -> ProjectedSpl
     return ProjectedSplat(xy.x, xy.y, conic.x, conic.y, conic.z, color.r, color.g, color.b, color.a);
 }
 
+// Inverse of the view space covariance of a splat (upper triangle), and the inverse times
+// the view space mean. These give the depth along a ray where the splat contributes most,
+// see ray_max_depth.
+struct SplatRayDepth {
+    inv_mean_x: f32,
+    inv_mean_y: f32,
+    inv_mean_z: f32,
+    inv_xx: f32,
+    inv_xy: f32,
+    inv_xz: f32,
+    inv_yy: f32,
+    inv_yz: f32,
+    inv_zz: f32,
+}
+
 struct PackedVec3 {
     x: f32,
     y: f32,
@@ -118,33 +189,162 @@ fn scale_to_mat(scale: vec3f) -> mat3x3f {
     );
 }
 
-fn project_pix(fxfy: vec2f, p_view: vec3f, pp: vec2f) -> vec2f {
+// Kannala-Brandt fisheye mapping from the angle to the optical axis to the distorted angle.
+fn fisheye_theta_d(theta: f32, k: vec4f) -> f32 {
+    let t2 = theta * theta;
+    return theta * (1.0 + t2 * (k.x + t2 * (k.y + t2 * (k.z + t2 * k.w))));
+}
+
+fn fisheye_theta_d_deriv(theta: f32, k: vec4f) -> f32 {
+    let t2 = theta * theta;
+    return 1.0 + t2 * (3.0 * k.x + t2 * (5.0 * k.y + t2 * (7.0 * k.z + t2 * 9.0 * k.w)));
+}
+
+// Fisheye projection is p_view.xy * s, with s = theta_d / r. Returns s, ds/dr and ds/dz.
+fn fisheye_scale(p_view: vec3f, k: vec4f) -> vec3f {
+    let r = length(p_view.xy);
+
+    if r < 1e-6 {
+        // Near the optical axis this is a pinhole projection.
+        return vec3f(1.0 / p_view.z, 0.0, -1.0 / (p_view.z * p_view.z));
+    }
+
+    let theta = atan2(r, p_view.z);
+    let theta_d = fisheye_theta_d(theta, k);
+    let dtheta_d = fisheye_theta_d_deriv(theta, k);
+    let rho2 = r * r + p_view.z * p_view.z;
+
+    let s = theta_d / r;
+    let ds_dr = (dtheta_d * p_view.z / rho2 - s) / r;
+    let ds_dz = -dtheta_d / rho2;
+    return vec3f(s, ds_dr, ds_dz);
+}
+
+// Jacobian of the fisheye projection in pixels. This is the local affine approximation
+// used to project the splat covariance.
+fn fisheye_jacobian(focal: vec2f, p_view: vec3f, k: vec4f) -> mat3x3f {
+    let scale = fisheye_scale(p_view, k);
+    let r = max(length(p_view.xy), 1e-6);
+    let a = p_view.xy;
+    let d = scale.y / r;
+
+    return mat3x3f(
+        vec3f(focal.x * (scale.x + a.x * a.x * d), focal.y * a.y * a.x * d, 0.0),
+        vec3f(focal.x * a.x * a.y * d, focal.y * (scale.x + a.y * a.y * d), 0.0),
+        vec3f(focal.x * a.x * scale.z, focal.y * a.y * scale.z, 0.0),
+    );
+}
+
+fn project_pix(camera_model: u32, distortion: vec4f, fxfy: vec2f, p_view: vec3f, pp: vec2f) -> vec2f {
+    if camera_model == CAMERA_ORTHOGRAPHIC {
+        // For an orthographic camera, the focal is in pixels per world unit.
+        return p_view.xy * fxfy + pp;
+    }
+    if camera_model == CAMERA_FISHEYE {
+        return p_view.xy * fisheye_scale(p_view, distortion).x * fxfy + pp;
+    }
     let p_proj = p_view.xy / (p_view.z + 1e-6f);
     return p_proj * fxfy + pp;
 }
 
-fn calc_cov2d(focal: vec2f, img_size: vec2u, pixel_center: vec2f, viewmat: mat4x4f, p_view: vec3f, scale: vec3f, quat: vec4f) -> vec3f {
-    let tan_fov = 0.5 * vec2f(img_size.xy) / focal;
+// View space origin of the ray through a pixel. Only orthographic rays don't start at the camera.
+fn pixel_ray_origin(camera_model: u32, fxfy: vec2f, pp: vec2f, pixel_coord: vec2f) -> vec3f {
+    if camera_model == CAMERA_ORTHOGRAPHIC {
+        return vec3f((pixel_coord - pp) / fxfy, 0.0);
+    }
+    return vec3f(0.0);
+}
 
-    let lims_neg = pixel_center / focal + 0.3f * tan_fov;
-    let lims_pos = (vec2f(img_size.xy) - pixel_center) / focal + 0.3f * tan_fov;
+// View space direction of the ray through a pixel, the inverse of project_pix.
+fn pixel_ray_dir(camera_model: u32, distortion: vec4f, fxfy: vec2f, pp: vec2f, pixel_coord: vec2f) -> vec3f {
+    let uv = (pixel_coord - pp) / fxfy;
 
-    let lims = 1.3 * tan_fov;
+    if camera_model == CAMERA_ORTHOGRAPHIC {
+        return vec3f(0.0, 0.0, 1.0);
+    }
+    if camera_model == CAMERA_FISHEYE {
+        // Invert the distortion with a few Newton steps, starting from the undistorted angle.
+        let theta_d = length(uv);
+        if theta_d < 1e-6 {
+            return vec3f(0.0, 0.0, 1.0);
+        }
+        var theta = theta_d;
+        for (var i = 0u; i < 4u; i++) {
+            theta -= (fisheye_theta_d(theta, distortion) - theta_d) / fisheye_theta_d_deriv(theta, distortion);
+        }
+        return vec3f(sin(theta) * uv / theta_d, cos(theta));
+    }
+    return vec3f(uv, 1.0);
+}
 
-    // Get ndc coords +- clipped to the frustum.
-    let t = p_view.z * clamp(p_view.xy / p_view.z, -lims_neg, lims_pos);
+// Ray parameter where a splat contributes most along a ray, as in StopThePop (Radl et al. 2024).
+// Sorting by this per pixel, rather than by the depth of the splat center, doesn't change
+// the order of overlapping splats as the view rotates, which avoids popping.
+fn ray_max_depth(ray_origin: vec3f, ray_dir: vec3f, splat: SplatRayDepth) -> f32 {
+    let inv_cov = mat3x3f(
+        vec3f(splat.inv_xx, splat.inv_xy, splat.inv_xz),
+        vec3f(splat.inv_xy, splat.inv_yy, splat.inv_yz),
+        vec3f(splat.inv_xz, splat.inv_yz, splat.inv_zz),
+    );
+    let inv_mean = vec3f(splat.inv_mean_x, splat.inv_mean_y, splat.inv_mean_z);
+    let inv_dir = inv_cov * ray_dir;
+    return (dot(ray_dir, inv_mean) - dot(inv_dir, ray_origin)) / dot(ray_dir, inv_dir);
+}
+
+// Depth used to sort the splats. Fisheye cameras can see behind the image plane,
+// so use the distance instead.
+fn sort_depth(camera_model: u32, p_view: vec3f) -> f32 {
+    if camera_model == CAMERA_FISHEYE {
+        return length(p_view);
+    }
+    return p_view.z;
+}
 
+// Direction that the SH colors are evaluated at.
+fn sh_view_dir(camera_model: u32, viewmat: mat4x4f, mean: vec3f) -> vec3f {
+    if camera_model == CAMERA_ORTHOGRAPHIC {
+        // All rays are parallel to the view axis.
+        return vec3f(viewmat[0].z, viewmat[1].z, viewmat[2].z);
+    }
+    // The view matrix is [R | t], so the camera sits at -R^T t in world space.
+    let W = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
+    let camera_pos = -(transpose(W) * viewmat[3].xyz);
+    return normalize(mean - camera_pos);
+}
+
+fn calc_cov2d(camera_model: u32, distortion: vec4f, focal: vec2f, img_size: vec2u, pixel_center: vec2f, viewmat: mat4x4f, p_view: vec3f, scale: vec3f, quat: vec4f) -> vec3f {
     var M = quat_to_rotmat(quat);
     M[0] *= scale.x;
     M[1] *= scale.y;
     M[2] *= scale.z;
     var V = M * transpose(M);
 
-    let J = mat3x3f(
-        vec3f(focal.x, 0.0, 0.0),
-        vec3f(0.0, focal.y, 0.0),
-        vec3f(-focal * t / p_view.z, 0.0)
-    ) * (1.0 / p_view.z);
+    var J: mat3x3f;
+
+    if camera_model == CAMERA_ORTHOGRAPHIC {
+        // The projection is linear, so the Jacobian is constant.
+        J = mat3x3f(
+            vec3f(focal.x, 0.0, 0.0),
+            vec3f(0.0, focal.y, 0.0),
+            vec3f(0.0, 0.0, 0.0)
+        );
+    } else if camera_model == CAMERA_FISHEYE {
+        J = fisheye_jacobian(focal, p_view, distortion);
+    } else {
+        let tan_fov = 0.5 * vec2f(img_size.xy) / focal;
+
+        let lims_neg = pixel_center / focal + 0.3f * tan_fov;
+        let lims_pos = (vec2f(img_size.xy) - pixel_center) / focal + 0.3f * tan_fov;
+
+        // Get ndc coords +- clipped to the frustum.
+        let t = p_view.z * clamp(p_view.xy / p_view.z, -lims_neg, lims_pos);
+
+        J = mat3x3f(
+            vec3f(focal.x, 0.0, 0.0),
+            vec3f(0.0, focal.y, 0.0),
+            vec3f(-focal * t / p_view.z, 0.0)
+        ) * (1.0 / p_view.z);
+    }
 
     let W = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let T = J * W;
@@ -173,6 +373,79 @@ fn cov_compensation(cov2d: vec3f) -> f32 {
     return sqrt(max(0.0, det_orig / det));
 }
 
+// Mip-Splatting 3D filter, which convolves each splat with an isotropic gaussian of the given
+// std. dev. This bounds the splat size by the sampling rate of the training cameras.
+// As the rotation doesn't change the isotropic part, this only grows the scales.
+fn filter_scale(scale: vec3f, filter_3d: f32) -> vec3f {
+    return sqrt(scale * scale + filter_3d * filter_3d);
+}
+
+// The filtered splat has a larger volume, scale the opacity to keep the same total density.
+fn filter_compensation(scale: vec3f, filter_3d: f32) -> f32 {
+    let scale_sqr = scale * scale;
+    let filtered_sqr = scale_sqr + filter_3d * filter_3d;
+    let ratio = scale_sqr / filtered_sqr;
+    return sqrt(ratio.x * ratio.y * ratio.z);
+}
+
+// Opacity of a splat at a pixel as blended by the rasterizer, or zero if it's skipped.
+fn splat_alpha(projected: ProjectedSplat, pixel_coord: vec2f) -> f32 {
+    let xy = vec2f(projected.xy_x, projected.xy_y);
+    let conic = vec3f(projected.conic_x, projected.conic_y, projected.conic_z);
+    let delta = xy - pixel_coord;
+    let sigma = 0.5f * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y;
+    let alpha = min(0.999f, projected.color_a * exp(-sigma));
+
+    if sigma < 0.0 || alpha < 1.0 / 255.0 {
+        return 0.0;
+    }
+    return alpha;
+}
+
+// Number of splats each pixel holds back when resorting, see resort_push.
+const RESORT_QUEUE_SIZE: u32 = 8u;
+
+// Pushes a splat onto the queue of a pixel, which is kept sorted by depth. Once the queue is
+// full, the nearest splat is returned to be blended, which is either the front of the queue
+// or the new splat itself. Otherwise this returns the invalid id 0xffffffff.
+// The queue is stable, so splats at the same depth keep their global order.
+fn resort_push(
+    depths: ptr<function, array<f32, RESORT_QUEUE_SIZE>>,
+    ids: ptr<function, array<u32, RESORT_QUEUE_SIZE>>,
+    len: ptr<function, u32>,
+    depth: f32,
+    id: u32,
+) -> u32 {
+    if *len == RESORT_QUEUE_SIZE {
+        if depth < (*depths)[0] {
+            return id;
+        }
+
+        // Pop the front, and move the nearer splats down to make place for the new one.
+        let front = (*ids)[0];
+        var i = 0u;
+        while i + 1u < RESORT_QUEUE_SIZE && (*depths)[i + 1u] <= depth {
+            (*depths)[i] = (*depths)[i + 1u];
+            (*ids)[i] = (*ids)[i + 1u];
+            i++;
+        }
+        (*depths)[i] = depth;
+        (*ids)[i] = id;
+        return front;
+    }
+
+    var i = *len;
+    while i > 0u && (*depths)[i - 1u] > depth {
+        (*depths)[i] = (*depths)[i - 1u];
+        (*ids)[i] = (*ids)[i - 1u];
+        i--;
+    }
+    (*depths)[i] = depth;
+    (*ids)[i] = id;
+    *len += 1u;
+    return 0xffffffffu;
+}
+
 fn calc_sigma(pixel_coord: vec2f, conic: vec3f, xy: vec2f) -> f32 {
     let delta = pixel_coord - xy;
     return 0.5f * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y;
@@ -6,6 +6,7 @@
 
 @group(0) @binding(3) var<storage, read_write> tile_id_from_isect: array<u32>;
 @group(0) @binding(4) var<storage, read_write> compact_gid_from_isect: array<u32>;
+@group(0) @binding(5) var<storage, read> global_from_compact_gid: array<u32>;
 
 @compute
 @workgroup_size(helpers::MAIN_WG, 1, 1)
@@ -24,6 +25,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
 
     let radius = helpers::radius_from_conic(conic, opac);
     let tile_bounds = uniforms.tile_bounds;
+    let camera = helpers::camera_of_gid(global_from_compact_gid[compact_gid], uniforms.total_splats);
 
     let tile_minmax = helpers::get_tile_bbox(xy, radius, tile_bounds);
     let tile_min = tile_minmax.xy;
@@ -38,7 +40,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     for (var ty = tile_min.y; ty < tile_max.y; ty++) {
         for (var tx = tile_min.x; tx < tile_max.x; tx++) {
             if helpers::can_be_visible(vec2u(tx, ty), xy, conic, opac) && isect_id < arrayLength(&tile_id_from_isect) {
-                let tile_id = tx + ty * tile_bounds.x; // tile within image
+                let tile_id = helpers::camera_tile_id(camera, vec2u(tx, ty), tile_bounds);
                 tile_id_from_isect[isect_id] = tile_id;
                 compact_gid_from_isect[isect_id] = compact_gid;
                 isect_id++; // handles gaussians that hit more than one tile
@@ -16,12 +16,63 @@
 @group(0) @binding(8) var<storage, read_write> v_scales: array<helpers::PackedVec3>;
 @group(0) @binding(9) var<storage, read_write> v_quats: array<vec4f>;
 
-fn project_pix_vjp(fxfy: vec2f, p_view: vec3f, v_xy: vec2f) -> vec3f {
+@group(0) @binding(10) var<storage, read> raw_opacities: array<f32>;
+@group(0) @binding(11) var<storage, read> v_colors: array<vec4f>;
+@group(0) @binding(12) var<storage, read> filter_3d: array<f32>;
+
+// Gradient of the view matrix summed per workgroup, as 3 columns of (rotation column, translation).
+@group(0) @binding(13) var<storage, read_write> v_viewmats: array<mat3x4f>;
+
+// Gradient of the intrinsics summed per workgroup, as (focal, pixel center).
+@group(0) @binding(14) var<storage, read_write> v_intrinsics: array<vec4f>;
+
+@group(0) @binding(15) var<storage, read> cameras: array<helpers::CameraUniforms>;
+
+var<workgroup> local_v_viewmat: array<mat3x4f, 256>;
+var<workgroup> local_v_intrinsics: array<vec4f, 256>;
+
+struct CameraGrads {
+    v_viewmat: mat3x4f,
+    v_intrinsics: vec4f,
+}
+
+fn sigmoid(x: f32) -> f32 {
+    return 1.0 / (1.0 + exp(-x));
+}
+
+fn project_pix_vjp(camera_model: u32, distortion: vec4f, fxfy: vec2f, p_view: vec3f, v_xy: vec2f) -> vec3f {
+    if camera_model == helpers::CAMERA_ORTHOGRAPHIC {
+        return vec3f(fxfy * v_xy, 0.0);
+    }
+    if camera_model == helpers::CAMERA_FISHEYE {
+        return transpose(helpers::fisheye_jacobian(fxfy, p_view, distortion)) * vec3f(v_xy, 0.0);
+    }
     let rw = 1.0f / (p_view.z + 1e-6f);
     let v_proj = fxfy * v_xy;
     return vec3f(v_proj.x * rw, v_proj.y * rw, -(v_proj.x * p_view.x + v_proj.y * p_view.y) * rw * rw);
 }
 
+// Gradient of the fisheye Jacobian with respect to the view space mean. The analytical
+// second derivatives are unwieldy, so use central differences of the Jacobian instead.
+fn fisheye_jacobian_vjp(focal: vec2f, p_view: vec3f, distortion: vec4f, v_J: mat3x3f) -> vec3f {
+    let h = max(length(p_view) * 1e-3, 1e-5);
+    var v_p = vec3f(0.0);
+
+    for (var i = 0u; i < 3u; i++) {
+        var dp = vec3f(0.0);
+        dp[i] = h;
+        let dJ_0 = helpers::fisheye_jacobian(focal, p_view - dp, distortion);
+        let dJ_1 = helpers::fisheye_jacobian(focal, p_view + dp, distortion);
+
+        var v = 0.0;
+        for (var c = 0u; c < 3u; c++) {
+            v += dot(v_J[c], dJ_1[c] - dJ_0[c]);
+        }
+        v_p[i] = v / (2.0 * h);
+    }
+    return v_p;
+}
+
 fn quat_to_rotmat_vjp(quat: vec4f, v_R: mat3x3f) -> vec4f {
     let w = quat.x;
     let x = quat.y;
@@ -72,28 +123,27 @@ fn cov2d_to_conic_vjp(conic: vec3f, v_conic: vec3f) -> vec3f {
 }
 
 
-@compute
-@workgroup_size(256, 1, 1)
-fn main(@builtin(global_invocation_id) gid: vec3u) {
-    let compact_gid = gid.x;
-    if compact_gid >= uniforms.num_visible {
-        return;
-    }
-
+// Writes the gradients of a visible splat, and returns its contribution to the camera gradients.
+fn project_backward(compact_gid: u32) -> CameraGrads {
     let v_conic = helpers::as_vec(v_conics[compact_gid]);
     let v_xy = v_xys[compact_gid];
 
-    let viewmat = uniforms.viewmat;
-    let focal = uniforms.focal;
-
     let global_gid = global_from_compact_gid[compact_gid];
-    let mean = helpers::as_vec(means[global_gid]);
-    let scale = exp(helpers::as_vec(log_scales[global_gid]));
-    let quat = quats[global_gid];
+    let splat_id = helpers::splat_of_gid(global_gid, uniforms.total_splats);
+    let camera = cameras[helpers::camera_of_gid(global_gid, uniforms.total_splats)];
+
+    let viewmat = camera.viewmat;
+    let focal = camera.focal;
+
+    let mean = helpers::as_vec(means[splat_id]);
+    let raw_scale = exp(helpers::as_vec(log_scales[splat_id]));
+    let filter_std = filter_3d[splat_id];
+    let scale = helpers::filter_scale(raw_scale, filter_std);
+    let quat = quats[splat_id];
 
     let W = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let p_view = W * mean + viewmat[3].xyz;
-    var v_mean = transpose(W) * project_pix_vjp(focal, p_view, v_xy);
+    var v_p_view = project_pix_vjp(camera.camera_model, camera.distortion, focal, p_view, v_xy);
 
     // get z gradient contribution to mean3d gradient
     // There's no way to supervise depth currently so this is currently disabled.
@@ -105,7 +155,7 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     // compute vjp from df/d_conic to df/c_cov2d
     // conic = inverse cov2d
     // df/d_cov2d = -conic * df/d_conic * conic
-    let cov2d = helpers::calc_cov2d(uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat, p_view, scale, quat);
+    let cov2d = helpers::calc_cov2d(camera.camera_model, camera.distortion, camera.focal, uniforms.img_size, camera.pixel_center, viewmat, p_view, scale, quat);
     let conic = helpers::cov_to_conic(cov2d);
     var v_cov2d = cov2d_to_conic_vjp(conic, v_conic);
 
@@ -131,12 +181,26 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let rz = 1.0 / p_view.z;
     let rz2 = rz * rz;
 
-    let J = mat3x3f(
+    let is_ortho = camera.camera_model == helpers::CAMERA_ORTHOGRAPHIC;
+    let is_fisheye = camera.camera_model == helpers::CAMERA_FISHEYE;
+
+    var J = mat3x3f(
         vec3f(focal.x * rz, 0.0f, 0.0f),
         vec3f(0.0f, focal.y * rz, 0.0f),
         vec3f(-focal.x * p_view.x * rz2, -focal.y * p_view.y * rz2, 0.0f)
     );
 
+    if is_ortho {
+        // Orthographic projection has a constant Jacobian.
+        J = mat3x3f(
+            vec3f(focal.x, 0.0f, 0.0f),
+            vec3f(0.0f, focal.y, 0.0f),
+            vec3f(0.0f, 0.0f, 0.0f)
+        );
+    } else if is_fisheye {
+        J = helpers::fisheye_jacobian(focal, p_view, camera.distortion);
+    }
+
     let R = helpers::quat_to_rotmat(quat);
     let S = helpers::scale_to_mat(scale);
     let M = R * S;
@@ -179,11 +243,12 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
             focal.y * rz2 * v_J[1][1] + 2.0 * focal.y * p_view.y * rz3 * v_J[2][1]
     );
 
-    v_mean += vec3f(
-        dot(v_t, W[0]),
-        dot(v_t, W[1]),
-        dot(v_t, W[2])
-    );
+    // The orthographic Jacobian doesn't depend on the mean.
+    if is_fisheye {
+        v_p_view += fisheye_jacobian_vjp(focal, p_view, camera.distortion, v_J);
+    } else if !is_ortho {
+        v_p_view += v_t;
+    }
 
     // cov3d is upper triangular elements of matrix
     // off-diagonal elements count grads from both ij and ji elements,
@@ -216,12 +281,72 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
         dot(R[1], v_M[1]),
         dot(R[2], v_M[2]),
     );
-    let v_scale_exp = v_scale * scale;
+    // Chain through the 3D filter, d scale / d raw_scale = raw_scale / scale.
+    var v_scale_exp = v_scale * raw_scale * raw_scale / scale;
+
+    // The filter also scales the opacity by the compensation. Its derivative
+    // wrt. the log scales is compensation * filter^2 / scale^2.
+    if filter_std > 0.0 {
+        let opac = sigmoid(raw_opacities[splat_id]) * helpers::filter_compensation(raw_scale, filter_std);
+        let v_opac = v_colors[compact_gid].w * opac;
+        v_scale_exp += v_opac * filter_std * filter_std / (scale * scale);
+    }
 
     let v_R = v_M * S;
     let v_quat = quat_to_rotmat_vjp(quat, v_R);
 
+    let v_mean = transpose(W) * v_p_view;
+
+    // With multiple cameras, the gradients are written per camera, and summed afterwards.
     v_means[global_gid] = helpers::as_packed(v_mean);
     v_scales[global_gid] = helpers::as_packed(v_scale_exp);
     v_quats[global_gid] = v_quat;
+
+    // p_view = W * mean + t, and T = J * W.
+    let v_W = mat3x3f(v_p_view * mean.x, v_p_view * mean.y, v_p_view * mean.z) + transpose(J) * v_T;
+    let v_viewmat = mat3x4f(
+        vec4f(v_W[0], v_p_view.x),
+        vec4f(v_W[1], v_p_view.y),
+        vec4f(v_W[2], v_p_view.z),
+    );
+
+    // The projected mean is xy = proj * focal + pixel_center, and the rows of J are
+    // proportional to the focal length for all camera models.
+    let proj = helpers::project_pix(camera.camera_model, camera.distortion, focal, p_view, vec2f(0.0)) / focal;
+    let J_t = transpose(J);
+    let v_J_t = transpose(v_J);
+    let v_focal = v_xy * proj + vec2f(dot(v_J_t[0], J_t[0]), dot(v_J_t[1], J_t[1])) / focal;
+
+    return CameraGrads(v_viewmat, vec4f(v_focal, v_xy));
+}
+
+@compute
+@workgroup_size(256, 1, 1)
+fn main(
+    @builtin(global_invocation_id) gid: vec3u,
+    @builtin(local_invocation_index) local_idx: u32,
+    @builtin(workgroup_id) wg_id: vec3u,
+) {
+    let compact_gid = gid.x;
+
+    var grads = CameraGrads(mat3x4f(), vec4f(0.0));
+    if compact_gid < uniforms.num_visible {
+        grads = project_backward(compact_gid);
+    }
+
+    // Sum the camera gradients of the workgroup, the per workgroup sums are added up later.
+    local_v_viewmat[local_idx] = grads.v_viewmat;
+    local_v_intrinsics[local_idx] = grads.v_intrinsics;
+    workgroupBarrier();
+
+    if local_idx == 0u {
+        var sum_viewmat = mat3x4f();
+        var sum_intrinsics = vec4f(0.0);
+        for (var i = 0u; i < 256u; i++) {
+            sum_viewmat += local_v_viewmat[i];
+            sum_intrinsics += local_v_intrinsics[i];
+        }
+        v_viewmats[wg_id.x] = sum_viewmat;
+        v_intrinsics[wg_id.x] = sum_intrinsics;
+    }
 }
@@ -11,33 +11,54 @@
 
 @group(0) @binding(4) var<storage, read_write> global_from_compact_gid: array<u32>;
 @group(0) @binding(5) var<storage, read_write> depths: array<f32>;
+// Planes as (normal, offset), splats outside of any of them are culled.
+@group(0) @binding(6) var<storage, read> clip_planes: array<vec4f>;
+// Per splat flags, only valid if uniforms.has_flags is set.
+@group(0) @binding(7) var<storage, read> flags: array<u32>;
+// Per splat std. dev. of the 3D smoothing filter, zero when unfiltered.
+@group(0) @binding(8) var<storage, read> filter_3d: array<f32>;
+@group(0) @binding(9) var<storage, read> cameras: array<helpers::CameraUniforms>;
 
 @compute
 @workgroup_size(256, 1, 1)
 fn main(@builtin(global_invocation_id) global_id: vec3u) {
     let global_gid = global_id.x;
 
-    if global_gid >= uniforms.total_splats {
+    if global_gid >= uniforms.total_splats * uniforms.num_cameras {
+        return;
+    }
+
+    let splat_id = helpers::splat_of_gid(global_gid, uniforms.total_splats);
+    let camera = cameras[helpers::camera_of_gid(global_gid, uniforms.total_splats)];
+
+    if uniforms.has_flags != 0u && (flags[splat_id] & helpers::SPLAT_HIDDEN) != 0u {
         return;
     }
 
     // Project world space to camera space.
-    let mean = helpers::as_vec(means[global_gid]);
+    let mean = helpers::as_vec(means[splat_id]);
 
-    let img_size = uniforms.img_size;
-    let viewmat = uniforms.viewmat;
+    let viewmat = camera.viewmat;
     let W = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let p_view = W * mean + viewmat[3].xyz;
 
-    if p_view.z <= 0.01 {
+    for (var i = 0u; i < uniforms.num_clip_planes; i++) {
+        let plane = clip_planes[i];
+        if dot(plane.xyz, mean) + plane.w < 0.0 {
+            return;
+        }
+    }
+
+    let depth = helpers::sort_depth(camera.camera_model, p_view);
+    if depth <= camera.near || depth >= camera.far {
         return;
     }
 
     // compute the projected covariance
-    let scale = exp(helpers::as_vec(log_scales[global_gid]));
-    let quat = quats[global_gid];
+    let scale = helpers::filter_scale(exp(helpers::as_vec(log_scales[splat_id])), filter_3d[splat_id]);
+    let quat = quats[splat_id];
 
-    let cov2d = helpers::calc_cov2d(uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat, p_view, scale, quat);
+    let cov2d = helpers::calc_cov2d(camera.camera_model, camera.distortion, camera.focal, uniforms.img_size, camera.pixel_center, viewmat, p_view, scale, quat);
     let det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
 
     if det == 0.0 {
@@ -48,7 +69,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     let conic = helpers::cov_to_conic(cov2d);
 
     // compute the projected mean
-    let xy = helpers::project_pix(uniforms.focal, p_view, uniforms.pixel_center);
+    let xy = helpers::project_pix(camera.camera_model, camera.distortion, camera.focal, p_view, camera.pixel_center);
 
     // TODO: Include opacity here or is this ok?
     let radius = helpers::radius_from_conic(conic, 1.0);
@@ -64,5 +85,5 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     // Now write all the data to the buffers.
     let write_id = atomicAdd(&uniforms.num_visible, 1u);
     global_from_compact_gid[write_id] = global_gid;
-    depths[write_id] = p_view.z;
+    depths[write_id] = depth;
 }
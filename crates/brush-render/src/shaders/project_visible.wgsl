@@ -13,6 +13,15 @@
 @group(0) @binding(7) var<storage, read_write> projected: array<helpers::ProjectedSplat>;
 @group(0) @binding(8) var<storage, read_write> num_tiles_hit: array<u32>;
 
+// Per splat std. dev. of the 3D smoothing filter, zero when unfiltered.
+@group(0) @binding(9) var<storage, read> filter_3d: array<f32>;
+@group(0) @binding(10) var<storage, read> cameras: array<helpers::CameraUniforms>;
+
+#ifdef RESORT
+    // Per splat data to sort the splats per pixel in the rasterizer.
+    @group(0) @binding(11) var<storage, read_write> ray_depths: array<helpers::SplatRayDepth>;
+#endif
+
 struct ShCoeffs {
     b0_c0: vec3f,
 
@@ -170,25 +179,29 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     }
 
     let global_gid = global_from_compact_gid[compact_gid];
+    let splat_id = helpers::splat_of_gid(global_gid, uniforms.total_splats);
+    let camera = cameras[helpers::camera_of_gid(global_gid, uniforms.total_splats)];
 
     // Project world space to camera space.
-    let mean = helpers::as_vec(means[global_gid]);
-    let scale = exp(helpers::as_vec(log_scales[global_gid]));
-    let quat = quats[global_gid];
-    let opac = sigmoid(raw_opacities[global_gid]);
-
-    let viewmat = uniforms.viewmat;
+    let mean = helpers::as_vec(means[splat_id]);
+    let raw_scale = exp(helpers::as_vec(log_scales[splat_id]));
+    let filter_std = filter_3d[splat_id];
+    let scale = helpers::filter_scale(raw_scale, filter_std);
+    let quat = quats[splat_id];
+    let opac = sigmoid(raw_opacities[splat_id]) * helpers::filter_compensation(raw_scale, filter_std);
+
+    let viewmat = camera.viewmat;
     let W = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let p_view = W * mean + viewmat[3].xyz;
-    let cov2d = helpers::calc_cov2d(uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat, p_view, scale, quat);
+    let cov2d = helpers::calc_cov2d(camera.camera_model, camera.distortion, camera.focal, uniforms.img_size, camera.pixel_center, viewmat, p_view, scale, quat);
     let conic = helpers::cov_to_conic(cov2d);
 
     // compute the projected mean
-    let xy = helpers::project_pix(uniforms.focal, p_view, uniforms.pixel_center);
+    let xy = helpers::project_pix(camera.camera_model, camera.distortion, camera.focal, p_view, camera.pixel_center);
 
     let sh_degree = uniforms.sh_degree;
     let num_coeffs = num_sh_coeffs(sh_degree);
-    var base_id = global_gid * num_coeffs;
+    var base_id = splat_id * num_coeffs;
 
     var sh = ShCoeffs();
     sh.b0_c0 = read_coeffs(&base_id);
@@ -229,8 +242,7 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
         }
     }
 
-    let camera_pos = uniforms.viewmat[3].xyz;
-    let viewdir = normalize(mean - camera_pos);
+    let viewdir = helpers::sh_view_dir(camera.camera_model, viewmat, mean);
 
     let color = sh_coeffs_to_color(sh_degree, viewdir, sh) + vec3f(0.5);
 
@@ -255,4 +267,23 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
         vec4f(color, opac)
     );
     num_tiles_hit[compact_gid] = u32(tile_area);
+
+#ifdef RESORT
+    // The inverse covariance in view space is W R S^-2 R^T W^T.
+    let WR = W * helpers::quat_to_rotmat(normalize(quat));
+    let inv_scale_sq = 1.0 / (scale * scale);
+    let inv_cov = mat3x3f(WR[0] * inv_scale_sq.x, WR[1] * inv_scale_sq.y, WR[2] * inv_scale_sq.z) * transpose(WR);
+    let inv_mean = inv_cov * p_view;
+    ray_depths[compact_gid] = helpers::SplatRayDepth(
+        inv_mean.x,
+        inv_mean.y,
+        inv_mean.z,
+        inv_cov[0].x,
+        inv_cov[0].y,
+        inv_cov[0].z,
+        inv_cov[1].y,
+        inv_cov[1].z,
+        inv_cov[2].z,
+    );
+#endif
 }
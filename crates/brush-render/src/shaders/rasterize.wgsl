@@ -7,12 +7,64 @@
 
 #ifdef RASTER_U32
     @group(0) @binding(4) var<storage, read_write> out_img: array<u32>;
+    @group(0) @binding(5) var<storage, read> global_from_compact_gid: array<u32>;
+    // Per splat flags, only valid if uniforms.has_flags is set.
+    @group(0) @binding(6) var<storage, read> flags: array<u32>;
+
+    // Color selected splats are tinted with when displaying.
+    const SELECTED_TINT: vec3f = vec3f(1.0, 0.5, 0.0);
 #else
     @group(0) @binding(4) var<storage, read_write> out_img: array<vec4f>;
     @group(0) @binding(5) var<storage, read_write> final_index : array<u32>;
 #endif
 
+#ifdef RESORT
+#ifdef RASTER_U32
+    @group(0) @binding(7) var<storage, read> ray_depths: array<helpers::SplatRayDepth>;
+    @group(0) @binding(8) var<storage, read> cameras: array<helpers::CameraUniforms>;
+#else
+    @group(0) @binding(6) var<storage, read> ray_depths: array<helpers::SplatRayDepth>;
+    @group(0) @binding(7) var<storage, read> cameras: array<helpers::CameraUniforms>;
+#endif
+#endif
+
 var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
+#ifdef RASTER_U32
+    var<workgroup> local_selected: array<u32, helpers::TILE_SIZE>;
+#endif
+
+#ifdef RESORT
+    var<workgroup> local_gid: array<u32, helpers::TILE_SIZE>;
+
+// Blends a splat that was held back in the resort queue. Returns false, without blending,
+// once the pixel is saturated.
+fn blend_splat(
+    compact_gid: u32,
+    pixel_coord: vec2f,
+    T: ptr<function, f32>,
+    pix_out: ptr<function, vec3f>,
+    selected: ptr<function, f32>,
+) -> bool {
+    let projected = projected_splats[compact_gid];
+    let alpha = helpers::splat_alpha(projected, pixel_coord);
+    let next_T = *T * (1.0 - alpha);
+
+    if next_T <= 1e-4f {
+        return false;
+    }
+
+    let fac = alpha * *T;
+    *pix_out += vec3f(projected.color_r, projected.color_g, projected.color_b) * fac;
+
+    #ifdef RASTER_U32
+        if uniforms.has_flags != 0u && (flags[global_from_compact_gid[compact_gid]] & helpers::SPLAT_SELECTED) != 0u {
+            *selected += fac;
+        }
+    #endif
+    *T = next_T;
+    return true;
+}
+#endif
 
 // kernel function for rasterizing each tile
 // each thread treats a single pixel
@@ -27,9 +79,12 @@ fn main(
     let background = uniforms.background;
     let img_size = uniforms.img_size;
 
+    // Each camera of a batch is a layer of workgroups.
+    let camera_id = workgroup_id.z;
+
     // Get index of tile being drawn.
-    let pix_id = global_id.x + global_id.y * img_size.x;
-    let tile_id = workgroup_id.x + workgroup_id.y * uniforms.tile_bounds.x;
+    let pix_id = camera_id * img_size.x * img_size.y + global_id.x + global_id.y * img_size.x;
+    let tile_id = helpers::camera_tile_id(camera_id, workgroup_id.xy, uniforms.tile_bounds);
     let pixel_coord = vec2f(global_id.xy) + 0.5;
 
     // return if out of bounds
@@ -46,6 +101,8 @@ fn main(
     var T = 1.0;
 
     var pix_out = vec3f(0.0);
+    // Amount of the pixel covered by selected splats.
+    var selected = 0.0;
 
     // collect and process batches of gaussians
     // each thread loads one gaussian at a time before rasterizing its
@@ -53,6 +110,16 @@ fn main(
     var t = 0u;
     var final_idx = 0u;
 
+#ifdef RESORT
+    // Splats held back to blend them in order of their depth along this pixel's ray.
+    let camera = cameras[camera_id];
+    let ray_origin = helpers::pixel_ray_origin(camera.camera_model, camera.focal, camera.pixel_center, pixel_coord);
+    let ray_dir = helpers::pixel_ray_dir(camera.camera_model, camera.distortion, camera.focal, camera.pixel_center, pixel_coord);
+    var queue_depths: array<f32, helpers::RESORT_QUEUE_SIZE>;
+    var queue_gids: array<u32, helpers::RESORT_QUEUE_SIZE>;
+    var queue_len = 0u;
+#endif
+
     // each thread loads one gaussian at a time before rasterizing its
     // designated pixel
     for (var b = 0u; b < num_batches; b++) {
@@ -66,12 +133,40 @@ fn main(
 
         if local_idx < remaining {
             let load_isect_id = batch_start + local_idx;
-            local_batch[local_idx] = projected_splats[compact_gid_from_isect[load_isect_id]];
+            let compact_gid = compact_gid_from_isect[load_isect_id];
+            local_batch[local_idx] = projected_splats[compact_gid];
+
+            #ifdef RESORT
+                local_gid[local_idx] = compact_gid;
+            #endif
+
+            #ifdef RASTER_U32
+                var is_selected = 0u;
+                if uniforms.has_flags != 0u {
+                    is_selected = flags[global_from_compact_gid[compact_gid]] & helpers::SPLAT_SELECTED;
+                }
+                local_selected[local_idx] = is_selected;
+            #endif
         }
         // Wait for all writes to complete.
         workgroupBarrier();
 
         for (var t = 0u; t < remaining && !done; t++) {
+#ifdef RESORT
+            if helpers::splat_alpha(local_batch[t], pixel_coord) == 0.0 {
+                continue;
+            }
+
+            // Every thread reads the same splat, so this is cheap, and saves on shared memory.
+            let depth = helpers::ray_max_depth(ray_origin, ray_dir, ray_depths[local_gid[t]]);
+            let blend_gid = helpers::resort_push(&queue_depths, &queue_gids, &queue_len, depth, local_gid[t]);
+
+            if blend_gid != 0xffffffffu && !blend_splat(blend_gid, pixel_coord, &T, &pix_out, &selected) {
+                done = true;
+                break;
+            }
+            final_idx = batch_start + t;
+#else
             let projected = local_batch[t];
 
             let xy = vec2f(projected.xy_x, projected.xy_y);
@@ -93,18 +188,33 @@ fn main(
 
                 let fac = alpha * T;
                 pix_out += vec3f(color.r, color.g, color.b) * fac;
+
+                #ifdef RASTER_U32
+                    if local_selected[t] != 0u {
+                        selected += fac;
+                    }
+                #endif
                 T = next_T;
 
                 let isect_id = batch_start + t;
                 final_idx = isect_id;
             }
+#endif
         }
     }
 
+#ifdef RESORT
+    // Blend the splats still held back.
+    for (var i = 0u; i < queue_len && !done; i++) {
+        done = !blend_splat(queue_gids[i], pixel_coord, &T, &pix_out, &selected);
+    }
+#endif
+
     if inside {
         let final_color = vec4f(pix_out + T * background.xyz, 1.0 - T);
         #ifdef RASTER_U32
-            let colors_u = vec4u(clamp(final_color * 255.0, vec4f(0.0), vec4f(255.0)));
+            let tinted = mix(final_color.rgb, SELECTED_TINT, 0.5 * selected);
+            let colors_u = vec4u(clamp(vec4f(tinted, final_color.a) * 255.0, vec4f(0.0), vec4f(255.0)));
             let packed: u32 = colors_u.x | (colors_u.y << 8u) | (colors_u.z << 16u) | (colors_u.w << 24u);
             out_img[pix_id] = packed;
         #else
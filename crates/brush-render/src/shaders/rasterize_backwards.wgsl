@@ -11,6 +11,10 @@
 @group(0) @binding(5) var<storage, read> output: array<vec4f>;
 @group(0) @binding(6) var<storage, read> v_output: array<vec4f>;
 
+#ifdef DETERMINISTIC
+    // Gradients per intersection, which are summed per splat in a fixed order afterwards.
+    @group(0) @binding(7) var<storage, read_write> v_isect_grads: array<helpers::ProjectedSplat>;
+#else
 #ifdef HARD_FLOAT
     @group(0) @binding(7) var<storage, read_write> v_xy: array<atomic<f32>>;
     @group(0) @binding(8) var<storage, read_write> v_conics: array<atomic<f32>>;
@@ -20,6 +24,13 @@
     @group(0) @binding(8) var<storage, read_write> v_conics: array<atomic<u32>>;
     @group(0) @binding(9) var<storage, read_write> v_colors: array<atomic<u32>>;
 #endif
+#ifdef RESORT
+    // Per splat data to sort the splats per pixel, as in the forward pass. Resorting isn't
+    // supported with deterministic gradients.
+    @group(0) @binding(10) var<storage, read> ray_depths: array<helpers::SplatRayDepth>;
+    @group(0) @binding(11) var<storage, read> cameras: array<helpers::CameraUniforms>;
+#endif
+#endif
 
 
 const MIN_WG_SIZE: u32 = 8u;
@@ -35,11 +46,19 @@ var<workgroup> local_id: array<u32, BATCH_SIZE>;
 // this gradient to the global gradient. Instead, we push each subgroup gradient to a buffer
 // until it has N threads gradients, which are then written to the global gradients all at once.
 
+#ifdef DETERMINISTIC
+// Gradient sum of each subgroup for the current splat, indexed by local_idx / subgroup_size.
+// Subgroups have at least 4 threads.
+const MAX_SUBGROUPS: u32 = helpers::TILE_SIZE / 4u;
+var<workgroup> subgroup_grads: array<helpers::ProjectedSplat, MAX_SUBGROUPS>;
+#else
 // Current queue of gradients to be flushed.
 var<workgroup> grad_count: atomic<i32>;
 var<workgroup> gather_grads: array<helpers::ProjectedSplat, BATCH_SIZE>;
 var<workgroup> gather_grad_id: array<u32, BATCH_SIZE>;
+#endif
 
+#ifndef DETERMINISTIC
 fn add_bitcast(cur: u32, add: f32) -> u32 {
     return bitcast<u32>(bitcast<f32>(cur) + add);
 }
@@ -133,6 +152,63 @@ fn write_grads_atomic(grads: helpers::ProjectedSplat, id: u32) {
     }
 #endif
 }
+#endif
+
+#ifdef RESORT
+// Blends a splat like the forward pass, and writes out its gradients. Returns false, without
+// blending, once the pixel is saturated.
+fn blend_splat_grads(
+    compact_gid: u32,
+    pixel_coord: vec2f,
+    pix_final: vec3f,
+    T_final: f32,
+    v_out: vec4f,
+    background: vec3f,
+    T: ptr<function, f32>,
+    pix_front: ptr<function, vec3f>,
+) -> bool {
+    let projected = projected_splats[compact_gid];
+    let alpha = helpers::splat_alpha(projected, pixel_coord);
+    let next_T = *T * (1.0 - alpha);
+
+    if next_T <= 1e-4f {
+        return false;
+    }
+
+    let xy = vec2f(projected.xy_x, projected.xy_y);
+    let conic = vec3f(projected.conic_x, projected.conic_y, projected.conic_z);
+    let color = vec4f(projected.color_r, projected.color_g, projected.color_b, projected.color_a);
+
+    let delta = xy - pixel_coord;
+    let sigma = 0.5f * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y;
+    let vis = exp(-sigma);
+
+    let fac = alpha * *T;
+    *pix_front += color.rgb * fac;
+
+    // The splats behind this one make up the rest of the final color.
+    let buffer = pix_final - T_final * background - *pix_front;
+    let ra = 1.0 / (1.0 - alpha);
+
+    var v_alpha = dot(color.rgb * *T - buffer * ra, v_out.xyz);
+    v_alpha += T_final * ra * v_out.w;
+    v_alpha -= dot(T_final * ra * background, v_out.xyz);
+
+    let v_sigma = -color.a * vis * v_alpha;
+    let v_xy = v_sigma * vec2f(
+        conic.x * delta.x + conic.y * delta.y,
+        conic.y * delta.x + conic.z * delta.y
+    );
+    let v_conic = vec3f(0.5f * v_sigma * delta.x * delta.x,
+                        v_sigma * delta.x * delta.y,
+                        0.5f * v_sigma * delta.y * delta.y);
+    let v_colors = vec4f(fac * v_out.xyz, vis * v_alpha);
+
+    write_grads_atomic(helpers::create_projected_splat(v_xy, v_conic, v_colors), compact_gid);
+    *T = next_T;
+    return true;
+}
+#endif
 
 // kernel function for rasterizing each tile
 // each thread treats a single pixel
@@ -150,10 +226,12 @@ fn main(
     let img_size = uniforms.img_size;
     let tile_bounds = uniforms.tile_bounds;
 
-    let tile_id = workgroup_id.x;
-    let tile_loc = vec2u(tile_id % tile_bounds.x, tile_id / tile_bounds.x);
+    // Each camera of a batch is a row of workgroups.
+    let camera_id = workgroup_id.y;
+    let tile_loc = vec2u(workgroup_id.x % tile_bounds.x, workgroup_id.x / tile_bounds.x);
+    let tile_id = helpers::camera_tile_id(camera_id, tile_loc, tile_bounds);
     let pixel_coordi = tile_loc * helpers::TILE_WIDTH + vec2u(local_idx % helpers::TILE_WIDTH, local_idx / helpers::TILE_WIDTH);
-    let pix_id = pixel_coordi.x + pixel_coordi.y * img_size.x;
+    let pix_id = camera_id * img_size.x * img_size.y + pixel_coordi.x + pixel_coordi.y * img_size.x;
     let pixel_coord = vec2f(pixel_coordi) + 0.5;
 
     // return if out of bounds
@@ -170,6 +248,62 @@ fn main(
 
     let num_batches = helpers::ceil_div(range.y - range.x, BATCH_SIZE);
 
+#ifdef RESORT
+    // Each pixel blends the splats in its own order, so retrace the forward pass front to back
+    // with the same queue. As the threads of a subgroup blend different splats at the same
+    // time, each thread writes out its own gradients.
+    let camera = cameras[camera_id];
+    let ray_origin = helpers::pixel_ray_origin(camera.camera_model, camera.focal, camera.pixel_center, pixel_coord);
+    let ray_dir = helpers::pixel_ray_dir(camera.camera_model, camera.distortion, camera.focal, camera.pixel_center, pixel_coord);
+    var queue_depths: array<f32, helpers::RESORT_QUEUE_SIZE>;
+    var queue_gids: array<u32, helpers::RESORT_QUEUE_SIZE>;
+    var queue_len = 0u;
+
+    let pix_final = output[pix_id].xyz;
+    var v_out = vec4f(0.0);
+    if inside {
+        v_out = v_output[pix_id];
+    }
+
+    var T = 1.0;
+    var pix_front = vec3f(0.0);
+    var done = !inside;
+
+    for (var b = 0u; b < num_batches; b++) {
+        let batch_start = range.x + b * BATCH_SIZE;
+        let remaining = min(BATCH_SIZE, range.y - batch_start);
+
+        // Wait for all threads to be done with the previous batch.
+        workgroupBarrier();
+
+        if local_idx < remaining {
+            let compact_gid = compact_gid_from_isect[batch_start + local_idx];
+            local_id[local_idx] = compact_gid;
+            local_batch[local_idx] = projected_splats[compact_gid];
+        }
+
+        // Wait for all threads to have collected the gaussians.
+        workgroupBarrier();
+
+        for (var t = 0u; t < remaining && !done; t++) {
+            if helpers::splat_alpha(local_batch[t], pixel_coord) == 0.0 {
+                continue;
+            }
+
+            let depth = helpers::ray_max_depth(ray_origin, ray_dir, ray_depths[local_id[t]]);
+            let blend_gid = helpers::resort_push(&queue_depths, &queue_gids, &queue_len, depth, local_id[t]);
+
+            if blend_gid != 0xffffffffu {
+                done = !blend_splat_grads(blend_gid, pixel_coord, pix_final, T_final, v_out, background, &T, &pix_front);
+            }
+        }
+    }
+
+    // Blend the splats still held back.
+    for (var i = 0u; i < queue_len && !done; i++) {
+        done = !blend_splat_grads(queue_gids[i], pixel_coord, pix_final, T_final, v_out, background, &T, &pix_front);
+    }
+#else
     // current visibility left to render
     var T = T_final;
 
@@ -186,8 +320,10 @@ fn main(
         v_out = v_output[pix_id];
     }
 
+#ifndef DETERMINISTIC
     // Make sure all groups start with empty gradient queue.
     atomicStore(&grad_count, 0);
+#endif
 
     let sg_per_tile = helpers::ceil_div(helpers::TILE_SIZE, subgroup_size);
     let microbatch_size = helpers::TILE_SIZE / sg_per_tile;
@@ -274,6 +410,39 @@ fn main(
                     }
                 }
 
+#ifdef DETERMINISTIC
+                // Sum the subgroup gradients in a fixed order, and write them out for this
+                // intersection. This needs a barrier per splat, so is quite a bit slower.
+                let v_xy_sum = subgroupAdd(v_xy);
+                let v_conic_sum = subgroupAdd(v_conic);
+                let v_colors_sum = subgroupAdd(v_colors);
+
+                // WGSL has no subgroup id, so this assumes subgroups are made of consecutive
+                // local invocations. That's how drivers lay out 1D workgroups in practice, but
+                // it isn't guaranteed. Otherwise two subgroups could write the same slot.
+                if subgroup_invocation_id == 0 {
+                    subgroup_grads[local_idx / subgroup_size] = helpers::create_projected_splat(
+                        v_xy_sum,
+                        v_conic_sum,
+                        v_colors_sum
+                    );
+                }
+                workgroupBarrier();
+
+                if local_idx == 0u {
+                    var sum_xy = vec2f(0.0);
+                    var sum_conic = vec3f(0.0);
+                    var sum_colors = vec4f(0.0);
+                    for (var i = 0u; i < sg_per_tile; i++) {
+                        let grads = subgroup_grads[i];
+                        sum_xy += vec2f(grads.xy_x, grads.xy_y);
+                        sum_conic += vec3f(grads.conic_x, grads.conic_y, grads.conic_z);
+                        sum_colors += vec4f(grads.color_r, grads.color_g, grads.color_b, grads.color_a);
+                    }
+                    v_isect_grads[isect_id] = helpers::create_projected_splat(sum_xy, sum_conic, sum_colors);
+                }
+                workgroupBarrier();
+#else
                 // Queue a new gradient if this subgroup has any.
                 // The gradient is sum of all gradients in the subgroup.
                 if subgroupAny(splat_active) {
@@ -293,8 +462,11 @@ fn main(
                         gather_grad_id[grad_idx] = local_id[t];
                     }
                 }
+#endif
             }
 
+#ifndef DETERMINISTIC
+
             // Make sure all threads are done, and flush a batch of gradients.
             workgroupBarrier();
             if local_idx < u32(grad_count) {
@@ -302,6 +474,8 @@ fn main(
             }
             workgroupBarrier();
             atomicStore(&grad_count, 0);
+#endif
         }
     }
+#endif
 }
@@ -0,0 +1,103 @@
+#import helpers
+
+struct FeatureUniforms {
+    // Number of feature channels per splat.
+    num_channels: u32,
+}
+
+@group(0) @binding(0) var<storage, read> uniforms: helpers::RenderUniforms;
+@group(0) @binding(1) var<storage, read> feature_uniforms: FeatureUniforms;
+@group(0) @binding(2) var<storage, read> compact_gid_from_isect: array<u32>;
+@group(0) @binding(3) var<storage, read> tile_bins: array<vec2u>;
+@group(0) @binding(4) var<storage, read> projected_splats: array<helpers::ProjectedSplat>;
+@group(0) @binding(5) var<storage, read> global_from_compact_gid: array<u32>;
+
+#ifdef BACKWARD
+    @group(0) @binding(6) var<storage, read> v_output: array<f32>;
+
+    #ifdef HARD_FLOAT
+        @group(0) @binding(7) var<storage, read_write> v_features: array<atomic<f32>>;
+    #else
+        @group(0) @binding(7) var<storage, read_write> v_features: array<atomic<u32>>;
+    #endif
+#else
+    @group(0) @binding(6) var<storage, read> features: array<f32>;
+    @group(0) @binding(7) var<storage, read_write> out_features: array<f32>;
+#endif
+
+fn add_bitcast(cur: u32, add: f32) -> u32 {
+    return bitcast<u32>(bitcast<f32>(cur) + add);
+}
+
+// Alpha composites the features of each splat, using the same blending as the rasterizer.
+//
+// As the features don't affect the blend weights, the backward pass can walk the splats in the
+// same front to back order, and just needs to scatter the output gradient with the same weights.
+@compute
+@workgroup_size(helpers::TILE_WIDTH, helpers::TILE_WIDTH, 1)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3u,
+    @builtin(workgroup_id) workgroup_id: vec3u,
+) {
+    let img_size = uniforms.img_size;
+
+    if global_id.x >= img_size.x || global_id.y >= img_size.y {
+        return;
+    }
+
+    let pix_id = global_id.x + global_id.y * img_size.x;
+    let tile_id = workgroup_id.x + workgroup_id.y * uniforms.tile_bounds.x;
+    let pixel_coord = vec2f(global_id.xy) + 0.5;
+    let num_channels = feature_uniforms.num_channels;
+
+    let range = tile_bins[tile_id];
+
+    // current visibility left to render
+    var T = 1.0;
+
+    for (var isect_id = range.x; isect_id < range.y; isect_id++) {
+        let compact_gid = compact_gid_from_isect[isect_id];
+        let projected = projected_splats[compact_gid];
+
+        let xy = vec2f(projected.xy_x, projected.xy_y);
+        let conic = vec3f(projected.conic_x, projected.conic_y, projected.conic_z);
+
+        let delta = xy - pixel_coord;
+        let sigma = 0.5f * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y) + conic.y * delta.x * delta.y;
+        let alpha = min(0.999f, projected.color_a * exp(-sigma));
+
+        if sigma < 0.0 || alpha < 1.0 / 255.0 {
+            continue;
+        }
+
+        let next_T = T * (1.0 - alpha);
+        if next_T <= 1e-4f {
+            break;
+        }
+
+        let weight = alpha * T;
+        let global_gid = global_from_compact_gid[compact_gid];
+
+        for (var c = 0u; c < num_channels; c++) {
+            let feature_id = global_gid * num_channels + c;
+
+            #ifdef BACKWARD
+                let v_feature = v_output[pix_id * num_channels + c] * weight;
+
+                #ifdef HARD_FLOAT
+                    atomicAdd(&v_features[feature_id], v_feature);
+                #else
+                    var old_value = atomicLoad(&v_features[feature_id]);
+                    loop {
+                        let cas = atomicCompareExchangeWeak(&v_features[feature_id], old_value, add_bitcast(old_value, v_feature));
+                        if cas.exchanged { break; } else { old_value = cas.old_value; }
+                    }
+                #endif
+            #else
+                out_features[pix_id * num_channels + c] += features[feature_id] * weight;
+            #endif
+        }
+
+        T = next_T;
+    }
+}
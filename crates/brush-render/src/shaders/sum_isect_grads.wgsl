@@ -0,0 +1,46 @@
+#import helpers;
+
+@group(0) @binding(0) var<storage, read> uniforms: helpers::RenderUniforms;
+@group(0) @binding(1) var<storage, read> cum_tiles_hit: array<u32>;
+// Intersection ids grouped per splat, see render.rs.
+@group(0) @binding(2) var<storage, read> isect_from_gid_sorted: array<u32>;
+@group(0) @binding(3) var<storage, read> v_isect_grads: array<helpers::ProjectedSplat>;
+
+@group(0) @binding(4) var<storage, read_write> v_xy: array<vec2f>;
+@group(0) @binding(5) var<storage, read_write> v_conics: array<helpers::PackedVec3>;
+@group(0) @binding(6) var<storage, read_write> v_colors: array<vec4f>;
+
+// Sums the gradients of all intersections of a splat, always in the same order.
+@compute
+@workgroup_size(helpers::MAIN_WG, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3u) {
+    let compact_gid = global_id.x;
+
+    if compact_gid >= uniforms.num_visible {
+        return;
+    }
+
+    // The intersections of a splat are a contiguous range, which can be cut off when
+    // there were more intersections than fit in the buffers.
+    let num_isects = arrayLength(&v_isect_grads);
+    var start = 0u;
+    if compact_gid > 0u {
+        start = min(cum_tiles_hit[compact_gid - 1u], num_isects);
+    }
+    let end = min(cum_tiles_hit[compact_gid], num_isects);
+
+    var sum_xy = vec2f(0.0);
+    var sum_conic = vec3f(0.0);
+    var sum_colors = vec4f(0.0);
+
+    for (var i = start; i < end; i++) {
+        let grads = v_isect_grads[isect_from_gid_sorted[i]];
+        sum_xy += vec2f(grads.xy_x, grads.xy_y);
+        sum_conic += vec3f(grads.conic_x, grads.conic_y, grads.conic_z);
+        sum_colors += vec4f(grads.color_r, grads.color_g, grads.color_b, grads.color_a);
+    }
+
+    v_xy[compact_gid] = sum_xy;
+    v_conics[compact_gid] = helpers::as_packed(sum_conic);
+    v_colors[compact_gid] = sum_colors;
+}
@@ -0,0 +1,127 @@
+use burn::{
+    module::{Module, Param, ParamId},
+    tensor::{backend::Backend, Int, Tensor},
+};
+
+/// Per view affine color transform, to absorb changes in exposure and white balance
+/// between the training images.
+///
+/// This is only applied to the renders during training, so the splats themselves learn
+/// one consistent appearance. Evaluation and exported splats don't use it.
+#[derive(Module, Debug)]
+pub struct AppearanceModel<B: Backend> {
+    // [num_views, 3, 4], a 3x3 color matrix followed by a color offset per view.
+    pub transforms: Param<Tensor<B, 3>>,
+}
+
+fn identity<B: Backend>(device: &B::Device) -> Tensor<B, 3> {
+    Tensor::<B, 1>::from_floats(
+        [
+            1.0, 0.0, 0.0, 0.0, //
+            0.0, 1.0, 0.0, 0.0, //
+            0.0, 0.0, 1.0, 0.0,
+        ],
+        device,
+    )
+    .reshape([1, 3, 4])
+}
+
+impl<B: Backend> AppearanceModel<B> {
+    pub fn new(num_views: usize, device: &B::Device) -> Self {
+        let transforms = identity(device).repeat_dim(0, num_views);
+
+        Self {
+            transforms: Param::initialized(ParamId::new(), transforms.require_grad()),
+        }
+    }
+
+    fn select(&self, view_ids: &[usize]) -> Tensor<B, 3> {
+        let view_ids: Vec<i32> = view_ids.iter().map(|&id| id as i32).collect();
+        let view_ids =
+            Tensor::<B, 1, Int>::from_ints(view_ids.as_slice(), &self.transforms.device());
+        self.transforms.val().select(0, view_ids)
+    }
+
+    /// Transform the colors of a batch of [N, H, W, 3] images, where each image uses the
+    /// transform of the corresponding view id.
+    pub fn apply(&self, images: Tensor<B, 4>, view_ids: &[usize]) -> Tensor<B, 4> {
+        let [n, h, w, _] = images.dims();
+        let transforms = self.select(view_ids);
+
+        // Colors are row vectors, so multiply with the transposed matrix.
+        let matrix = transforms.clone().slice([0..n, 0..3, 0..3]).swap_dims(1, 2);
+        let offset = transforms.slice([0..n, 0..3, 3..4]).reshape([n, 1, 3]);
+
+        let colors = images.reshape([n, h * w, 3]);
+        (colors.matmul(matrix) + offset).reshape([n, h, w, 3])
+    }
+
+    /// Mean squared difference of the transforms of these views to the identity. Keeping the
+    /// transforms close to the identity stops them from absorbing colors the splats should learn.
+    pub fn identity_penalty(&self, view_ids: &[usize]) -> Tensor<B, 1> {
+        let transforms = self.select(view_ids);
+        (transforms.clone() - identity(&transforms.device()))
+            .powf_scalar(2.0)
+            .mean()
+    }
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use brush_render::PrimaryBackend;
+    use burn::{backend::wgpu::WgpuDevice, tensor::Distribution};
+
+    type B = PrimaryBackend;
+
+    #[test]
+    fn identity_leaves_images_unchanged() {
+        let device = WgpuDevice::BestAvailable;
+        let model = AppearanceModel::<B>::new(4, &device);
+        let images = Tensor::<B, 4>::random([2, 8, 6, 3], Distribution::Uniform(0.0, 1.0), &device);
+
+        let applied = model.apply(images.clone(), &[3, 1]);
+        let diff = (applied - images).abs().max().into_scalar();
+        assert!(
+            diff < 1e-6,
+            "Identity transform changed the images by {diff}"
+        );
+        assert_eq!(model.identity_penalty(&[3, 1]).into_scalar(), 0.0);
+    }
+
+    #[test]
+    fn apply_selects_view_transform() {
+        let device = WgpuDevice::BestAvailable;
+        let mut model = AppearanceModel::<B>::new(3, &device);
+        // View 1 swaps red and blue and adds an offset, the other views are the identity.
+        let swap = Tensor::<B, 1>::from_floats(
+            [
+                0.0, 0.0, 1.0, 0.1, //
+                0.0, 1.0, 0.0, 0.2, //
+                1.0, 0.0, 0.0, 0.3,
+            ],
+            &device,
+        )
+        .reshape([1, 3, 4]);
+        let transforms = Tensor::cat(vec![identity(&device), swap, identity(&device)], 0);
+        model.transforms = Param::initialized(ParamId::new(), transforms);
+
+        let images = Tensor::<B, 1>::from_floats([0.2, 0.5, 0.7, 0.2, 0.5, 0.7], &device)
+            .reshape([2, 1, 1, 3]);
+        let applied = model
+            .apply(images, &[0, 1])
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap();
+        let expected = [0.2, 0.5, 0.7, 0.8, 0.7, 0.5];
+
+        for (a, e) in applied.iter().zip(expected) {
+            assert!(
+                (a - e).abs() < 1e-6,
+                "Expected {expected:?}, got {applied:?}"
+            );
+        }
+        assert!(model.identity_penalty(&[1]).into_scalar() > 0.0);
+        assert_eq!(model.identity_penalty(&[0, 2]).into_scalar(), 0.0);
+    }
+}
@@ -0,0 +1,113 @@
+use brush_render::camera::{Camera, CameraModel};
+use burn::{
+    module::{Module, Param, ParamId},
+    tensor::{backend::Backend, Tensor},
+};
+
+/// Learned corrections to the intrinsics of the training cameras.
+///
+/// Each camera has a log scale of the focal length, and an offset of the principal point
+/// in uv coordinates. All views can share one camera, or each view gets its own.
+#[derive(Module, Debug)]
+pub struct IntrinsicsRefinement<B: Backend> {
+    // [num_cameras, 4], log focal scale (x, y) followed by the center offset (u, v).
+    pub deltas: Param<Tensor<B, 2>>,
+}
+
+impl<B: Backend> IntrinsicsRefinement<B> {
+    /// Create the refinement for a number of views. With `shared` set, all views use
+    /// the same correction.
+    pub fn new(num_views: usize, shared: bool, device: &B::Device) -> Self {
+        let num_cameras = if shared { 1 } else { num_views };
+        Self {
+            deltas: Param::initialized(
+                ParamId::new(),
+                Tensor::zeros([num_cameras, 4], device).require_grad(),
+            ),
+        }
+    }
+
+    fn camera_index(&self, view_id: usize) -> usize {
+        if self.deltas.dims()[0] == 1 {
+            0
+        } else {
+            view_id
+        }
+    }
+
+    /// Read back the deltas of all cameras, to pass to [`Self::refine_view`]. Reading these
+    /// once per step avoids a readback for every view.
+    pub async fn read_deltas(&self) -> Vec<f32> {
+        self.deltas
+            .val()
+            .into_data_async()
+            .await
+            .to_vec::<f32>()
+            .expect("Failed to read intrinsics deltas")
+    }
+
+    /// Returns the refined camera of a view, and its [focal.x, focal.y, center.x, center.y]
+    /// in pixels. These carry gradients back to the delta, see [`brush_render::CameraGrads`].
+    ///
+    /// `deltas` are the current deltas of all cameras, see [`Self::read_deltas`].
+    pub fn refine_view(
+        &self,
+        view_id: usize,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        deltas: &[f32],
+    ) -> (Camera, Tensor<B, 1>) {
+        let device = self.deltas.device();
+        let index = self.camera_index(view_id);
+        let delta = self
+            .deltas
+            .val()
+            .slice([index..index + 1, 0..4])
+            .reshape([4]);
+
+        let focal = camera.focal(img_size);
+        let center = camera.center(img_size);
+        let size = img_size.as_vec2();
+
+        let scale = delta.clone().slice([0..2]).exp();
+        let offset = delta.slice([2..4]);
+        let focal = Tensor::<B, 1>::from_floats([focal.x, focal.y], &device) * scale;
+        let center = Tensor::<B, 1>::from_floats([center.x, center.y], &device)
+            + offset * Tensor::from_floats([size.x, size.y], &device);
+
+        let refined = refine_camera(camera, &deltas[index * 4..index * 4 + 4]);
+        (refined, Tensor::cat(vec![focal, center], 0))
+    }
+
+    /// Returns the refined cameras of all views.
+    pub async fn refined_cameras(&self, cameras: &[Camera]) -> Vec<Camera> {
+        let deltas = self.read_deltas().await;
+
+        cameras
+            .iter()
+            .enumerate()
+            .map(|(view_id, camera)| {
+                let index = self.camera_index(view_id);
+                refine_camera(camera, &deltas[index * 4..index * 4 + 4])
+            })
+            .collect()
+    }
+}
+
+fn refine_camera(camera: &Camera, delta: &[f32]) -> Camera {
+    let scale = glam::vec2(delta[0].exp(), delta[1].exp());
+    let mut refined = camera.clone();
+
+    // Scaling the focal length scales the tangent of the half field of view inversely, which
+    // doesn't depend on the image size.
+    match &mut refined.model {
+        CameraModel::Orthographic { extent } => *extent /= scale,
+        CameraModel::Pinhole | CameraModel::Fisheye { .. } => {
+            let tan_half = glam::vec2((camera.fov.x * 0.5).tan(), (camera.fov.y * 0.5).tan());
+            let tan_half = tan_half / scale;
+            refined.fov = glam::vec2(tan_half.x.atan() * 2.0, tan_half.y.atan() * 2.0);
+        }
+    }
+    refined.center_uv += glam::vec2(delta[2], delta[3]);
+    refined
+}
@@ -1,6 +1,9 @@
+pub mod appearance;
 pub mod eval;
 pub mod ssim;
 pub mod train;
 
 pub mod image;
+pub mod intrinsics;
+pub mod pose;
 pub mod scene;
@@ -0,0 +1,226 @@
+use brush_render::camera::Camera;
+use burn::{
+    module::{Module, Param, ParamId},
+    tensor::{backend::Backend, Tensor},
+};
+
+/// Learned corrections to the poses of the training views.
+///
+/// Each view has a small rigid transform, stored as a tangent vector of a translation
+/// followed by an axis-angle rotation. The transform is applied in camera space, on top
+/// of the original world to camera transform.
+#[derive(Module, Debug)]
+pub struct PoseRefinement<B: Backend> {
+    // [num_views, 6], translation followed by axis-angle rotation.
+    pub deltas: Param<Tensor<B, 2>>,
+}
+
+fn delta_to_mat(delta: &[f32]) -> glam::Mat4 {
+    let translation = glam::vec3(delta[0], delta[1], delta[2]);
+    let rotation = glam::Quat::from_scaled_axis(glam::vec3(delta[3], delta[4], delta[5]));
+    glam::Mat4::from_rotation_translation(rotation, translation)
+}
+
+fn mat_to_tensor<B: Backend>(mat: glam::Mat4, device: &B::Device) -> Tensor<B, 2> {
+    // Tensors are row major, so upload the columns of the transpose.
+    Tensor::<B, 1>::from_floats(mat.transpose().to_cols_array(), device).reshape([4, 4])
+}
+
+// Same as delta_to_mat, but with tensor ops so gradients flow back to the delta.
+fn delta_to_tensor<B: Backend>(delta: Tensor<B, 2>) -> Tensor<B, 2> {
+    let device = delta.device();
+    let translation = delta.clone().slice([0..1, 0..3]);
+    let axis_angle = delta.slice([0..1, 3..6]);
+
+    // Maps (x, y, z) to the flattened cross product matrix.
+    let cross = Tensor::<B, 1>::from_floats(
+        [
+            0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0, //
+            0.0, 0.0, 1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, //
+            0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+        ],
+        &device,
+    )
+    .reshape([3, 9]);
+    let k = axis_angle.clone().matmul(cross).reshape([3, 3]);
+
+    // Rodrigues' formula. The epsilon keeps the gradient finite at a zero rotation.
+    let theta = (axis_angle.powf_scalar(2.0).sum() + 1e-12).sqrt();
+    let sin_term = theta.clone().sin() / theta.clone();
+    let cos_term = (-theta.clone().cos() + 1.0) / theta.powf_scalar(2.0);
+    let identity = Tensor::<B, 1>::from_floats(
+        [
+            1.0, 0.0, 0.0, //
+            0.0, 1.0, 0.0, //
+            0.0, 0.0, 1.0,
+        ],
+        &device,
+    )
+    .reshape([3, 3]);
+    let rotation =
+        identity + k.clone() * sin_term.unsqueeze() + k.clone().matmul(k) * cos_term.unsqueeze();
+
+    let last_row = Tensor::<B, 1>::from_floats([0.0, 0.0, 0.0, 1.0], &device).reshape([1, 4]);
+    Tensor::cat(
+        vec![
+            Tensor::cat(vec![rotation, translation.transpose()], 1),
+            last_row,
+        ],
+        0,
+    )
+}
+
+impl<B: Backend> PoseRefinement<B> {
+    pub fn new(num_views: usize, device: &B::Device) -> Self {
+        Self {
+            deltas: Param::initialized(
+                ParamId::new(),
+                Tensor::zeros([num_views, 6], device).require_grad(),
+            ),
+        }
+    }
+
+    /// Read back the deltas of all views, to pass to [`Self::refine_view`]. Reading these once
+    /// per step avoids a readback for every view.
+    pub async fn read_deltas(&self) -> Vec<f32> {
+        self.deltas
+            .val()
+            .into_data_async()
+            .await
+            .to_vec::<f32>()
+            .expect("Failed to read pose deltas")
+    }
+
+    /// Returns the refined camera of a view, and its world to camera matrix. The matrix
+    /// carries gradients back to the pose delta, see [`brush_render::CameraGrads`].
+    ///
+    /// `deltas` are the current deltas of all views, see [`Self::read_deltas`].
+    pub fn refine_view(
+        &self,
+        view_id: usize,
+        camera: &Camera,
+        deltas: &[f32],
+    ) -> (Camera, Tensor<B, 2>) {
+        let device = self.deltas.device();
+        let delta = self.deltas.val().slice([view_id..view_id + 1, 0..6]);
+        let delta_values = &deltas[view_id * 6..view_id * 6 + 6];
+
+        let world_to_local = camera.world_to_local();
+        let refined = refine_camera(camera, delta_to_mat(delta_values));
+        let viewmat = delta_to_tensor(delta).matmul(mat_to_tensor(world_to_local, &device));
+        (refined, viewmat)
+    }
+
+    /// Returns the refined cameras of all views.
+    pub async fn refined_cameras(&self, cameras: &[Camera]) -> Vec<Camera> {
+        let deltas = self.read_deltas().await;
+
+        cameras
+            .iter()
+            .zip(deltas.chunks_exact(6))
+            .map(|(camera, delta)| refine_camera(camera, delta_to_mat(delta)))
+            .collect()
+    }
+}
+
+fn refine_camera(camera: &Camera, delta: glam::Mat4) -> Camera {
+    let local_to_world = (delta * camera.world_to_local()).inverse();
+    let (_, rotation, position) = local_to_world.to_scale_rotation_translation();
+    let mut refined = camera.clone();
+    refined.position = position;
+    refined.rotation = rotation;
+    refined
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use async_std::task;
+    use brush_render::{
+        bounding_box::BoundingBox,
+        gaussian_splats::{RandomSplatsConfig, Splats},
+        CameraGrads, PrimaryBackend, RenderOptions,
+    };
+    use burn::backend::{wgpu::WgpuDevice, Autodiff};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type DiffBack = Autodiff<PrimaryBackend>;
+
+    #[test]
+    fn delta_grad_matches_finite_difference() {
+        let device = WgpuDevice::BestAvailable;
+        let mut rng = StdRng::seed_from_u64(4);
+        // Nb: Only SH degree 0, the view direction of the SH doesn't have a camera gradient.
+        let splats = Splats::<DiffBack>::from_random_config(
+            RandomSplatsConfig::new().with_init_count(500),
+            BoundingBox::from_min_max(glam::vec3(-2.0, -2.0, 3.0), glam::vec3(2.0, 2.0, 7.0)),
+            &mut rng,
+            &device,
+        );
+        let img_size = glam::uvec2(64, 48);
+        let cam = Camera::new(
+            glam::vec3(0.1, -0.2, 0.0),
+            glam::Quat::from_rotation_y(0.05),
+            glam::vec2(0.8, 0.8),
+            glam::vec2(0.5, 0.5),
+        );
+
+        // A small rotation, so the gradient goes through the full Rodrigues' formula.
+        let delta = [0.02, -0.01, 0.03, 0.03, -0.02, 0.01];
+
+        let loss = |delta: [f32; 6]| {
+            let poses = PoseRefinement::<DiffBack> {
+                deltas: Param::initialized(
+                    ParamId::new(),
+                    Tensor::<DiffBack, 1>::from_floats(delta, &device)
+                        .reshape([1, 6])
+                        .require_grad(),
+                ),
+            };
+            let (refined, viewmat) = poses.refine_view(0, &cam, &delta);
+            let (img, _) = splats.render_with(
+                &refined,
+                img_size,
+                glam::Vec3::ZERO,
+                RenderOptions {
+                    camera_grads: CameraGrads {
+                        viewmat: Some(viewmat),
+                        intrinsics: None,
+                    },
+                    ..Default::default()
+                },
+            );
+            (poses, img.powf_scalar(2.0).sum())
+        };
+
+        let (poses, value) = loss(delta);
+        let grads = value.backward();
+        let v_delta = poses
+            .deltas
+            .val()
+            .grad(&grads)
+            .expect("Pose delta needs a gradient")
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap();
+
+        for i in 0..6 {
+            let eps = 5e-3;
+            let mut delta_pos = delta;
+            delta_pos[i] += eps;
+            let mut delta_neg = delta;
+            delta_neg[i] -= eps;
+
+            let finite_diff = (loss(delta_pos).1 - loss(delta_neg).1).into_scalar() / (2.0 * eps);
+            let err = (v_delta[i] - finite_diff).abs() / finite_diff.abs().max(1e-3);
+            assert!(
+                err < 0.1,
+                "Gradient {} of delta {i} doesn't match finite difference {finite_diff}",
+                v_delta[i]
+            );
+        }
+
+        // The readback of the deltas matches what the views are refined with.
+        assert_eq!(task::block_on(poses.read_deltas()), delta);
+    }
+}
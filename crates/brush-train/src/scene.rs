@@ -40,14 +40,17 @@ impl Scene {
         }
     }
 
-    // Returns the extent of the cameras in the scene.
+    // Returns the extent of the cameras in the scene, looking between cam_near and cam_far.
+    // This range is limited to the near and far planes of each camera.
     pub fn bounds(&self, cam_near: f32, cam_far: f32) -> BoundingBox {
         let (min, max) = self.views.iter().fold(
             (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
             |(min, max), view| {
                 let cam = &view.camera;
-                let pos1 = cam.position + cam.rotation * Vec3::Z * cam_near;
-                let pos2 = cam.position + cam.rotation * Vec3::Z * cam_far;
+                let near = cam_near.max(cam.near);
+                let far = cam_far.min(cam.far).max(near);
+                let pos1 = cam.position + cam.rotation * Vec3::Z * near;
+                let pos2 = cam.position + cam.rotation * Vec3::Z * far;
                 (min.min(pos1).min(pos2), max.max(pos1).max(pos2))
             },
         );
@@ -1,6 +1,10 @@
 use burn::tensor::{backend::Backend, module::conv2d, ops::ConvOptions, Tensor};
 
+// SSIM needs blurred versions of img1, img2, img1^2, img2^2 and img1 * img2.
+const NUM_BLURRED: usize = 5;
+
 pub struct Ssim<B: Backend> {
+    // Horizontal 1D window for every blurred channel, as [channels out, in, h, w].
     weights: Tensor<B, 4>,
 }
 
@@ -14,81 +18,58 @@ fn gaussian<B: Backend>(window_size: usize, sigma: f32, device: &B::Device) -> T
 }
 
 impl<B: Backend> Ssim<B> {
-    // TODO: Try a seperable convolution.
-    // fn gaussian_blur<B: Backend>(img: Tensor<B, 4>, window: Tensor<B, 4>) -> Tensor<B, 4> {
-    //     let [_, channel, _, _] = img.dims();
-    //     let window_size = window.dims()[2];
-    //     let padding = window_size / 2;
-    //     let conv_options = ConvOptions::new([1, 1], [padding, 1], [1, 1], channel);
-    //     let xx = conv2d(img.clone(), window.clone(), None, conv_options.clone());
-    //     println!("Window shape {:?}", window.dims());
-    //     let conv_options = ConvOptions::new([1, 1], [1, padding], [1, 1], channel);
-    //     conv2d(
-    //         xx.clone(),
-    //         window.clone().permute([0, 1, 3, 2]),
-    //         None,
-    //         conv_options.clone(),
-    //     )
-    // }
-
     pub fn new(window_size: usize, channels: usize, device: &B::Device) -> Self {
-        let window1d = gaussian(window_size, 1.5, device).reshape([window_size, 1]);
-        let window2d = window1d.clone().matmul(window1d.transpose());
-        // Channels out, in, h, w.
-        let weights = window2d.unsqueeze().repeat_dim(0, channels);
+        let window = gaussian(window_size, 1.5, device).reshape([1, 1, 1, window_size]);
+        let weights = window.repeat_dim(0, channels * NUM_BLURRED);
         Self { weights }
     }
 
+    // The gaussian window is separable, so blur with a horizontal and a vertical 1D window,
+    // which needs 2 * window_size taps per pixel instead of window_size^2.
+    fn gaussian_blur(&self, img: Tensor<B, 4>) -> Tensor<B, 4> {
+        let [channels, _, _, window_size] = self.weights.dims();
+        let padding = window_size / 2;
+
+        let options_x = ConvOptions::new([1, 1], [0, padding], [1, 1], channels);
+        let blur_x = conv2d(img, self.weights.clone(), None, options_x);
+
+        let options_y = ConvOptions::new([1, 1], [padding, 0], [1, 1], channels);
+        conv2d(
+            blur_x,
+            self.weights.clone().swap_dims(2, 3),
+            None,
+            options_y,
+        )
+    }
+
     pub fn ssim(&self, img1: Tensor<B, 4>, img2: Tensor<B, 4>) -> Tensor<B, 1> {
         // Images are [N, H, W, C], need them as [N, C, H, W].
         let img1 = img1.permute([0, 3, 1, 2]).clamp(0.0, 1.0);
         let img2 = img2.permute([0, 3, 1, 2]).clamp(0.0, 1.0);
 
-        let [channels, _, _, window_size] = self.weights.dims();
-        let padding = window_size / 2;
-        let conv_options = ConvOptions::new([1, 1], [padding, padding], [1, 1], channels);
-        let mu1 = conv2d(
-            img1.clone(),
-            self.weights.clone(),
-            None,
-            conv_options.clone(),
-        );
-        let mu2 = conv2d(
-            img2.clone(),
-            self.weights.clone(),
-            None,
-            conv_options.clone(),
-        );
+        // Blur all the images in one go.
+        let blurred = self.gaussian_blur(Tensor::cat(
+            vec![
+                img1.clone(),
+                img2.clone(),
+                img1.clone().powi_scalar(2),
+                img2.clone().powi_scalar(2),
+                img1 * img2,
+            ],
+            1,
+        ));
+        let [mu1, mu2, img1_sq, img2_sq, img12]: [Tensor<B, 4>; NUM_BLURRED] = blurred
+            .chunk(NUM_BLURRED, 1)
+            .try_into()
+            .expect("Expected a chunk per blurred image");
 
-        // let mu1 = gaussian_blur(img1.clone(), window.clone());
-        // let mu2 = gaussian_blur(img1.clone(), window.clone());
         let mu1_sq = mu1.clone().powi_scalar(2);
         let mu2_sq = mu2.clone().powi_scalar(2);
         let mu1_mu2 = mu1 * mu2;
 
-        // let sigma1_sq = gaussian_blur(img1.clone().powf_scalar(2.0), window.clone()) - mu1_sq.clone();
-        // let sigma2_sq = gaussian_blur(img2.clone().powf_scalar(2.0), window.clone()) - mu2_sq.clone();
-        // let sigma12 = gaussian_blur(img1.clone() * img2.clone(), window) - mu1_mu2.clone();
-        let sigma1_sq = conv2d(
-            img1.clone().powi_scalar(2.0),
-            self.weights.clone(),
-            None,
-            conv_options.clone(),
-        ) - mu1_sq.clone();
-
-        let sigma2_sq = conv2d(
-            img2.clone().powi_scalar(2.0),
-            self.weights.clone(),
-            None,
-            conv_options.clone(),
-        ) - mu2_sq.clone();
-
-        let sigma12 = conv2d(
-            img1.clone() * img2.clone(),
-            self.weights.clone(),
-            None,
-            conv_options.clone(),
-        ) - mu1_mu2.clone();
+        let sigma1_sq = img1_sq - mu1_sq.clone();
+        let sigma2_sq = img2_sq - mu2_sq.clone();
+        let sigma12 = img12 - mu1_mu2.clone();
 
         let c1: f32 = 0.01f32.powf(2.0);
         let c2: f32 = 0.03f32.powf(2.0);
@@ -98,3 +79,68 @@ impl<B: Backend> Ssim<B> {
         ssim_map.mean()
     }
 }
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use brush_render::PrimaryBackend;
+    use burn::{backend::wgpu::WgpuDevice, tensor::Distribution};
+
+    type B = PrimaryBackend;
+
+    // SSIM with a full 2D window, as the separable blur should give the same result.
+    fn reference_ssim(img1: Tensor<B, 4>, img2: Tensor<B, 4>, window_size: usize) -> f32 {
+        let device = img1.device();
+        let img1 = img1.permute([0, 3, 1, 2]).clamp(0.0, 1.0);
+        let img2 = img2.permute([0, 3, 1, 2]).clamp(0.0, 1.0);
+        let channels = img1.dims()[1];
+
+        let window1d = gaussian::<B>(window_size, 1.5, &device).reshape([window_size, 1]);
+        let window2d = window1d.clone().matmul(window1d.transpose());
+        let weights = window2d.unsqueeze::<4>().repeat_dim(0, channels);
+        let padding = window_size / 2;
+        let options = ConvOptions::new([1, 1], [padding, padding], [1, 1], channels);
+        let blur = |img: Tensor<B, 4>| conv2d(img, weights.clone(), None, options.clone());
+
+        let mu1 = blur(img1.clone());
+        let mu2 = blur(img2.clone());
+        let sigma1_sq = blur(img1.clone().powi_scalar(2)) - mu1.clone().powi_scalar(2);
+        let sigma2_sq = blur(img2.clone().powi_scalar(2)) - mu2.clone().powi_scalar(2);
+        let sigma12 = blur(img1 * img2) - mu1.clone() * mu2.clone();
+
+        let c1 = 0.01f32.powf(2.0);
+        let c2 = 0.03f32.powf(2.0);
+        let ssim_map = ((mu1.clone() * mu2.clone() * 2.0 + c1) * (sigma12 * 2.0 + c2))
+            / ((mu1.powi_scalar(2) + mu2.powi_scalar(2) + c1) * (sigma1_sq + sigma2_sq + c2));
+        ssim_map.mean().into_scalar()
+    }
+
+    #[test]
+    fn ssim_matches_2d_window() {
+        let device = WgpuDevice::BestAvailable;
+        let shape = [2, 24, 32, 3];
+        let img1 = Tensor::<B, 4>::random(shape, Distribution::Uniform(0.0, 1.0), &device);
+        // A noisy version of the first image, so the images are correlated.
+        let img2 = (img1.clone()
+            + Tensor::random(shape, Distribution::Uniform(-0.2, 0.2), &device))
+        .clamp(0.0, 1.0);
+
+        for window_size in [5, 11] {
+            let ssim = Ssim::<B>::new(window_size, 3, &device);
+
+            let same = ssim.ssim(img1.clone(), img1.clone()).into_scalar();
+            assert!(
+                (same - 1.0).abs() < 1e-4,
+                "SSIM of identical images is {same} for window {window_size}"
+            );
+
+            let value = ssim.ssim(img1.clone(), img2.clone()).into_scalar();
+            let reference = reference_ssim(img1.clone(), img2.clone(), window_size);
+            assert!(value > 0.0 && value < 0.99, "Unexpected SSIM {value}");
+            assert!(
+                (value - reference).abs() < 1e-4,
+                "SSIM {value} differs from the 2D window result {reference} for window {window_size}"
+            );
+        }
+    }
+}
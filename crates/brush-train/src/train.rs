@@ -1,6 +1,6 @@
 use anyhow::Result;
 use brush_render::gaussian_splats::{inverse_sigmoid, Splats};
-use brush_render::{AutodiffBackend, Backend, RenderAux};
+use brush_render::{AutodiffBackend, Backend, CameraGrads, RenderAux, RenderOptions};
 use burn::lr_scheduler::exponential::{ExponentialLrScheduler, ExponentialLrSchedulerConfig};
 use burn::lr_scheduler::LrScheduler;
 use burn::optim::adaptor::OptimizerAdaptor;
@@ -8,12 +8,16 @@ use burn::optim::Adam;
 use burn::tensor::{Bool, Distribution, Int};
 use burn::{
     config::Config,
+    module::Param,
     optim::{AdamConfig, GradientsParams, Optimizer},
     tensor::Tensor,
 };
 use tracing::trace_span;
 
-use crate::scene::SceneView;
+use crate::appearance::AppearanceModel;
+use crate::intrinsics::IntrinsicsRefinement;
+use crate::pose::PoseRefinement;
+use crate::scene::{Scene, SceneView};
 use crate::ssim::Ssim;
 
 #[derive(Config)]
@@ -59,13 +63,40 @@ pub struct TrainConfig {
     #[config(default = 0.2)]
     ssim_weight: f32,
 
-    // TODO: Up this to 11 when convolutions aren't as slow anymore
-    #[config(default = 5)]
+    #[config(default = 11)]
     ssim_window_size: usize,
 
     #[config(default = true)]
     scale_mean_lr_by_extent: bool,
 
+    // Limit the size of splats to the sampling rate of the training views (Mip-Splatting 3D filter).
+    #[config(default = false)]
+    filter_3d: bool,
+
+    // Every this many steps, recompute the 3D filter. Zero only computes it after refining.
+    #[config(default = 100)]
+    filter_3d_every: u32,
+
+    // Learn a color transform per training view, to compensate for changes in exposure and white balance.
+    #[config(default = false)]
+    appearance_model: bool,
+
+    // Weight of the penalty that keeps the appearance transforms close to the identity.
+    #[config(default = 0.01)]
+    appearance_reg_weight: f32,
+
+    // Optimize small corrections to the poses of the training views.
+    #[config(default = false)]
+    refine_poses: bool,
+
+    // Optimize corrections to the focal length and principal point of the training cameras.
+    #[config(default = false)]
+    refine_intrinsics: bool,
+
+    // Whether all training views share one set of intrinsics, or each view gets its own.
+    #[config(default = true)]
+    shared_intrinsics: bool,
+
     // Learning rates.
     lr_mean: ExponentialLrSchedulerConfig,
 
@@ -86,14 +117,35 @@ pub struct TrainConfig {
     #[config(default = 0.001)]
     lr_rotation: f64,
 
+    #[config(default = 0.001)]
+    lr_appearance: f64,
+
+    #[config(default = 0.0001)]
+    lr_pose: f64,
+
+    #[config(default = 0.0001)]
+    lr_intrinsics: f64,
+
     #[config(default = 42)]
     seed: u64,
+
+    // Make the gradients independent of the GPU thread order, so runs with the same
+    // seed and data give identical results. This makes training slower.
+    #[config(default = false)]
+    deterministic: bool,
+
+    // Sort the splats per pixel by where they contribute most along the ray, which reduces
+    // popping when the view rotates. Can't be combined with deterministic training.
+    #[config(default = false)]
+    resort_splats: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct SceneBatch<B: Backend> {
     pub gt_images: Tensor<B, 4>,
     pub gt_views: Vec<SceneView>,
+    // Index of each view in the training scene.
+    pub gt_view_ids: Vec<usize>,
     pub scene_extent: f64,
 }
 
@@ -110,6 +162,8 @@ pub struct TrainStepStats<B: AutodiffBackend> {
     pub pred_images: Tensor<B, 4>,
     pub gt_images: Tensor<B, 4>,
     pub gt_views: Vec<SceneView>,
+    /// The render aux per view. Without camera refinement, the batch is rendered at once, so
+    /// this holds a single aux for all views.
     pub auxes: Vec<RenderAux>,
     pub loss: Tensor<B, 1>,
     pub lr_mean: f64,
@@ -118,6 +172,10 @@ pub struct TrainStepStats<B: AutodiffBackend> {
     pub lr_coeffs: f64,
     pub lr_opac: f64,
 
+    /// Refined intrinsics per camera, as the focal length scale (x, y) followed by the
+    /// principal point offset in uv (u, v). Only set when refining intrinsics.
+    pub intrinsics: Option<Tensor<B, 2>>,
+
     pub refine: Option<RefineStats>,
 }
 
@@ -133,6 +191,15 @@ where
     optim: OptimizerAdaptor<Adam<B::InnerBackend>, Splats<B>, B>,
     opt_config: AdamConfig,
 
+    appearance: Option<AppearanceModel<B>>,
+    appearance_optim: OptimizerAdaptor<Adam<B::InnerBackend>, AppearanceModel<B>, B>,
+
+    poses: Option<PoseRefinement<B>>,
+    pose_optim: OptimizerAdaptor<Adam<B::InnerBackend>, PoseRefinement<B>, B>,
+
+    intrinsics: Option<IntrinsicsRefinement<B>>,
+    intrinsics_optim: OptimizerAdaptor<Adam<B::InnerBackend>, IntrinsicsRefinement<B>, B>,
+
     // Helper tensors for accumulating the viewspace_xy gradients and the number
     // of observations per gaussian. Used in pruning and densification.
     grad_2d_accum: Tensor<B, 1>,
@@ -184,23 +251,51 @@ impl<B: AutodiffBackend> SplatTrainer<B>
 where
     B::InnerBackend: Backend,
 {
-    pub fn new(num_points: usize, config: &TrainConfig, splats: &Splats<B>) -> Self {
+    pub fn new(
+        num_points: usize,
+        num_views: usize,
+        config: &TrainConfig,
+        splats: &Splats<B>,
+    ) -> Result<Self> {
+        // The resorted backward pass adds up gradients in whatever order threads run.
+        if config.deterministic && config.resort_splats {
+            anyhow::bail!("Deterministic training can't be combined with resorting splats.");
+        }
+
         let opt_config = AdamConfig::new().with_epsilon(1e-15);
         let optim = opt_config.init::<B, Splats<B>>();
+        let appearance_optim = opt_config.init::<B, AppearanceModel<B>>();
+        let pose_optim = opt_config.init::<B, PoseRefinement<B>>();
+        let intrinsics_optim = opt_config.init::<B, IntrinsicsRefinement<B>>();
 
         let device = &splats.means.device();
+        let appearance = config
+            .appearance_model
+            .then(|| AppearanceModel::new(num_views, device));
+        let poses = config
+            .refine_poses
+            .then(|| PoseRefinement::new(num_views, device));
+        let intrinsics = config
+            .refine_intrinsics
+            .then(|| IntrinsicsRefinement::new(num_views, config.shared_intrinsics, device));
 
         let ssim = Ssim::new(config.ssim_window_size, 3, device);
-        Self {
+        Ok(Self {
             config: config.clone(),
             iter: 0,
             sched_mean: config.lr_mean.init(),
             optim,
             opt_config,
+            appearance,
+            appearance_optim,
+            poses,
+            pose_optim,
+            intrinsics,
+            intrinsics_optim,
             grad_2d_accum: Tensor::zeros([num_points], device),
             xy_grad_counts: Tensor::zeros([num_points], device),
             ssim,
-        }
+        })
     }
 
     fn reset_stats(&mut self, num_points: usize, device: &B::Device) {
@@ -208,9 +303,61 @@ where
         self.xy_grad_counts = Tensor::zeros([num_points], device);
     }
 
+    /// Recompute the 3D filter from the training views, if enabled. This is done every
+    /// `filter_3d_every` steps, and after refining, as new splats don't have a filter yet.
+    pub fn update_filter_3d(&self, splats: &mut Splats<B>, scene: &Scene, refined: bool) {
+        if !self.config.filter_3d {
+            return;
+        }
+
+        let every = self.config.filter_3d_every;
+        let periodic = every != 0 && self.iter % every == 0;
+        if splats.filter_3d.is_some() && !refined && !periodic {
+            return;
+        }
+
+        splats.update_filter_3d(scene.views.iter().map(|view| {
+            (
+                &view.camera,
+                glam::uvec2(view.image.width(), view.image.height()),
+            )
+        }));
+    }
+
+    /// The training scene with the refined cameras, if poses or intrinsics are being refined.
+    pub async fn refined_scene(&self, scene: &Scene) -> Option<Scene> {
+        if self.poses.is_none() && self.intrinsics.is_none() {
+            return None;
+        }
+
+        let mut cameras: Vec<_> = scene.views.iter().map(|v| v.camera.clone()).collect();
+        if let Some(poses) = &self.poses {
+            cameras = poses.refined_cameras(&cameras).await;
+        }
+        if let Some(intrinsics) = &self.intrinsics {
+            cameras = intrinsics.refined_cameras(&cameras).await;
+        }
+
+        let views = scene
+            .views
+            .iter()
+            .zip(cameras)
+            .map(|(view, camera)| SceneView {
+                camera,
+                ..view.clone()
+            })
+            .collect();
+        Some(Scene::new(views, scene.background))
+    }
+
     pub(crate) fn reset_opacity(&self, splats: &mut Splats<B>) {
+        let locked = splats.locked_mask();
         Splats::map_param(&mut splats.raw_opacity, |op| {
-            Tensor::zeros_like(&op) + inverse_sigmoid(self.config.reset_alpha_value)
+            let reset = Tensor::zeros_like(&op) + inverse_sigmoid(self.config.reset_alpha_value);
+            match &locked {
+                Some(locked) => reset.mask_where(locked.clone(), op),
+                None => reset,
+            }
         });
     }
 
@@ -225,30 +372,82 @@ where
         let [batch_size, img_h, img_w, _] = batch.gt_images.dims();
 
         let (pred_images, auxes, loss) = {
-            let mut renders = vec![];
-            let mut auxes = vec![];
-
-            for i in 0..batch.gt_views.len() {
-                let camera = &batch.gt_views[i].camera;
-
-                let (pred_image, aux) = splats.render(
-                    camera,
-                    glam::uvec2(img_w as u32, img_h as u32),
+            let img_size = glam::uvec2(img_w as u32, img_h as u32);
+
+            let (pred_images, auxes) = if self.poses.is_none() && self.intrinsics.is_none() {
+                // Without camera refinement, the whole batch can be rendered at once.
+                let cameras: Vec<_> = batch.gt_views.iter().map(|v| v.camera.clone()).collect();
+                let (pred_images, aux) = splats.render_batch(
+                    &cameras,
+                    img_size,
                     background_color,
-                    false,
+                    RenderOptions {
+                        deterministic: self.config.deterministic,
+                        resort: self.config.resort_splats,
+                        ..Default::default()
+                    },
                 );
-
-                renders.push(pred_image);
-                auxes.push(aux);
-            }
-
-            let pred_images = Tensor::stack(renders, 0);
+                (pred_images, vec![aux])
+            } else {
+                let mut renders = vec![];
+                let mut auxes = vec![];
+
+                // Read the current camera corrections once for the whole batch.
+                let pose_deltas = match &self.poses {
+                    Some(poses) => poses.read_deltas().await,
+                    None => vec![],
+                };
+                let intrinsics_deltas = match &self.intrinsics {
+                    Some(intrinsics) => intrinsics.read_deltas().await,
+                    None => vec![],
+                };
+
+                for i in 0..batch.gt_views.len() {
+                    let mut camera = batch.gt_views[i].camera.clone();
+                    let view_id = batch.gt_view_ids[i];
+
+                    let mut camera_grads = CameraGrads::default();
+                    if let Some(poses) = &self.poses {
+                        let (refined, viewmat) = poses.refine_view(view_id, &camera, &pose_deltas);
+                        camera = refined;
+                        camera_grads.viewmat = Some(viewmat);
+                    }
+
+                    if let Some(refinement) = &self.intrinsics {
+                        let (refined, intrinsics) =
+                            refinement.refine_view(view_id, &camera, img_size, &intrinsics_deltas);
+                        camera = refined;
+                        camera_grads.intrinsics = Some(intrinsics);
+                    }
+
+                    let (pred_image, aux) = splats.render_with(
+                        &camera,
+                        img_size,
+                        background_color,
+                        RenderOptions {
+                            deterministic: self.config.deterministic,
+                            resort: self.config.resort_splats,
+                            camera_grads,
+                            ..Default::default()
+                        },
+                    );
+
+                    renders.push(pred_image);
+                    auxes.push(aux);
+                }
+
+                (Tensor::stack(renders, 0), auxes)
+            };
 
             let _span = trace_span!("Calculate losses", sync_burn = true).entered();
 
             let pred_rgb = pred_images
                 .clone()
                 .slice([0..batch_size, 0..img_h, 0..img_w, 0..3]);
+            let pred_rgb = match &self.appearance {
+                Some(appearance) => appearance.apply(pred_rgb, &batch.gt_view_ids),
+                None => pred_rgb,
+            };
             let gt_rgb = batch
                 .gt_images
                 .clone()
@@ -261,6 +460,13 @@ where
             } else {
                 loss
             };
+            let loss = match &self.appearance {
+                Some(appearance) => {
+                    loss + appearance.identity_penalty(&batch.gt_view_ids)
+                        * self.config.appearance_reg_weight
+                }
+                None => loss,
+            };
 
             (pred_images, auxes, loss)
         };
@@ -297,6 +503,7 @@ where
         });
 
         let post_step_splat = trace_span!("Optimizer step", sync_burn = true).in_scope(|| {
+            let splats_pre = &splats;
             let mut splats = splats.clone();
             let grad_means = GradientsParams::from_params(&mut grads, &splats, &[splats.means.id]);
             splats = self.optim.step(lr_mean, splats, grad_means);
@@ -335,9 +542,55 @@ where
 
             // Make sure rotations are still valid after optimization step.
             splats.norm_rotations();
+
+            if let Some(locked) = splats.locked_mask() {
+                keep_locked(&mut splats.means, splats_pre.means.val(), &locked);
+                keep_locked(
+                    &mut splats.raw_opacity,
+                    splats_pre.raw_opacity.val(),
+                    &locked,
+                );
+                keep_locked(&mut splats.sh_coeffs, splats_pre.sh_coeffs.val(), &locked);
+                keep_locked(&mut splats.rotation, splats_pre.rotation.val(), &locked);
+                keep_locked(&mut splats.log_scales, splats_pre.log_scales.val(), &locked);
+            }
             splats
         });
 
+        if let Some(appearance) = self.appearance.take() {
+            let grad_appearance =
+                GradientsParams::from_params(&mut grads, &appearance, &[appearance.transforms.id]);
+            self.appearance = Some(self.appearance_optim.step(
+                self.config.lr_appearance,
+                appearance,
+                grad_appearance,
+            ));
+        }
+
+        if let Some(poses) = self.poses.take() {
+            let grad_poses = GradientsParams::from_params(&mut grads, &poses, &[poses.deltas.id]);
+            self.poses = Some(self.pose_optim.step(self.config.lr_pose, poses, grad_poses));
+        }
+
+        if let Some(intrinsics) = self.intrinsics.take() {
+            let grad_intrinsics =
+                GradientsParams::from_params(&mut grads, &intrinsics, &[intrinsics.deltas.id]);
+            self.intrinsics = Some(self.intrinsics_optim.step(
+                self.config.lr_intrinsics,
+                intrinsics,
+                grad_intrinsics,
+            ));
+        }
+
+        // Report the intrinsics as focal scale and center offset.
+        let intrinsics = self.intrinsics.as_ref().map(|intrinsics| {
+            let deltas = intrinsics.deltas.val();
+            let num_cameras = deltas.dims()[0];
+            let focal_scale = deltas.clone().slice([0..num_cameras, 0..2]).exp();
+            let center_offset = deltas.slice([0..num_cameras, 2..4]);
+            Tensor::cat(vec![focal_scale, center_offset], 1)
+        });
+
         let mut refine_stats = None;
 
         let do_refine = self.iter < max_refine_step
@@ -366,6 +619,7 @@ where
             lr_scale,
             lr_coeffs,
             lr_opac,
+            intrinsics,
             refine: refine_stats,
         };
 
@@ -384,6 +638,11 @@ where
         // Otherwise, do refinement, but do the split/clone on gaussians with no grads applied.
         let grads =
             self.grad_2d_accum.clone() / self.xy_grad_counts.clone().clamp(1, i32::MAX).float();
+        // Locked splats are never split or cloned.
+        let grads = match splats_pre_step.locked_mask() {
+            Some(locked) => grads.mask_fill(locked, 0.0),
+            None => grads,
+        };
 
         let big_grad_mask = grads.greater_equal_elem(self.config.densify_grad_thresh);
         let split_clone_size_mask = splats_post_step
@@ -523,19 +782,23 @@ where
         let start_count = splats.num_splats();
 
         // Remove barely visible gaussians.
-        let alpha_mask = splats.opacity().lower_elem(self.config.cull_alpha_thresh);
+        let opacity = splats.opacity();
+        let opacity = match splats.locked_mask() {
+            Some(locked) => opacity.mask_fill(locked, 1.0),
+            None => opacity,
+        };
+        let alpha_mask = opacity.lower_elem(self.config.cull_alpha_thresh);
         prune_points(&mut splats, alpha_mask).await;
 
         let alpha_pruned = start_count - splats.num_splats();
 
         // Delete Gaussians with too large of a radius in world-units.
-        let scale_mask = splats
-            .log_scales
-            .val()
-            .exp()
-            .max_dim(1)
-            .squeeze(1)
-            .greater_elem(self.config.cull_scale_thresh);
+        let max_scale = splats.log_scales.val().exp().max_dim(1).squeeze(1);
+        let max_scale = match splats.locked_mask() {
+            Some(locked) => max_scale.mask_fill(locked, 0.0),
+            None => max_scale,
+        };
+        let scale_mask = max_scale.greater_elem(self.config.cull_scale_thresh);
         prune_points(&mut splats, scale_mask).await;
 
         let scale_pruned = start_count - splats.num_splats();
@@ -562,6 +825,24 @@ where
     }
 }
 
+// Sets the locked splats of a parameter back to their values before the optimizer step.
+fn keep_locked<B: AutodiffBackend, const D: usize>(
+    param: &mut Param<Tensor<B, D>>,
+    before: Tensor<B, D>,
+    locked: &Tensor<B, 1, Bool>,
+) {
+    Splats::map_param(param, |x| {
+        let dims = x.dims();
+        let mut mask_shape = [1; D];
+        mask_shape[0] = dims[0];
+        let mut mask = locked.clone().reshape(mask_shape);
+        for (dim, &size) in dims.iter().enumerate().skip(1) {
+            mask = mask.repeat_dim(dim, size);
+        }
+        x.mask_where(mask, before.clone())
+    });
+}
+
 // Prunes points based on the given mask.
 //
 // Args:
@@ -599,6 +880,14 @@ pub async fn prune_points<B: AutodiffBackend>(splats: &mut Splats<B>, prune: Ten
             .log_scales
             .clone()
             .map(|x| Tensor::from_inner(x.select(0, valid_inds.clone()).inner()).require_grad());
+        splats.flags = splats
+            .flags
+            .take()
+            .map(|flags| flags.select(0, valid_inds.clone()));
+        splats.filter_3d = splats
+            .filter_3d
+            .take()
+            .map(|filter| filter.select(0, valid_inds.clone()));
     }
 }
 
@@ -625,4 +914,147 @@ pub fn concat_splats<B: AutodiffBackend>(
     Splats::map_param(&mut splats.log_scales, |x| {
         Tensor::cat(vec![x, log_scales.clone()], 0)
     });
+    // New splats start without any flags set.
+    splats.flags = splats.flags.take().map(|flags| {
+        let new_flags = Tensor::zeros([means.dims()[0]], &means.device());
+        Tensor::cat(vec![flags, new_flags], 0)
+    });
+    // New splats are unfiltered until the filter is recomputed.
+    splats.filter_3d = splats.filter_3d.take().map(|filter| {
+        let new_filter = Tensor::zeros([means.dims()[0]], &means.device());
+        Tensor::cat(vec![filter, new_filter], 0)
+    });
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use async_std::task;
+    use brush_render::{
+        bounding_box::BoundingBox,
+        camera::Camera,
+        gaussian_splats::{RandomSplatsConfig, SPLAT_LOCKED},
+        PrimaryBackend,
+    };
+    use burn::backend::{wgpu::WgpuDevice, Autodiff};
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::sync::Arc;
+
+    type DiffBack = Autodiff<PrimaryBackend>;
+
+    fn test_splats(device: &WgpuDevice) -> Splats<DiffBack> {
+        let mut rng = StdRng::seed_from_u64(4);
+        Splats::from_random_config(
+            RandomSplatsConfig::new().with_init_count(500),
+            BoundingBox::from_min_max(glam::vec3(-2.0, -2.0, 3.0), glam::vec3(2.0, 2.0, 7.0)),
+            &mut rng,
+            device,
+        )
+    }
+
+    // A single view with a smooth gradient as ground truth.
+    fn test_batch(device: &WgpuDevice) -> SceneBatch<DiffBack> {
+        let (w, h) = (64, 48);
+        let pixels: Vec<f32> = (0..h)
+            .flat_map(|y| (0..w).flat_map(move |x| [x as f32 / w as f32, y as f32 / h as f32, 0.5]))
+            .collect();
+        let camera = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            glam::vec2(0.8, 0.6),
+            glam::vec2(0.5, 0.5),
+        );
+        SceneBatch {
+            gt_images: Tensor::<DiffBack, 1>::from_floats(pixels.as_slice(), device)
+                .reshape([1, h, w, 3]),
+            gt_views: vec![SceneView {
+                name: "test".to_owned(),
+                camera,
+                image: Arc::new(image::DynamicImage::new_rgb8(w as u32, h as u32)),
+            }],
+            gt_view_ids: vec![0],
+            scene_extent: 1.0,
+        }
+    }
+
+    fn train_steps(
+        config: &TrainConfig,
+        splats: Splats<DiffBack>,
+        steps: usize,
+        device: &WgpuDevice,
+    ) -> Splats<DiffBack> {
+        let mut trainer = SplatTrainer::new(splats.num_splats(), 1, config, &splats).unwrap();
+        let mut splats = splats;
+        for _ in 0..steps {
+            let batch = test_batch(device);
+            (splats, _) = task::block_on(trainer.step(batch, glam::Vec3::ZERO, splats)).unwrap();
+        }
+        splats
+    }
+
+    fn test_config() -> TrainConfig {
+        TrainConfig::new(ExponentialLrSchedulerConfig::new(1.6e-4, 1.0))
+    }
+
+    #[test]
+    fn locked_splats_are_not_trained() {
+        let device = WgpuDevice::BestAvailable;
+        let mut splats = test_splats(&device);
+        let num_splats = splats.num_splats();
+        let locked: Vec<bool> = (0..num_splats).map(|i| i % 2 == 0).collect();
+        let flags: Vec<u32> = locked
+            .iter()
+            .map(|&l| if l { SPLAT_LOCKED } else { 0 })
+            .collect();
+        splats.set_flags(&flags);
+
+        let params = |splats: &Splats<DiffBack>| {
+            let means = splats.means.val().into_data().to_vec::<f32>().unwrap();
+            let opacity = splats
+                .raw_opacity
+                .val()
+                .into_data()
+                .to_vec::<f32>()
+                .unwrap();
+            (means, opacity)
+        };
+        let (means, opacity) = params(&splats);
+        let trained = train_steps(&test_config(), splats, 3, &device);
+        let (trained_means, trained_opacity) = params(&trained);
+
+        let mut any_trained = false;
+        for i in 0..num_splats {
+            let same = means[i * 3..i * 3 + 3] == trained_means[i * 3..i * 3 + 3]
+                && opacity[i] == trained_opacity[i];
+            if locked[i] {
+                assert!(same, "Locked splat {i} changed");
+            } else {
+                any_trained |= !same;
+            }
+        }
+        assert!(any_trained);
+    }
+
+    #[test]
+    fn deterministic_steps_are_identical() {
+        let device = WgpuDevice::BestAvailable;
+        let config = test_config().with_deterministic(true);
+
+        let bits = |splats: Splats<DiffBack>| -> Vec<u32> {
+            [
+                splats.means.val().into_data(),
+                splats.rotation.val().into_data(),
+                splats.log_scales.val().into_data(),
+                splats.sh_coeffs.val().into_data(),
+                splats.raw_opacity.val().into_data(),
+            ]
+            .iter()
+            .flat_map(|data| data.iter::<f32>().map(f32::to_bits))
+            .collect()
+        };
+
+        let first = bits(train_steps(&config, test_splats(&device), 5, &device));
+        let second = bits(train_steps(&config, test_splats(&device), 5, &device));
+        assert!(first == second, "Deterministic training runs differ");
+    }
 }
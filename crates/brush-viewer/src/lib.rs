@@ -7,6 +7,7 @@ use viewer::{ViewerContext, ViewerMessage};
 
 mod burn_texture;
 mod orbit_controls;
+mod render_mode;
 mod timeout_future;
 
 mod panels;
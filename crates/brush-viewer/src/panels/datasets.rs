@@ -54,6 +54,10 @@ impl ViewerPanel for DatasetPanel {
                 }
                 context.dataset = d;
             }
+            ViewerMessage::RefinedCameras { scene } => {
+                // Keep the current view, only the training cameras moved.
+                context.dataset.train = scene;
+            }
             ViewerMessage::DoneLoading { training: _ } => {
                 self.loading = false;
             }
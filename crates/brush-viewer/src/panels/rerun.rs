@@ -264,6 +264,28 @@ impl VisualizeTools {
             rec.log("lr/coeffs", &rerun::Scalar::new(stats.lr_coeffs))?;
             rec.log("lr/opac", &rerun::Scalar::new(stats.lr_opac))?;
 
+            if let Some(intrinsics) = stats.intrinsics.clone() {
+                // Log the average over the cameras, which is just the one camera when shared.
+                let intrinsics = intrinsics
+                    .mean_dim(0)
+                    .into_data_async()
+                    .await
+                    .to_vec::<f32>()
+                    .unwrap();
+                let names = [
+                    "focal_scale_x",
+                    "focal_scale_y",
+                    "center_offset_u",
+                    "center_offset_v",
+                ];
+                for (name, value) in names.iter().zip(intrinsics) {
+                    rec.log(
+                        format!("intrinsics/{name}"),
+                        &rerun::Scalar::new(value as f64),
+                    )?;
+                }
+            }
+
             let [batch_size, img_h, img_w, _] = stats.pred_images.dims();
             let pred_rgb =
                 stats
@@ -295,17 +317,22 @@ impl VisualizeTools {
                 &rerun::Scalar::new(ssim.into_scalar_async().await.elem::<f64>()),
             )?;
 
-            // Not sure what's best here, atm let's just log the first batch render only.
-            // Maybe could do an average instead?
-            let main_aux = &stats.auxes[0];
+            // Log the average per view. A batched render has one aux counting all its views,
+            // otherwise there's an aux per view, so sum them all up.
+            let mut num_intersections = 0;
+            let mut num_visible = 0;
+            for aux in &stats.auxes {
+                num_intersections += aux.read_num_intersections().await;
+                num_visible += aux.read_num_visible().await;
+            }
 
             rec.log(
                 "splats/num_intersects",
-                &rerun::Scalar::new(main_aux.read_num_intersections().await as f64),
+                &rerun::Scalar::new(num_intersections as f64 / batch_size as f64),
             )?;
             rec.log(
                 "splats/splats_visible",
-                &rerun::Scalar::new(main_aux.read_num_visible().await as f64),
+                &rerun::Scalar::new(num_visible as f64 / batch_size as f64),
             )?;
 
             if let Some(refine) = stats.refine {
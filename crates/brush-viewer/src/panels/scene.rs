@@ -1,9 +1,13 @@
 use async_std::task;
-use brush_dataset::splat_export;
+use brush_dataset::camera_export;
+use brush_dataset::splat_export::{self, ExportOptions};
+use brush_render::bounding_box::{ClipVolume, OrientedBox};
+use brush_render::camera::{Camera, CameraModel, DEFAULT_FAR, DEFAULT_NEAR};
 use egui::epaint::mutex::RwLock as EguiRwLock;
-use std::sync::Arc;
+use std::{future::Future, sync::Arc};
 
 use brush_render::gaussian_splats::Splats;
+use brush_render::RenderAux;
 use eframe::egui_wgpu::Renderer;
 use egui::{Color32, Rect};
 use glam::Vec2;
@@ -13,11 +17,63 @@ use wgpu::CommandEncoderDescriptor;
 
 use crate::{
     burn_texture::BurnTexture,
+    render_mode::{render_view, RenderMode},
     train_loop::TrainMessage,
     viewer::{ViewerContext, ViewerMessage},
     ViewerPanel,
 };
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Ply,
+    Safetensors,
+    PointCloud,
+    Ellipsoids,
+    Panorama,
+    ColmapCameras,
+    ColmapImages,
+    TransformsJson,
+}
+
+impl ExportFormat {
+    const ALL: [Self; 8] = [
+        Self::Ply,
+        Self::Safetensors,
+        Self::PointCloud,
+        Self::Ellipsoids,
+        Self::Panorama,
+        Self::ColmapCameras,
+        Self::ColmapImages,
+        Self::TransformsJson,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Ply => "Splat ply",
+            Self::Safetensors => "Safetensors",
+            Self::PointCloud => "Colored point cloud ply",
+            Self::Ellipsoids => "Ellipsoid mesh ply",
+            Self::Panorama => "360° panorama png (from current view)",
+            Self::ColmapCameras => "COLMAP cameras.txt (training views)",
+            Self::ColmapImages => "COLMAP images.txt (training views)",
+            Self::TransformsJson => "transforms.json (training views)",
+        }
+    }
+
+    fn file_name(&self) -> &'static str {
+        match self {
+            Self::Ply => "export.ply",
+            Self::Safetensors => "export.safetensors",
+            Self::PointCloud => "export_points.ply",
+            Self::Ellipsoids => "export_mesh.ply",
+            Self::Panorama => "panorama.png",
+            Self::ColmapCameras => "cameras.txt",
+            Self::ColmapImages => "images.txt",
+            Self::TransformsJson => "transforms.json",
+        }
+    }
+}
+
 pub(crate) struct ScenePanel {
     pub(crate) backbuffer: BurnTexture,
     pub(crate) last_draw: Option<Instant>,
@@ -27,9 +83,20 @@ pub(crate) struct ScenePanel {
     is_training: bool,
     live_update: bool,
     paused: bool,
+    orthographic: bool,
+    render_mode: RenderMode,
+    resort: bool,
+    near: f32,
+    far: f32,
+    clip_box: Option<OrientedBox>,
+    last_aux: Option<RenderAux>,
 
     dirty: bool,
 
+    export_options: ExportOptions,
+    export_format: ExportFormat,
+    panorama_width: u32,
+
     queue: Arc<wgpu::Queue>,
     device: Arc<wgpu::Device>,
     renderer: Arc<EguiRwLock<Renderer>>,
@@ -47,9 +114,19 @@ impl ScenePanel {
             last_message: None,
             live_update: true,
             paused: false,
+            orthographic: false,
+            render_mode: RenderMode::Rgb,
+            resort: false,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            clip_box: None,
+            last_aux: None,
             dirty: false,
             is_loading: false,
             is_training: false,
+            export_options: ExportOptions::default(),
+            export_format: ExportFormat::Ply,
+            panorama_width: 4096,
             queue,
             device,
             renderer,
@@ -63,6 +140,16 @@ impl ScenePanel {
         splats: &Splats<brush_render::PrimaryBackend>,
         background: glam::Vec3,
     ) {
+        context.camera.model = if self.orthographic {
+            CameraModel::Orthographic {
+                extent: ortho_extent(context),
+            }
+        } else {
+            CameraModel::Pinhole
+        };
+        context.camera.near = self.near;
+        context.camera.far = self.far;
+
         let mut size = ui.available_size();
         let focal = context.camera.focal(glam::uvec2(1, 1));
         let aspect_ratio = focal.y / focal.x;
@@ -76,9 +163,27 @@ impl ScenePanel {
 
         let (rect, response) = ui.allocate_exact_size(
             egui::Vec2::new(size.x as f32, size.y as f32),
-            egui::Sense::drag(),
+            egui::Sense::click_and_drag(),
         );
 
+        // Log the splats under the cursor when clicking, to help track down floaters.
+        if let (Some(aux), Some(pos)) = (self.last_aux.clone(), response.interact_pointer_pos()) {
+            if response.clicked() {
+                let pos = (pos - rect.min) * size.x as f32 / rect.width();
+                let pixel = glam::uvec2(pos.x as u32, pos.y as u32);
+                task::spawn_local(async move {
+                    for splat in aux.pick_pixel(pixel).await {
+                        log::info!(
+                            "Splat {} at {pixel}: alpha {:.3}, weight {:.3}",
+                            splat.global_gid,
+                            splat.alpha,
+                            splat.weight
+                        );
+                    }
+                });
+            }
+        }
+
         let mouse_delta = glam::vec2(response.drag_delta().x, response.drag_delta().y);
 
         let (pan, rotate) = if response.dragged_by(egui::PointerButton::Primary) {
@@ -111,7 +216,17 @@ impl ScenePanel {
         // If this viewport is re-rendering.
         if ui.ctx().has_requested_repaint() && self.dirty {
             let _span = trace_span!("Render splats").entered();
-            let (img, _) = splats.render(&context.camera, size, background, true);
+            let clip = self.clip_box.clone().map(ClipVolume::Box);
+            let (img, aux) = render_view(
+                self.render_mode,
+                splats,
+                &context.camera,
+                size,
+                background,
+                clip.as_ref(),
+                self.resort,
+            );
+            self.last_aux = Some(aux);
 
             let mut encoder = self
                 .device
@@ -137,7 +252,224 @@ impl ScenePanel {
                 );
             });
         }
+
+        if let Some(clip_box) = self.clip_box.as_mut() {
+            if clip_box_gizmo(ui, rect, size, &context.camera, clip_box) {
+                self.dirty = true;
+            }
+        }
+    }
+}
+
+fn export_file(
+    file_name: &'static str,
+    data: impl Future<Output = anyhow::Result<Vec<u8>>> + 'static,
+) {
+    task::spawn_local(async move {
+        let data = match data.await {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Failed to save file: {e}");
+                return;
+            }
+        };
+        // Not sure where/how to show this error if any.
+        if let Err(e) = rrfd::save_file(file_name, data).await {
+            log::error!("Failed to save file: {e}");
+        }
+    });
+}
+
+// Size of the view at the orbit focus, such that switching to orthographic keeps the scale
+// of the scene, and zooming still works.
+fn ortho_extent(context: &ViewerContext) -> glam::Vec2 {
+    let distance = (context.camera.position - context.controls.focus).length();
+    let fov = context.camera.fov;
+    2.0 * distance * glam::vec2((fov.x * 0.5).tan(), (fov.y * 0.5).tan())
+}
+
+// Projects a world space point to a position in the viewport.
+fn world_to_screen(
+    camera: &Camera,
+    img_size: glam::UVec2,
+    rect: Rect,
+    point: glam::Vec3,
+) -> Option<egui::Pos2> {
+    let p_view = camera.world_to_local().transform_point3(point);
+    let focal = camera.focal(img_size);
+    let pixel = match camera.model {
+        CameraModel::Orthographic { .. } => p_view.truncate() * focal,
+        _ => {
+            if p_view.z <= camera.near {
+                return None;
+            }
+            p_view.truncate() / p_view.z * focal
+        }
+    } + camera.center(img_size);
+    let scale = glam::vec2(rect.width(), rect.height()) / img_size.as_vec2();
+    Some(rect.min + egui::vec2(pixel.x * scale.x, pixel.y * scale.y))
+}
+
+// Draws the clip box over the viewport, with a handle on each face to drag it.
+// Returns whether the box was changed.
+fn clip_box_gizmo(
+    ui: &egui::Ui,
+    rect: Rect,
+    img_size: glam::UVec2,
+    camera: &Camera,
+    clip_box: &mut OrientedBox,
+) -> bool {
+    let painter = ui.painter_at(rect);
+    let stroke = egui::Stroke::new(1.5, Color32::from_rgb(255, 200, 0));
+    let axes = glam::Mat3::from_quat(clip_box.rotation);
+
+    let corner = |i: usize| {
+        let signs = glam::vec3(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            if i & 4 == 0 { -1.0 } else { 1.0 },
+        );
+        clip_box.center + axes * (signs * clip_box.extent)
+    };
+
+    for i in 0..8 {
+        for bit in [1, 2, 4] {
+            // Draw each edge once, from the corner with the lower index.
+            if i & bit != 0 {
+                continue;
+            }
+            let start = world_to_screen(camera, img_size, rect, corner(i));
+            let end = world_to_screen(camera, img_size, rect, corner(i | bit));
+            if let (Some(start), Some(end)) = (start, end) {
+                painter.line_segment([start, end], stroke);
+            }
+        }
+    }
+
+    let mut changed = false;
+
+    for axis_id in 0..3 {
+        for sign in [-1.0, 1.0] {
+            let axis = axes.col(axis_id) * sign;
+            let face = clip_box.center + axis * clip_box.extent[axis_id];
+
+            let (Some(handle), Some(handle_end)) = (
+                world_to_screen(camera, img_size, rect, face),
+                world_to_screen(camera, img_size, rect, face + axis),
+            ) else {
+                continue;
+            };
+
+            let id = ui.id().with(("clip_box_handle", axis_id, sign as i32));
+            let response = ui.interact(
+                Rect::from_center_size(handle, egui::vec2(14.0, 14.0)),
+                id,
+                egui::Sense::drag(),
+            );
+
+            let color = if response.hovered() || response.dragged() {
+                Color32::WHITE
+            } else {
+                stroke.color
+            };
+            painter.circle_filled(handle, 5.0, color);
+
+            if response.dragged() {
+                // Move the face along its axis by the drag distance along the projected axis.
+                let screen_axis = handle_end - handle;
+                let len_sq = screen_axis.length_sq();
+                if len_sq > 1e-3 {
+                    let delta = response.drag_delta().dot(screen_axis) / len_sq;
+                    let delta = delta.max(-2.0 * clip_box.extent[axis_id]);
+                    clip_box.extent[axis_id] += delta / 2.0;
+                    clip_box.center += axis * delta / 2.0;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    changed
+}
+
+fn drag_vec3(ui: &mut egui::Ui, label: &str, value: &mut glam::Vec3, speed: f32) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let value: &mut [f32; 3] = value.as_mut();
+        let mut changed = false;
+        for (axis, v) in ["x: ", "y: ", "z: "].into_iter().zip(value) {
+            changed |= ui
+                .add(egui::DragValue::new(v).speed(speed).prefix(axis))
+                .changed();
+        }
+        changed
+    })
+    .inner
+}
+
+fn export_format_ui(ui: &mut egui::Ui, format: &mut ExportFormat, panorama_width: &mut u32) {
+    ui.label("Format");
+    for f in ExportFormat::ALL {
+        ui.radio_value(format, f, f.label());
+    }
+
+    if *format == ExportFormat::Panorama {
+        ui.add(
+            egui::Slider::new(panorama_width, 512..=16384)
+                .logarithmic(true)
+                .text("Panorama width"),
+        );
+    }
+}
+
+fn export_options_ui(ui: &mut egui::Ui, options: &mut ExportOptions, context: &ViewerContext) {
+    let mut limit_sh = options.sh_degree.is_some();
+    ui.checkbox(&mut limit_sh, "Limit SH degree");
+    if limit_sh {
+        let mut degree = options.sh_degree.unwrap_or(3);
+        ui.add(egui::Slider::new(&mut degree, 0..=3).text("SH degree"));
+        options.sh_degree = Some(degree);
+    } else {
+        options.sh_degree = None;
+    }
+
+    ui.add(egui::Slider::new(&mut options.min_opacity, 0.0..=1.0).text("Min opacity"));
+    ui.checkbox(&mut options.bake_color, "Bake view independent color");
+
+    let mut crop = options.crop.is_some();
+    ui.checkbox(&mut crop, "Crop to box");
+
+    if !crop {
+        options.crop = None;
+        return;
+    }
+
+    let crop = options.crop.get_or_insert_with(|| {
+        if context.dataset.train.views.is_empty() {
+            OrientedBox::new(glam::Vec3::ZERO, glam::Vec3::ONE, glam::Quat::IDENTITY)
+        } else {
+            context.dataset.train.bounds(0.0, 0.0).into()
+        }
+    });
+
+    oriented_box_ui(ui, crop);
+}
+
+// Edits the center, extent and rotation of a box. Returns whether the box was changed.
+fn oriented_box_ui(ui: &mut egui::Ui, bounds: &mut OrientedBox) -> bool {
+    let mut changed = false;
+    changed |= drag_vec3(ui, "Center", &mut bounds.center, 0.01);
+    changed |= drag_vec3(ui, "Extent", &mut bounds.extent, 0.01);
+    bounds.extent = bounds.extent.max(glam::Vec3::ZERO);
+
+    let (y, x, z) = bounds.rotation.to_euler(glam::EulerRot::YXZ);
+    let mut angles = glam::vec3(x, y, z) * 180.0 / std::f32::consts::PI;
+    if drag_vec3(ui, "Rotation", &mut angles, 0.5) {
+        let angles = angles * std::f32::consts::PI / 180.0;
+        bounds.rotation = glam::Quat::from_euler(glam::EulerRot::YXZ, angles.y, angles.x, angles.z);
+        changed = true;
     }
+    changed
 }
 
 impl ViewerPanel for ScenePanel {
@@ -182,7 +514,7 @@ impl ViewerPanel for ScenePanel {
             ui.add_space(5.0);
             ui.label(
                 r#"
-Load a pretrained .ply file to view it
+Load a pretrained .ply or .safetensors file to view it, or pick multiple to merge them
 
 Or load a dataset to train on. These are zip files with:
     - a transform_train.json and images, like the synthetic NeRF dataset format.
@@ -244,23 +576,158 @@ runs consider using the native app."#,
                             ui.add_space(15.0);
 
                             if ui.button("↑ Export").clicked() {
-                                let splats = splats.clone();
-                                task::spawn_local(async move {
-                                    let data = splat_export::splat_to_ply(*splats).await;
-                                    let data = match data {
-                                        Ok(data) => data,
-                                        Err(e) => {
-                                            log::error!("Failed to save file: {e}");
-                                            return;
+                                let splats = *splats.clone();
+                                let options = self.export_options.clone();
+                                let format = self.export_format;
+                                let camera = context.camera.clone();
+                                let background = context.dataset.train.background;
+                                let train_views = context.dataset.train.views.clone();
+                                let panorama_size =
+                                    glam::uvec2(self.panorama_width, self.panorama_width / 2);
+
+                                export_file(format.file_name(), async move {
+                                    match format {
+                                        ExportFormat::Ply => {
+                                            splat_export::splat_to_ply(splats, &options).await
+                                        }
+                                        ExportFormat::Safetensors => {
+                                            splat_export::apply_export_options(splats, &options)
+                                                .await?
+                                                .to_safetensors()
+                                                .await
+                                        }
+                                        ExportFormat::PointCloud => {
+                                            splat_export::splat_to_point_cloud_ply(splats, &options)
+                                                .await
+                                        }
+                                        ExportFormat::Ellipsoids => {
+                                            splat_export::splat_to_ellipsoid_ply(
+                                                splats, &options, 2.0,
+                                            )
+                                            .await
+                                        }
+                                        ExportFormat::Panorama => {
+                                            splat_export::splat_to_panorama_png(
+                                                splats,
+                                                &options,
+                                                camera.position,
+                                                camera.rotation,
+                                                panorama_size,
+                                                background,
+                                            )
+                                            .await
+                                        }
+                                        ExportFormat::ColmapCameras => {
+                                            camera_export::views_to_colmap_cameras(&train_views)
+                                                .map(String::into_bytes)
+                                        }
+                                        ExportFormat::ColmapImages => {
+                                            camera_export::views_to_colmap_images(&train_views)
+                                                .map(String::into_bytes)
+                                        }
+                                        ExportFormat::TransformsJson => {
+                                            camera_export::views_to_transforms_json(&train_views)
+                                                .map(String::into_bytes)
                                         }
-                                    };
-                                    // Not sure where/how to show this error if any.
-                                    if let Err(e) = rrfd::save_file("export.ply", data).await {
-                                        log::error!("Failed to save file: {e}");
                                     }
                                 });
                             }
+
+                            ui.menu_button("⚙ Export settings", |ui| {
+                                export_format_ui(
+                                    ui,
+                                    &mut self.export_format,
+                                    &mut self.panorama_width,
+                                );
+                                ui.separator();
+                                export_options_ui(ui, &mut self.export_options, context);
+                            });
                         }
+
+                        ui.add_space(15.0);
+
+                        if ui
+                            .selectable_label(self.orthographic, "⬚ Orthographic")
+                            .clicked()
+                        {
+                            self.orthographic = !self.orthographic;
+                            self.dirty = true;
+                        }
+
+                        egui::ComboBox::from_id_salt("render_mode")
+                            .selected_text(self.render_mode.label())
+                            .show_ui(ui, |ui| {
+                                for mode in RenderMode::ALL {
+                                    if ui
+                                        .selectable_value(&mut self.render_mode, mode, mode.label())
+                                        .changed()
+                                    {
+                                        self.dirty = true;
+                                    }
+                                }
+                            });
+
+                        if ui
+                            .selectable_label(self.resort, "⇅ Sort per pixel")
+                            .on_hover_text("Sort splats per pixel to reduce popping, at some cost")
+                            .clicked()
+                        {
+                            self.resort = !self.resort;
+                            self.dirty = true;
+                        }
+
+                        ui.menu_button("✂ Clipping", |ui| {
+                            let mut clip = self.clip_box.is_some();
+                            if ui.checkbox(&mut clip, "Clip to box").changed() {
+                                self.clip_box = clip.then(|| {
+                                    if context.dataset.train.views.is_empty() {
+                                        OrientedBox::new(
+                                            glam::Vec3::ZERO,
+                                            glam::Vec3::ONE,
+                                            glam::Quat::IDENTITY,
+                                        )
+                                    } else {
+                                        context.dataset.train.bounds(0.0, 0.0).into()
+                                    }
+                                });
+                                self.dirty = true;
+                            }
+                            if let Some(clip_box) = self.clip_box.as_mut() {
+                                if oriented_box_ui(ui, clip_box) {
+                                    self.dirty = true;
+                                }
+                            }
+                            ui.separator();
+
+                            ui.horizontal(|ui| {
+                                ui.label("Near");
+                                let speed = (self.near * 0.01).max(1e-4);
+                                if ui
+                                    .add(
+                                        egui::DragValue::new(&mut self.near)
+                                            .speed(speed)
+                                            .range(0.001..=self.far),
+                                    )
+                                    .changed()
+                                {
+                                    self.dirty = true;
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Far");
+                                let speed = (self.far * 0.01).max(1e-4);
+                                if ui
+                                    .add(
+                                        egui::DragValue::new(&mut self.far)
+                                            .speed(speed)
+                                            .range(self.near..=DEFAULT_FAR),
+                                    )
+                                    .changed()
+                                {
+                                    self.dirty = true;
+                                }
+                            });
+                        });
                     });
                 }
                 _ => {}
@@ -0,0 +1,190 @@
+use brush_kernel::bitcast_tensor;
+use brush_render::{
+    bounding_box::ClipVolume, camera::Camera, gaussian_splats::Splats, Backend, PrimaryBackend,
+    RenderAux, RenderOptions,
+};
+use burn::tensor::{Int, Tensor, TensorPrimitive};
+
+type B = PrimaryBackend;
+
+/// What to show in the scene view. Besides the regular render, these can show where the
+/// rasterizer spends its time, to find performance hotspots and dense floaters.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RenderMode {
+    Rgb,
+    Depth,
+    Alpha,
+    TilesHit,
+    SplatsPerPixel,
+}
+
+impl RenderMode {
+    pub(crate) const ALL: [Self; 5] = [
+        Self::Rgb,
+        Self::Depth,
+        Self::Alpha,
+        Self::TilesHit,
+        Self::SplatsPerPixel,
+    ];
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            Self::Rgb => "RGB",
+            Self::Depth => "Depth",
+            Self::Alpha => "Alpha",
+            Self::TilesHit => "Splats per tile",
+            Self::SplatsPerPixel => "Splats per pixel",
+        }
+    }
+}
+
+/// Render the splats as a packed RGBA buffer, ready to display. With `resort` set, the splats
+/// are sorted per pixel to avoid popping. The depth view blends the splat depths in the global
+/// order, so it ignores `resort`.
+///
+/// The heatmaps are log scaled and normalized to the maximum in the current view.
+pub(crate) fn render_view(
+    mode: RenderMode,
+    splats: &Splats<B>,
+    camera: &Camera,
+    img_size: glam::UVec2,
+    background: glam::Vec3,
+    clip_volume: Option<&ClipVolume>,
+    resort: bool,
+) -> (Tensor<B, 3>, RenderAux) {
+    let resort = resort && mode != RenderMode::Depth;
+    let render = |render_u32_buffer| {
+        let options = RenderOptions {
+            clip_volume,
+            render_u32_buffer,
+            resort,
+            ..Default::default()
+        };
+        splats.render_with(camera, img_size, background, options)
+    };
+
+    if mode == RenderMode::Rgb {
+        return render(true);
+    }
+
+    // The float render also records the final splat of each pixel.
+    let (img, aux) = render(false);
+    let [h, w, _] = img.dims();
+    let alpha = img.slice([0..h, 0..w, 3..4]).squeeze::<2>(2);
+
+    let colors = match mode {
+        RenderMode::Rgb => unreachable!("RGB is rendered directly"),
+        RenderMode::Depth => {
+            let depth = normalized_depth(splats, camera, &aux, img_size, alpha.clone());
+            turbo(depth) * alpha.unsqueeze_dim::<3>(2)
+        }
+        RenderMode::Alpha => alpha.unsqueeze_dim::<3>(2).repeat_dim(2, 3),
+        RenderMode::TilesHit => turbo(log_normalized(aux.read_tile_depth_per_pixel())),
+        RenderMode::SplatsPerPixel => {
+            let count = aux
+                .read_splats_per_pixel()
+                .mask_fill(alpha.equal_elem(0.0), 0);
+            turbo(log_normalized(count))
+        }
+    };
+
+    (pack_rgba(colors), aux)
+}
+
+// Depth of the splats blended per pixel, scaled so the nearest covered pixel is one and the
+// furthest zero.
+fn normalized_depth(
+    splats: &Splats<B>,
+    camera: &Camera,
+    aux: &RenderAux,
+    img_size: glam::UVec2,
+    alpha: Tensor<B, 2>,
+) -> Tensor<B, 2> {
+    let device = splats.means.device();
+    let row = camera.world_to_local().row(2);
+    let to_depth = Tensor::<B, 1>::from_floats([row.x, row.y, row.z], &device).reshape([3, 1]);
+    let splat_depth = splats.means.val().matmul(to_depth) + row.w;
+
+    let [h, w] = alpha.dims();
+    // Depth is always rendered from a single camera without resorting, see render_view.
+    let depth = B::render_features(aux, img_size, splat_depth)
+        .expect("Depth renders don't resort")
+        .reshape([h, w])
+        / alpha.clone().clamp_min(1e-6);
+
+    // Only normalize over pixels that are mostly covered, the depth of the rest is noisy.
+    let uncovered = alpha.lower_elem(0.5);
+    let near = depth
+        .clone()
+        .mask_fill(uncovered.clone(), f32::MAX)
+        .min()
+        .unsqueeze();
+    let far = depth
+        .clone()
+        .mask_fill(uncovered, f32::MIN)
+        .max()
+        .unsqueeze();
+    let range = (far.clone() - near).clamp_min(1e-6);
+    ((far - depth) / range).clamp(0.0, 1.0)
+}
+
+fn log_normalized(counts: Tensor<B, 2, Int>) -> Tensor<B, 2> {
+    let counts = counts.float().log1p();
+    let max = counts.clone().max().clamp_min(1e-6).unsqueeze();
+    counts / max
+}
+
+// Polynomial approximation of the Turbo colormap, see
+// https://research.google/blog/turbo-an-improved-rainbow-colormap-for-visualization/
+fn turbo(t: Tensor<B, 2>) -> Tensor<B, 3> {
+    const COEFFS: [[f32; 6]; 3] = [
+        [
+            0.135_721_38,
+            4.615_392_6,
+            -42.660_324,
+            132.131_08,
+            -152.942_4,
+            59.286_38,
+        ],
+        [
+            0.091_402_61,
+            2.194_188_4,
+            4.842_966_6,
+            -14.185_033,
+            4.277_298_7,
+            2.829_566,
+        ],
+        [
+            0.106_673_3,
+            12.641_946,
+            -60.582_05,
+            110.362_77,
+            -89.903_11,
+            27.348_25,
+        ],
+    ];
+
+    let t = t.clamp(0.0, 1.0);
+    let channels = COEFFS
+        .iter()
+        .map(|coeffs| {
+            coeffs
+                .iter()
+                .rev()
+                .fold(t.zeros_like(), |acc, &c| acc * t.clone() + c)
+        })
+        .collect();
+    Tensor::stack(channels, 2)
+}
+
+// Pack [H, W, 3] colors into opaque RGBA bytes, like the u32 buffer of the rasterizer.
+fn pack_rgba(colors: Tensor<B, 3>) -> Tensor<B, 3> {
+    let device = colors.device();
+    let bytes = (colors.clamp(0.0, 1.0) * 255.0 + 0.5).int();
+    let shifts = Tensor::<B, 1, Int>::from_ints([1, 1 << 8, 1 << 16], &device).reshape([1, 1, 3]);
+    let alpha = 0xff00_0000u32 as i32;
+    let packed = (bytes * shifts).sum_dim(2) + alpha;
+    Tensor::from_primitive(TensorPrimitive::Float(bitcast_tensor(
+        packed.into_primitive(),
+    )))
+}
@@ -94,7 +94,12 @@ pub(crate) fn train_loop(
         let eval_scene = dataset.eval.clone();
 
         let mut dataloader = SceneLoader::new(&train_scene, batch_size, seed, &device);
-        let mut trainer = SplatTrainer::new(splats.num_splats(), &config, &splats);
+        let mut trainer = SplatTrainer::new(
+            splats.num_splats(),
+            train_scene.views.len(),
+            &config,
+            &splats,
+        )?;
 
         let mut is_paused = false;
 
@@ -152,17 +157,32 @@ pub(crate) fn train_loop(
                         .instrument(trace_span!("Train step"))
                         .await?;
                     splats = new_splats;
+                    trainer.update_filter_3d(&mut splats, &train_scene, stats.refine.is_some());
 
                     // Log out train stats.
                     // HACK: Always emit events that do a refine,
                     // as stats might want to log them.
                     if trainer.iter - last_logged > 5 || stats.refine.is_some() {
+                        // On wasm the intersection buffers are an estimate, warn if they were too small.
+                        if cfg!(target_family = "wasm") {
+                            for aux in &stats.auxes {
+                                if aux.read_intersections_truncated().await {
+                                    log::warn!(
+                                        "Too many intersections to render, some splats are missing. Try a lower resolution."
+                                    );
+                                }
+                            }
+                        }
+
                         emitter
                             .emit(ViewerMessage::Splats {
                                 iter: trainer.iter,
                                 splats: Box::new(splats.valid()),
                             })
                             .await;
+                        if let Some(scene) = trainer.refined_scene(&train_scene).await {
+                            emitter.emit(ViewerMessage::RefinedCameras { scene }).await;
+                        }
                         emitter
                             .emit(ViewerMessage::TrainStep {
                                 stats: Box::new(stats),
@@ -191,6 +211,11 @@ pub(crate) fn train_loop(
             task::yield_now().await;
         }
 
+        // Send out the final refined cameras, as the last log might be a few steps behind.
+        if let Some(scene) = trainer.refined_scene(&train_scene).await {
+            emitter.emit(ViewerMessage::RefinedCameras { scene }).await;
+        }
+
         Ok(())
     })
 }
@@ -1,5 +1,6 @@
 use std::{pin::Pin, sync::Arc};
 
+use anyhow::Context;
 use async_fn_stream::try_fn_stream;
 use async_std::{
     channel::{Receiver, Sender, TrySendError},
@@ -8,8 +9,9 @@ use async_std::{
 };
 use brush_dataset::{self, splat_import, Dataset, LoadDatasetArgs, LoadInitArgs, ZipData};
 use brush_render::camera::Camera;
-use brush_render::gaussian_splats::Splats;
+use brush_render::gaussian_splats::{merge_splats, MergePart, Splats};
 use brush_render::PrimaryBackend;
+use brush_train::scene::Scene;
 use brush_train::train::TrainStepStats;
 use brush_train::{eval::EvalStats, train::TrainConfig};
 use burn::backend::Autodiff;
@@ -51,6 +53,10 @@ pub(crate) enum ViewerMessage {
     Dataset {
         data: Dataset,
     },
+    /// The training views with camera poses refined during training.
+    RefinedCameras {
+        scene: Scene,
+    },
     /// Splat, or dataset and initial splat, are done loading.
     DoneLoading {
         training: bool,
@@ -87,6 +93,26 @@ pub(crate) struct ViewerContext {
     receiver: Option<Receiver<ViewerMessage>>,
 }
 
+/// Loads the final splats from a single splat file, without streaming intermediate results.
+async fn load_splat_file(
+    file: rrfd::PickedFile,
+    device: &WgpuDevice,
+) -> anyhow::Result<Splats<PrimaryBackend>> {
+    if file.file_name.contains(".ply") {
+        let splat_stream = splat_import::load_splat_from_ply(file.data, device.clone());
+        let mut splat_stream = std::pin::pin!(splat_stream);
+        let mut splats = None;
+        while let Some(loaded) = splat_stream.next().await {
+            splats = Some(loaded?);
+        }
+        splats.context("No splats found")
+    } else if file.file_name.contains(".safetensors") {
+        splat_import::load_splat_from_safetensors(&file.data, device)
+    } else {
+        anyhow::bail!("Only .ply and .safetensors files can be loaded together.")
+    }
+}
+
 fn process_loop(
     device: WgpuDevice,
     train_receiver: Receiver<TrainMessage>,
@@ -96,7 +122,29 @@ fn process_loop(
 ) -> Pin<Box<impl Stream<Item = anyhow::Result<ViewerMessage>>>> {
     let stream = try_fn_stream(|emitter| async move {
         let _ = emitter.emit(ViewerMessage::PickFile).await;
-        let picked = rrfd::pick_file().await?;
+        let mut picked = rrfd::pick_files().await?;
+
+        if picked.len() > 1 {
+            let _ = emitter
+                .emit(ViewerMessage::StartLoading { training: false })
+                .await;
+
+            let mut parts = vec![];
+            for file in picked {
+                parts.push(MergePart::new(load_splat_file(file, &device).await?));
+            }
+            let splats = merge_splats(parts).await?;
+
+            emitter
+                .emit(ViewerMessage::Splats {
+                    iter: 0,
+                    splats: Box::new(splats),
+                })
+                .await;
+            return Ok(());
+        }
+
+        let picked = picked.pop().context("No file selected")?;
 
         if picked.file_name.contains(".ply") {
             let _ = emitter
@@ -114,6 +162,18 @@ fn process_loop(
                     })
                     .await;
             }
+        } else if picked.file_name.contains(".safetensors") {
+            let _ = emitter
+                .emit(ViewerMessage::StartLoading { training: false })
+                .await;
+            let splats =
+                splat_import::load_splat_from_safetensors::<PrimaryBackend>(&picked.data, &device)?;
+            emitter
+                .emit(ViewerMessage::Splats {
+                    iter: 0,
+                    splats: Box::new(splats),
+                })
+                .await;
         } else if picked.file_name.contains(".zip") {
             let _ = emitter
                 .emit(ViewerMessage::StartLoading { training: true })
@@ -132,7 +192,7 @@ fn process_loop(
                 emitter.emit(message?).await;
             }
         } else {
-            anyhow::bail!("Only .ply and .zip files are supported.")
+            anyhow::bail!("Only .ply, .safetensors and .zip files are supported.")
         }
 
         Ok(())
@@ -34,6 +34,37 @@ pub async fn pick_file() -> Result<PickedFile> {
     }
 }
 
+/// Pick one or more files and return the names & bytes of the files.
+///
+/// Nb: On Android this only picks a single file.
+pub async fn pick_files() -> Result<Vec<PickedFile>> {
+    #[cfg(not(target_os = "android"))]
+    {
+        async move {
+            use anyhow::Context;
+            let files = rfd::AsyncFileDialog::new()
+                .pick_files()
+                .await
+                .context("No file selected")?;
+
+            let mut picked = Vec::with_capacity(files.len());
+            for file in files {
+                picked.push(PickedFile {
+                    data: file.read().await,
+                    file_name: file.file_name(),
+                });
+            }
+            Ok(picked)
+        }
+        .await
+    }
+
+    #[cfg(target_os = "android")]
+    {
+        Ok(vec![android::pick_file().await?])
+    }
+}
+
 /// Saves data to a file and returns the filename the data was saved too.
 ///
 /// Nb: Does not work on Android currently.